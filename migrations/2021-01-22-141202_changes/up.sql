@@ -0,0 +1,12 @@
+-- Transfer times between specific trains or stations, a service of 0 means any train
+CREATE TABLE changes (
+    from_station VARCHAR(7) NOT NULL,
+    from_service INT UNSIGNED NOT NULL,
+    to_station VARCHAR(7) NOT NULL,
+    to_service INT UNSIGNED NOT NULL,
+    duration TINYINT UNSIGNED NOT NULL,
+
+    PRIMARY KEY (from_station, from_service, to_station, to_service),
+    FOREIGN KEY (from_station) REFERENCES stations(code),
+    FOREIGN KEY (to_station) REFERENCES stations(code)
+);
\ No newline at end of file
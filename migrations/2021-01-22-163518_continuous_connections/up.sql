@@ -0,0 +1,11 @@
+-- Connections between stations which are available at any time, like walking or taking the metro
+CREATE TABLE continuous_connections (
+    from_station VARCHAR(7) NOT NULL,
+    to_station VARCHAR(7) NOT NULL,
+    duration TINYINT UNSIGNED NOT NULL,
+    mode VARCHAR(5) NOT NULL,
+
+    PRIMARY KEY (from_station, to_station, mode),
+    FOREIGN KEY (from_station) REFERENCES stations(code),
+    FOREIGN KEY (to_station) REFERENCES stations(code)
+);
\ No newline at end of file
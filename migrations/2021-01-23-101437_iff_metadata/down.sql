@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE services
+DROP COLUMN company_id;
+
+DROP TABLE IF EXISTS companies;
+DROP TABLE IF EXISTS countries;
\ No newline at end of file
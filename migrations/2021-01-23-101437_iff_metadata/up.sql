@@ -0,0 +1,17 @@
+-- Companies
+CREATE TABLE companies (
+    id INT UNSIGNED PRIMARY KEY NOT NULL,
+    code VARCHAR(10) NOT NULL,
+    name VARCHAR(30) NOT NULL,
+    time_turn SMALLINT UNSIGNED NOT NULL
+);
+
+-- Countries
+CREATE TABLE countries (
+    code VARCHAR(4) PRIMARY KEY NOT NULL,
+    inland BOOL NOT NULL,
+    name VARCHAR(30) NOT NULL
+);
+
+ALTER TABLE services
+ADD company_id INT UNSIGNED NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Difference in hours between the stations in a timezone and the times in the timetable
+CREATE TABLE timezones (
+    id INT UNSIGNED NOT NULL,
+    difference TINYINT NOT NULL,
+    from_date DATE NOT NULL,
+    to_date DATE NOT NULL,
+
+    PRIMARY KEY (id, from_date)
+);
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE services
+DROP COLUMN checksum;
+
+DROP TABLE IF EXISTS iff_versions;
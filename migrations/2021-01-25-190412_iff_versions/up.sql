@@ -0,0 +1,15 @@
+-- Versions of the imported IFF exports, the last imported one is currently in the database
+CREATE TABLE iff_versions (
+    company INT UNSIGNED NOT NULL,
+    version INT UNSIGNED NOT NULL,
+    valid_from DATE NOT NULL,
+    valid_till DATE NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    imported_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    PRIMARY KEY (company, version)
+);
+
+-- Checksum of the contents of a service, to find the services that changed between versions
+ALTER TABLE services
+ADD checksum BIGINT UNSIGNED NOT NULL DEFAULT 0;
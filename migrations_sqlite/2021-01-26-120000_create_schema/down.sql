@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS dvs_messages;
+DROP TABLE IF EXISTS iff_versions;
+DROP TABLE IF EXISTS continuous_connections;
+DROP TABLE IF EXISTS changes;
+DROP TABLE IF EXISTS service_identifier;
+DROP TABLE IF EXISTS service_attributes;
+DROP TABLE IF EXISTS service_trns_modes;
+DROP TABLE IF EXISTS service_stops;
+DROP TABLE IF EXISTS services;
+DROP TABLE IF EXISTS validities;
+DROP TABLE IF EXISTS timezones;
+DROP TABLE IF EXISTS countries;
+DROP TABLE IF EXISTS companies;
+DROP TABLE IF EXISTS attributes;
+DROP TABLE IF EXISTS trns_modes;
+DROP TABLE IF EXISTS stations;
@@ -0,0 +1,151 @@
+-- The schema created by the MySQL migrations. SQLite has no enums, so the stop type is checked instead, and the foreign key
+-- from services to validities is left out as it doesn't refer to a unique column.
+
+-- Stations
+CREATE TABLE stations (
+    code VARCHAR(7) PRIMARY KEY NOT NULL,
+    name VARCHAR(30) NOT NULL,
+    country VARCHAR(4) NOT NULL,
+    timezone SMALLINT NOT NULL,
+    interchange_duration SMALLINT NOT NULL,
+    is_interchange_station BOOL NOT NULL,
+    lat INTEGER NOT NULL,
+    lng INTEGER NOT NULL
+);
+
+-- trns modes
+CREATE TABLE trns_modes (
+    id VARCHAR(5) PRIMARY KEY NOT NULL,
+    description VARCHAR(30)
+);
+
+-- attributes
+CREATE TABLE attributes (
+    id VARCHAR(5) PRIMARY KEY NOT NULL,
+    description VARCHAR(30)
+);
+
+-- Companies
+CREATE TABLE companies (
+    id BIGINT PRIMARY KEY NOT NULL,
+    code VARCHAR(10) NOT NULL,
+    name VARCHAR(30) NOT NULL,
+    time_turn INTEGER NOT NULL
+);
+
+-- Countries
+CREATE TABLE countries (
+    code VARCHAR(4) PRIMARY KEY NOT NULL,
+    inland BOOL NOT NULL,
+    name VARCHAR(30) NOT NULL
+);
+
+-- Difference in hours between the stations in a timezone and the times in the timetable
+CREATE TABLE timezones (
+    id BIGINT NOT NULL,
+    difference SMALLINT NOT NULL,
+    from_date DATE NOT NULL,
+    to_date DATE NOT NULL,
+
+    PRIMARY KEY (id, from_date)
+);
+
+-- Validity
+CREATE TABLE validities (
+    id BIGINT NOT NULL,
+    date DATE NOT NULL,
+    PRIMARY KEY (id, date)
+);
+
+-- Services, the checksum is used to find the services that changed between versions
+CREATE TABLE services (
+    id BIGINT PRIMARY KEY NOT NULL,
+    validity_id BIGINT NOT NULL,
+    company_id BIGINT NOT NULL DEFAULT 0,
+    checksum BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE service_stops (
+    service_id BIGINT NOT NULL,
+    ordering BIGINT NOT NULL,
+    type VARCHAR(20) NOT NULL CHECK (type IN ('departure', 'short_intermediate', 'intermediate', 'pass', 'arrival')),
+    station_code VARCHAR(7) NOT NULL,
+    arr_time INTEGER,
+    dep_time INTEGER,
+    platform VARCHAR(3),
+
+    PRIMARY KEY (service_id, ordering),
+    FOREIGN KEY (service_id) REFERENCES services(id),
+    FOREIGN KEY (station_code) REFERENCES stations(code)
+);
+
+CREATE TABLE service_trns_modes (
+    service_id BIGINT NOT NULL,
+    trns_mode_id VARCHAR(5) NOT NULL,
+
+    PRIMARY KEY (service_id, trns_mode_id),
+    FOREIGN KEY (service_id) REFERENCES services(id),
+    FOREIGN KEY (trns_mode_id) REFERENCES trns_modes(id)
+);
+
+CREATE TABLE service_attributes (
+    service_id BIGINT NOT NULL,
+    attribute_id VARCHAR(5) NOT NULL,
+
+    PRIMARY KEY (service_id, attribute_id),
+    FOREIGN KEY (service_id) REFERENCES services(id),
+    FOREIGN KEY (attribute_id) REFERENCES attributes(id)
+);
+
+CREATE TABLE service_identifier (
+    service_id BIGINT NOT NULL,
+    identifier BIGINT NOT NULL,
+    from_index INTEGER NOT NULL,
+    to_index INTEGER NOT NULL,
+    PRIMARY KEY (service_id, identifier, from_index, to_index)
+);
+
+-- Transfer times between specific trains or stations, a service of 0 means any train
+CREATE TABLE changes (
+    from_station VARCHAR(7) NOT NULL,
+    from_service BIGINT NOT NULL,
+    to_station VARCHAR(7) NOT NULL,
+    to_service BIGINT NOT NULL,
+    duration SMALLINT NOT NULL,
+
+    PRIMARY KEY (from_station, from_service, to_station, to_service),
+    FOREIGN KEY (from_station) REFERENCES stations(code),
+    FOREIGN KEY (to_station) REFERENCES stations(code)
+);
+
+-- Connections between stations which are available at any time, like walking or taking the metro
+CREATE TABLE continuous_connections (
+    from_station VARCHAR(7) NOT NULL,
+    to_station VARCHAR(7) NOT NULL,
+    duration SMALLINT NOT NULL,
+    mode VARCHAR(5) NOT NULL,
+
+    PRIMARY KEY (from_station, to_station, mode),
+    FOREIGN KEY (from_station) REFERENCES stations(code),
+    FOREIGN KEY (to_station) REFERENCES stations(code)
+);
+
+-- Versions of the imported IFF exports, the last imported one is currently in the database
+CREATE TABLE iff_versions (
+    company BIGINT NOT NULL,
+    version BIGINT NOT NULL,
+    valid_from DATE NOT NULL,
+    valid_till DATE NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    imported_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    PRIMARY KEY (company, version)
+);
+
+-- DVS Messages
+CREATE TABLE dvs_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    envelope VARCHAR(255),
+    message TEXT
+);
@@ -25,12 +25,12 @@ macro_rules! alg_test {
                 }
 
                 let timetable = Timetable {
-                    stops: HashMap::new(),
                     trips: vec![Trip {
                         identifier: 0,
                         connections: connections.clone()
                     }],
-                    footpaths
+                    footpaths,
+                    ..Default::default()
                 };
 
                 let alg = $x::new(&timetable);
@@ -47,6 +47,200 @@ macro_rules! alg_test {
                     ]
                 });
             }
+
+            #[test]
+            fn large_stop_ids_test() {
+                use crate::types::{Timetable, Connection, Trip};
+
+                // Stop ids beyond the amount of stations in the Netherlands, as used by feeds of larger areas
+                let connections = vec![
+                    Connection { dep_stop: 150000, arr_stop: 150001, dep_time: 1, arr_time: 4, trip_id: 0 },
+                    Connection { dep_stop: 150001, arr_stop: 150002, dep_time: 5, arr_time: 9, trip_id: 0 },
+                ];
+
+                let timetable = Timetable {
+                    trips: vec![Trip { identifier: 0, connections }],
+                    footpaths: (150000..=150002).map(|i| (i, vec![(i, 0)])).collect(),
+                    ..Default::default()
+                };
+
+                let alg = $x::new(&timetable);
+                assert_eq!(alg.find_earliest_arrival(150000, 150002, 0).unwrap().arrival(), 9);
+            }
+
+            #[test]
+            fn latest_departure_test() {
+                use crate::types::{Timetable, Connection, TripResult, Trip, TripPart};
+                use std::collections::HashMap;
+
+                // Two trips to stop 1 of which the later one still catches the only trip to stop 2
+                let connections = vec![
+                    Connection { dep_stop: 0, arr_stop: 1, dep_time: 1, arr_time: 4, trip_id: 0 },
+                    Connection { dep_stop: 0, arr_stop: 1, dep_time: 10, arr_time: 12, trip_id: 1 },
+                    Connection { dep_stop: 1, arr_stop: 2, dep_time: 20, arr_time: 25, trip_id: 2 },
+                ];
+
+                let mut footpaths = HashMap::new();
+                for i in 0..=2 {
+                    footpaths.insert(i, vec![(i, 0)]);
+                }
+
+                let timetable = Timetable {
+                    trips: connections.iter().map(|c| Trip {
+                        identifier: c.trip_id,
+                        connections: vec![c.clone()]
+                    }).collect(),
+                    footpaths,
+                    ..Default::default()
+                };
+
+                let alg = $x::new(&timetable);
+
+                let result = alg.find_earliest_arrival(0, 2, 0).unwrap();
+                assert_eq!(result.arrival(), 25);
+
+                assert_eq!(timetable.latest_departure(0, 0, &result).unwrap(), TripResult {
+                    parts: vec![
+                        TripPart::Connection(&connections[1], &connections[1]),
+                        TripPart::Footpath(1, 1, 0),
+                        TripPart::Connection(&connections[2], &connections[2])
+                    ]
+                });
+            }
+
+            #[test]
+            fn latest_departure_footpath_test() {
+                use crate::types::{Timetable, Connection, TripResult, Trip, TripPart};
+                use std::collections::HashMap;
+
+                // Both trips reach stop 1 at the same time, from where the destination is reached by walking
+                let connections = vec![
+                    Connection { dep_stop: 0, arr_stop: 1, dep_time: 1, arr_time: 4, trip_id: 0 },
+                    Connection { dep_stop: 0, arr_stop: 1, dep_time: 2, arr_time: 4, trip_id: 1 },
+                ];
+
+                let mut footpaths = HashMap::new();
+                for i in 0..=2 {
+                    footpaths.insert(i, vec![(i, 0)]);
+                }
+                footpaths.get_mut(&1).unwrap().push((2, 3));
+
+                let timetable = Timetable {
+                    trips: connections.iter().map(|c| Trip {
+                        identifier: c.trip_id,
+                        connections: vec![c.clone()]
+                    }).collect(),
+                    footpaths,
+                    ..Default::default()
+                };
+
+                let alg = $x::new(&timetable);
+
+                // Walking to the destination after the last connection, as time dependent results do
+                let mut result = alg.find_earliest_arrival(0, 1, 0).unwrap();
+                result.parts.push(TripPart::Footpath(1, 2, 3));
+
+                assert_eq!(timetable.latest_departure(0, 0, &result).unwrap(), TripResult {
+                    parts: vec![
+                        TripPart::Connection(&connections[1], &connections[1]),
+                        TripPart::Footpath(1, 2, 3)
+                    ]
+                });
+            }
+
+            #[test]
+            fn continuation_test() {
+                use crate::types::{Timetable, Connection, TripResult, Trip, TripPart};
+                use std::collections::HashMap;
+
+                // Trip 0 continues as trip 1 at stop 1 after a dwell shorter than the interchange time, trip 2 leaves later on
+                let connections = vec![
+                    Connection { dep_stop: 0, arr_stop: 1, dep_time: 0, arr_time: 10, trip_id: 0 },
+                    Connection { dep_stop: 1, arr_stop: 2, dep_time: 12, arr_time: 20, trip_id: 1 },
+                    Connection { dep_stop: 1, arr_stop: 2, dep_time: 30, arr_time: 40, trip_id: 2 },
+                ];
+
+                let mut footpaths = HashMap::new();
+                for i in 0..=2 {
+                    footpaths.insert(i, vec![(i, if i == 1 { 5 } else { 0 })]);
+                }
+
+                let mut continuations = HashMap::new();
+                continuations.insert(0, 1);
+
+                let timetable = Timetable {
+                    trips: connections.iter().map(|c| Trip {
+                        identifier: c.trip_id,
+                        connections: vec![c.clone()]
+                    }).collect(),
+                    footpaths,
+                    continuations,
+                    ..Default::default()
+                };
+
+                let alg = $x::new(&timetable);
+
+                // Trip 1 can only be caught by staying seated
+                let result = alg.find_earliest_arrival(0, 2, 0).unwrap();
+                assert_eq!(result.arrival(), 20);
+
+                let seated = TripResult {
+                    parts: vec![
+                        TripPart::Connection(&connections[0], &connections[0]),
+                        TripPart::Footpath(1, 1, 0),
+                        TripPart::Connection(&connections[1], &connections[1])
+                    ]
+                };
+
+                assert_eq!(timetable.latest_departure(0, 0, &result).unwrap(), seated);
+                assert_eq!(timetable.join_continuations(result), TripResult {
+                    parts: vec![
+                        TripPart::Connection(&connections[0], &connections[0]),
+                        TripPart::Connection(&connections[1], &connections[1])
+                    ]
+                });
+            }
+        }
+    }
+}
+
+// Tests for algorithms which support the transfer times between specific trips of `Timetable::transfers`
+#[macro_export]
+macro_rules! transfer_test {
+    ($x:ident) => {
+        #[cfg(test)]
+        mod transfer_tests {
+            use super::*;
+
+            #[test]
+            fn specific_transfer_time() {
+                use crate::types::{Timetable, Connection, Trip};
+
+                let connections = vec![
+                    Connection { dep_stop: 0, arr_stop: 1, dep_time: 0, arr_time: 10, trip_id: 0 },
+                    Connection { dep_stop: 1, arr_stop: 2, dep_time: 12, arr_time: 20, trip_id: 1 },
+                    Connection { dep_stop: 1, arr_stop: 2, dep_time: 15, arr_time: 25, trip_id: 2 },
+                ];
+
+                let mut timetable = Timetable {
+                    trips: connections.iter().map(|c| Trip { identifier: c.trip_id, connections: vec![c.clone()] }).collect(),
+                    footpaths: (0..=2).map(|i| (i, vec![(i, 0)])).collect(),
+                    ..Default::default()
+                };
+
+                assert_eq!($x::new(&timetable).find_earliest_arrival(0, 2, 0).unwrap().arrival(), 20);
+
+                // Transferring from trip 0 to trip 1 takes longer than the footpath of the stop
+                timetable.transfers.insert((1, Some(0), Some(1)), 5);
+                assert_eq!($x::new(&timetable).find_earliest_arrival(0, 2, 0).unwrap().arrival(), 25);
+
+                // While transferring to any trip can take less time than the footpath of the stop
+                timetable.footpaths.insert(1, vec![(1, 4)]);
+                timetable.transfers.insert((1, Some(0), None), 2);
+                assert_eq!($x::new(&timetable).find_earliest_arrival(0, 2, 0).unwrap().arrival(), 25);
+                timetable.transfers.remove(&(1, Some(0), Some(1)));
+                assert_eq!($x::new(&timetable).find_earliest_arrival(0, 2, 0).unwrap().arrival(), 20);
+            }
         }
     }
 }
@@ -1,13 +1,24 @@
 use std::collections::{BTreeSet, HashMap};
 
+use super::csa_vec::{board_seated, can_board, continued_from, trip_index};
 use crate::{benchable::{Benchable, BenchableLive}, types::{Connection, Timetable, TripPart, TripResult, TripUpdate}};
 
-pub const MAX_STATIONS: usize = 100000;
-
 #[derive(Debug)]
 pub struct CSABTree<'a> {
     connections: BTreeSet<&'a Connection>,
-    footpaths: &'a HashMap<usize, Vec<(usize, u32)>>
+    footpaths: &'a HashMap<usize, Vec<(usize, u32)>>,
+    timetable: &'a Timetable,
+    continued_from: HashMap<usize, (usize, &'a Connection)>,
+    trip_index: HashMap<usize, usize>,
+    stop_count: usize
+}
+
+impl<'a> CSABTree<'a> {
+    // Gives a trip added by an update an index
+    fn index_trip(&mut self, trip_id: usize) {
+        let next = self.trip_index.len();
+        self.trip_index.entry(trip_id).or_insert(next);
+    }
 }
 
 // Based on https://github.com/trainline-eu/csa-challenge/blob/master/csa.rs (WTFPL license)
@@ -23,14 +34,18 @@ impl<'a> Benchable<'a> for CSABTree<'a> {
         }
 
         CSABTree {
+            trip_index: trip_index(&timetable.trips),
             connections,
-            footpaths: &timetable.footpaths
+            footpaths: &timetable.footpaths,
+            continued_from: continued_from(timetable),
+            stop_count: timetable.stop_count(),
+            timetable
         }
     }
 
     fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
-        let mut earliest_arrival = vec!(std::u32::MAX; MAX_STATIONS);
-        let mut in_connection = vec!(None; MAX_STATIONS * 10);
+        let mut earliest_arrival = vec!(std::u32::MAX; self.stop_count);
+        let mut in_connection = vec!(None; self.trip_index.len());
         let mut journeys = HashMap::new();
 
         for &(f_stop, dur) in self.footpaths.get(&dep_stop).unwrap() {
@@ -38,25 +53,30 @@ impl<'a> Benchable<'a> for CSABTree<'a> {
         }
 
         for &conn in self.connections.range(Connection {
-            dep_stop: MAX_STATIONS,
-            arr_stop: MAX_STATIONS,
+            dep_stop: 0,
+            arr_stop: 0,
             dep_time,
-            arr_time: dep_time,
+            arr_time: 0,
             trip_id: 0
         }..) {
             if earliest_arrival[arr_stop] <= conn.dep_time {
                 break;
             }
 
-            if in_connection[conn.trip_id].is_some() || earliest_arrival[conn.dep_stop] <= conn.dep_time {
-                if in_connection[conn.trip_id].is_none() {
-                    in_connection[conn.trip_id] = Some(conn);
+            let trip = self.trip_index[&conn.trip_id];
+            if in_connection[trip].is_none() {
+                in_connection[trip] = board_seated(conn, &self.continued_from, |trip_id| in_connection[self.trip_index[&trip_id]]);
+            }
+
+            if in_connection[trip].is_some() || can_board(self.timetable, conn, &earliest_arrival, &journeys) {
+                if in_connection[trip].is_none() {
+                    in_connection[trip] = Some(conn);
                 }
 
                 for &(f_stop, dur) in self.footpaths.get(&conn.arr_stop).unwrap() {
                     if conn.arr_time + dur < earliest_arrival[f_stop] {
                         earliest_arrival[f_stop] = conn.arr_time + dur;
-                        journeys.insert(f_stop, (in_connection[conn.trip_id].unwrap(), conn, (conn.arr_stop, f_stop, dur)));
+                        journeys.insert(f_stop, (in_connection[trip].unwrap(), conn, (conn.arr_stop, f_stop, dur)));
                     }
                 }
             }
@@ -66,7 +86,7 @@ impl<'a> Benchable<'a> for CSABTree<'a> {
         let mut cur = arr_stop;
         while let Some((con1, con2, footpath)) = journeys.get(&cur) {
             journey.push(TripPart::Footpath(footpath.0, footpath.1, footpath.2));
-            journey.push(TripPart::Connection(con1, con2));
+            journey.extend(self.timetable.seated_legs(con1, con2).into_iter().rev());
             cur = con1.dep_stop;
         }
 
@@ -95,11 +115,13 @@ impl<'a> BenchableLive<'a> for CSABTree<'a> {
                 }
             }
             TripUpdate::AddTrip { trip } => {
+                self.index_trip(trip.identifier);
                 for conn in trip.connections.iter() {
                     self.connections.insert(conn);
                 }
             }
             TripUpdate::AddConnection { old_trip: _, new_trip: _, connection } => {
+                self.index_trip(connection.trip_id);
                 self.connections.insert(connection);
             }
             TripUpdate::DeleteConnection { old_trip: _, new_trip: _, connection } => {
@@ -109,4 +131,5 @@ impl<'a> BenchableLive<'a> for CSABTree<'a> {
     }
 }
 
-alg_test!(CSABTree);
\ No newline at end of file
+alg_test!(CSABTree);
+transfer_test!(CSABTree);
\ No newline at end of file
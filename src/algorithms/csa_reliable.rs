@@ -0,0 +1,378 @@
+use std::collections::{HashMap, HashSet};
+
+use super::csa_vec::{board_seated, continued_from};
+use crate::{benchable::Benchable, types::{Connection, Delays, Timetable, TripPart, TripResult, partition_point, trip_identifier}};
+
+/// Minimum success probability of a transfer when no other threshold is given
+pub const DEFAULT_MIN_PROBABILITY: f64 = 0.9;
+
+// Arrival at a stop, a stop can have multiple labels since an earlier arrival is not necessarily a more reliable one
+#[derive(Debug)]
+struct Label<'a> {
+    time: u32,
+
+    // Trip and stop at which we alighted, used for looking up the arrival delay distribution
+    alighted: Option<(usize, usize)>,
+
+    // First and last connection of the leg, the footpath after alighting and the label we boarded from
+    prev: Option<(&'a Connection, &'a Connection, (usize, usize, u32), usize)>
+}
+
+// Departure from a stop in a profile: departure time, success probability, boarding connection, alighting connection
+// and the footpath after alighting (none when alighting at the destination)
+type ProfileEntry<'a> = (u32, f64, &'a Connection, &'a Connection, Option<(usize, usize, u32)>);
+
+#[derive(Debug)]
+pub struct CSAReliable<'a> {
+    connections: Vec<&'a Connection>,
+    footpaths: &'a HashMap<usize, Vec<(usize, u32)>>,
+    timetable: &'a Timetable,
+    continued_from: HashMap<usize, (usize, &'a Connection)>,
+
+    // Stops with transfer times between specific trips
+    transfer_stops: HashSet<usize>,
+    delays: Delays,
+    min_probability: f64
+}
+
+impl<'a> CSAReliable<'a> {
+    pub fn with_delays(timetable: &'a Timetable, delays: Delays, min_probability: f64) -> Self {
+        let mut alg = Self::new(timetable);
+        alg.delays = delays;
+        alg.min_probability = min_probability;
+        alg
+    }
+
+    fn footpaths_from(&self, stop: usize) -> Vec<(usize, u32)> {
+        self.footpaths.get(&stop).cloned().unwrap_or_else(|| vec![(stop, 0)])
+    }
+
+    // Time at which a connection can be boarded from a label, a transfer time given for the trip we alighted from and the
+    // trip of the connection takes precedence over the interchange time of the stop
+    fn boarding_time(&self, label: &Label, conn: &Connection) -> u32 {
+        match label.prev {
+            Some((_, alight, (from, to, _), _)) if from == to && alight.trip_id != conn.trip_id => self.timetable
+                .transfer_time(from, alight.trip_id, conn.trip_id)
+                .map_or(label.time, |time| alight.arr_time + time),
+            _ => label.time
+        }
+    }
+
+    fn transfer_probability(&self, label: &Label, conn: &Connection) -> f64 {
+        let time = self.boarding_time(label, conn);
+        if time > conn.dep_time {
+            return 0.0;
+        }
+
+        match label.alighted.and_then(|(trip, stop)| self.delays.get(&(trip_identifier(trip), stop))) {
+            Some(distribution) => distribution.probability_within(conn.dep_time - time),
+            None => 1.0
+        }
+    }
+
+    fn delay_samples(&self, conn: &Connection) -> &[u32] {
+        self.delays.get(&(trip_identifier(conn.trip_id), conn.arr_stop))
+            .map(|distribution| distribution.samples())
+            .filter(|samples| !samples.is_empty())
+            .unwrap_or(&[0])
+    }
+
+    /// Finds the journey maximising the probability of arriving at `arr_stop` before `deadline`, in the style of stochastic CSA.
+    /// Connections are scanned in reverse while keeping a profile of departures and their success probability per stop,
+    /// after alighting the traveller takes the best departure that is still reachable given the delay of the arriving trip.
+    /// The journey returned is the one taken when every trip runs on time. Transfer times between specific trips are only
+    /// applied to the departures kept in a profile, which are chosen using the interchange time of the stop.
+    pub fn find_most_reliable_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32, deadline: u32) -> Option<(f64, TripResult)> {
+        fn best<'p, 'a>(profile: Option<&'p Vec<ProfileEntry<'a>>>, time: u32) -> Option<&'p ProfileEntry<'a>> {
+            let profile = profile?;
+            match partition_point(profile, |entry| entry.0 >= time) {
+                0 => None,
+                i => Some(&profile[i - 1])
+            }
+        }
+
+        // Entries are added with decreasing departure times, but only if they increase the success probability
+        let mut profiles: HashMap<usize, Vec<ProfileEntry>> = HashMap::new();
+
+        // Success probability when on a trip, together with the connection at which to alight and the footpath taken afterwards
+        let mut trips: HashMap<usize, (f64, &Connection, Option<(usize, usize, u32)>)> = HashMap::new();
+
+        for &conn in self.connections.iter().rev() {
+            if conn.arr_time > deadline {
+                continue;
+            }
+
+            if conn.dep_time < dep_time {
+                break;
+            }
+
+            let delays = self.delay_samples(conn);
+
+            // Staying seated at the end of a trip which continues as another one is as reliable as that trip
+            let mut exit = trips.get(&conn.trip_id)
+                .or_else(|| self.timetable.continuations.get(&conn.trip_id).and_then(|next| trips.get(next)))
+                .cloned();
+
+            if conn.arr_stop == arr_stop {
+                let probability = delays.iter().filter(|&&d| conn.arr_time + d <= deadline).count() as f64 / delays.len() as f64;
+                if exit.map_or(true, |(p, _, _)| probability > p) {
+                    exit = Some((probability, conn, None));
+                }
+            } else {
+                for (f_stop, dur) in self.footpaths_from(conn.arr_stop) {
+                    // Transfer times given for specific trips at the stop require looking at the trip of every departure
+                    let transfers = f_stop == conn.arr_stop && self.transfer_stops.contains(&f_stop);
+                    let probability = delays.iter()
+                        .map(|&d| if transfers {
+                            profiles.get(&f_stop).and_then(|profile| profile.iter().rev().find(|entry| entry.0 >= conn.arr_time + d + self.timetable
+                                .transfer_time(f_stop, conn.trip_id, entry.2.trip_id)
+                                .unwrap_or(dur)))
+                        } else {
+                            best(profiles.get(&f_stop), conn.arr_time + d + dur)
+                        })
+                        .map(|entry| entry.map_or(0.0, |entry| entry.1))
+                        .sum::<f64>() / delays.len() as f64;
+
+                    if exit.map_or(true, |(p, _, _)| probability > p) {
+                        exit = Some((probability, conn, Some((conn.arr_stop, f_stop, dur))));
+                    }
+                }
+            }
+
+            if let Some((probability, alight, footpath)) = exit {
+                if probability <= 0.0 {
+                    continue;
+                }
+
+                trips.insert(conn.trip_id, (probability, alight, footpath));
+
+                let profile = profiles.entry(conn.dep_stop).or_insert_with(Vec::new);
+                if profile.last().map_or(true, |entry| probability > entry.1) {
+                    profile.push((conn.dep_time, probability, conn, alight, footpath));
+                }
+            }
+        }
+
+        // Pick the most reliable departure, possibly after walking to a nearby stop
+        let (start, dur) = self.footpaths_from(dep_stop).into_iter()
+            .filter(|&(f_stop, dur)| best(profiles.get(&f_stop), dep_time + dur).is_some())
+            .max_by(|a, b| {
+                let pa = best(profiles.get(&a.0), dep_time + a.1).unwrap().1;
+                let pb = best(profiles.get(&b.0), dep_time + b.1).unwrap().1;
+                pa.partial_cmp(&pb).unwrap()
+            })?;
+
+        let probability = best(profiles.get(&start), dep_time + dur)?.1;
+
+        let mut parts = vec![];
+        if start != dep_stop {
+            parts.push(TripPart::Footpath(dep_stop, start, dur));
+        }
+
+        let mut cur = start;
+        let mut time = dep_time + dur;
+        while let Some(&(_, _, board, alight, footpath)) = best(profiles.get(&cur), time) {
+            parts.extend(self.timetable.seated_legs(board, alight));
+
+            match footpath {
+                Some((from, to, dur)) => {
+                    parts.push(TripPart::Footpath(from, to, dur));
+                    cur = to;
+                    time = alight.arr_time + dur;
+                },
+                None => break
+            }
+        }
+
+        Some((probability, TripResult {
+            parts
+        }))
+    }
+}
+
+impl<'a> Benchable<'a> for CSAReliable<'a> {
+    fn name(&self) -> &'static str {
+        "CSA with transfer reliability"
+    }
+
+    fn new(timetable: &'a Timetable) -> Self {
+        let mut connections = vec![];
+        for trip in &timetable.trips {
+            connections.extend(&trip.connections);
+        }
+
+        connections.sort();
+
+        CSAReliable {
+            connections,
+            footpaths: &timetable.footpaths,
+            continued_from: continued_from(timetable),
+            transfer_stops: timetable.transfers.keys().map(|&(stop, _, _)| stop).collect(),
+            timetable,
+            delays: HashMap::new(),
+            min_probability: DEFAULT_MIN_PROBABILITY
+        }
+    }
+
+    // Earliest arrival in which every transfer succeeds with at least the minimum probability
+    fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
+        let mut labels: Vec<Label> = vec![];
+        let mut stop_labels: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut in_connection: HashMap<usize, (&Connection, usize)> = HashMap::new();
+        let mut target: Option<usize> = None;
+
+        for (f_stop, dur) in self.footpaths_from(dep_stop) {
+            labels.push(Label { time: dep_time + dur, alighted: None, prev: None });
+            stop_labels.entry(f_stop).or_insert_with(Vec::new).push(labels.len() - 1);
+        }
+
+        let start = partition_point(&self.connections, |conn| conn.dep_time < dep_time);
+        for &conn in &self.connections[start..] {
+            if let Some(t) = target {
+                if labels[t].time <= conn.dep_time {
+                    break;
+                }
+            }
+
+            if !in_connection.contains_key(&conn.trip_id) {
+                if let Some(seated) = board_seated(conn, &self.continued_from, |trip_id| in_connection.get(&trip_id).copied()) {
+                    in_connection.insert(conn.trip_id, seated);
+                }
+            }
+
+            if !in_connection.contains_key(&conn.trip_id) {
+                let boarding = stop_labels.get(&conn.dep_stop).and_then(|stop_labels| stop_labels.iter()
+                    .find(|&&l| self.transfer_probability(&labels[l], conn) >= self.min_probability));
+
+                match boarding {
+                    Some(&l) => in_connection.insert(conn.trip_id, (conn, l)),
+                    None => continue
+                };
+            }
+
+            let (first, boarded_from) = in_connection[&conn.trip_id];
+            for (f_stop, dur) in self.footpaths_from(conn.arr_stop) {
+                let time = conn.arr_time + dur;
+                let alighted = Some((conn.trip_id, conn.arr_stop));
+
+                // An earlier arrival is only better if it is certain or comes from the same trip
+                let dominated = stop_labels.get(&f_stop).map_or(false, |stop_labels| stop_labels.iter()
+                    .any(|&l| labels[l].time <= time && (labels[l].alighted.is_none() || labels[l].alighted == alighted)));
+
+                if dominated {
+                    continue;
+                }
+
+                labels.push(Label { time, alighted, prev: Some((first, conn, (conn.arr_stop, f_stop, dur), boarded_from)) });
+                stop_labels.entry(f_stop).or_insert_with(Vec::new).push(labels.len() - 1);
+
+                if f_stop == arr_stop && target.map_or(true, |t| time < labels[t].time) {
+                    target = Some(labels.len() - 1);
+                }
+            }
+        }
+
+        let mut journey = vec![];
+        let mut cur = target?;
+        while let Some((con1, con2, footpath, prev)) = labels[cur].prev {
+            journey.push(TripPart::Footpath(footpath.0, footpath.1, footpath.2));
+            journey.extend(self.timetable.seated_legs(con1, con2).into_iter().rev());
+            cur = prev;
+        }
+
+        if journey.is_empty() {
+            return None;
+        }
+
+        journey.reverse();
+
+        // We do not care about the final footpath
+        journey.remove(journey.len()-1);
+
+        return Some(TripResult {
+            parts: journey
+        });
+    }
+}
+
+alg_test!(CSAReliable);
+transfer_test!(CSAReliable);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DelayDistribution, Trip};
+
+    fn timetable() -> Timetable {
+        // Trip 0 arrives at stop 1 just before trip 1 leaves, trip 2 leaves stop 1 later on
+        let connections = vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 0, arr_time: 100, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 2, dep_time: 120, arr_time: 200, trip_id: 1 },
+            Connection { dep_stop: 1, arr_stop: 2, dep_time: 600, arr_time: 700, trip_id: 2 },
+        ];
+
+        let mut footpaths = HashMap::new();
+        for i in 0..=2 {
+            footpaths.insert(i, vec![(i, 0)]);
+        }
+
+        Timetable {
+            trips: connections.into_iter().map(|c| Trip {
+                identifier: c.trip_id,
+                connections: vec![c]
+            }).collect(),
+            footpaths,
+            ..Default::default()
+        }
+    }
+
+    fn delays() -> Delays {
+        // Trip 0 is late by more than 20 seconds half of the time
+        let mut delays = HashMap::new();
+        delays.insert((0, 1), DelayDistribution::new(vec![0, 0, 60, 180]));
+        delays
+    }
+
+    #[test]
+    fn avoids_unreliable_transfer() {
+        let timetable = timetable();
+
+        let alg = CSAReliable::with_delays(&timetable, HashMap::new(), 0.9);
+        assert_eq!(alg.find_earliest_arrival(0, 2, 0).unwrap().arrival(), 200);
+
+        let alg = CSAReliable::with_delays(&timetable, delays(), 0.9);
+        assert_eq!(alg.find_earliest_arrival(0, 2, 0).unwrap().arrival(), 700);
+
+        let alg = CSAReliable::with_delays(&timetable, delays(), 0.5);
+        assert_eq!(alg.find_earliest_arrival(0, 2, 0).unwrap().arrival(), 200);
+    }
+
+    #[test]
+    fn most_reliable_arrival() {
+        let timetable = timetable();
+        let alg = CSAReliable::with_delays(&timetable, delays(), DEFAULT_MIN_PROBABILITY);
+
+        // Only trip 1 arrives in time, which is caught half of the time
+        let (probability, journey) = alg.find_most_reliable_arrival(0, 2, 0, 300).unwrap();
+        assert_eq!(probability, 0.5);
+        assert_eq!(journey.arrival(), 200);
+
+        // With a later deadline trip 2 is always caught
+        let (probability, journey) = alg.find_most_reliable_arrival(0, 2, 0, 800).unwrap();
+        assert_eq!(probability, 1.0);
+        assert_eq!(journey.arrival(), 700);
+    }
+
+    #[test]
+    fn most_reliable_arrival_with_transfer_time() {
+        let mut timetable = timetable();
+        let alg = CSAReliable::new(&timetable);
+        assert_eq!(alg.find_most_reliable_arrival(0, 2, 0, 300).unwrap(), (1.0, alg.find_earliest_arrival(0, 2, 0).unwrap()));
+
+        // Transferring from trip 0 to trip 1 takes too long, such that only trip 2 can be taken
+        timetable.transfers.insert((1, Some(0), Some(1)), 60);
+        let alg = CSAReliable::new(&timetable);
+        assert!(alg.find_most_reliable_arrival(0, 2, 0, 300).is_none());
+        assert_eq!(alg.find_most_reliable_arrival(0, 2, 0, 800).unwrap().1.arrival(), 700);
+    }
+}
@@ -1,38 +1,62 @@
 use std::collections::HashMap;
 
-use crate::{benchable::Benchable, types::{Connection, Timetable, TripPart, TripResult}};
-
-pub const MAX_STATIONS: usize = 100000;
+use crate::{benchable::Benchable, types::{Connection, Timetable, Trip, TripPart, TripResult}};
 
 #[derive(Debug)]
 pub struct CSAVec<'a> {
-    connections: Vec<&'a Connection>,
-    footpaths: &'a HashMap<usize, Vec<(usize, u32)>>
+    // Connections together with the index of their trip
+    connections: Vec<(&'a Connection, usize)>,
+    footpaths: &'a HashMap<usize, Vec<(usize, u32)>>,
+    timetable: &'a Timetable,
+    continued_from: HashMap<usize, (usize, &'a Connection)>,
+    trip_index: HashMap<usize, usize>,
+    stop_count: usize
 }
 
-// Based on https://github.com/trainline-eu/csa-challenge/blob/master/csa.rs (WTFPL license)
-impl<'a> Benchable<'a> for CSAVec<'a> {
-    fn name(&self) -> &'static str {
-        "CSA with Vec"
+/// Index of every trip by its id, such that trips can be tracked in a vector of the size of the timetable, as trip ids can
+/// be far apart (e.g. for timetables of multiple days)
+pub fn trip_index(trips: &[Trip]) -> HashMap<usize, usize> {
+    trips.iter().enumerate().map(|(i, trip)| (trip.identifier, i)).collect()
+}
+
+/// Trips that continue as another trip, by the trip they continue as, together with their last connection
+pub fn continued_from(timetable: &Timetable) -> HashMap<usize, (usize, &Connection)> {
+    let last_connections: HashMap<usize, &Connection> = timetable.trips.iter()
+        .filter_map(|trip| Some((trip.identifier, trip.connections.last()?)))
+        .collect();
+
+    timetable.continuations.iter()
+        .filter_map(|(&from, &to)| Some((to, (from, *last_connections.get(&from)?))))
+        .collect()
+}
+
+/// Connection at which the trip of `conn` was boarded when staying seated in the trip it continues from, which is possible
+/// when we were in that trip and it arrived before `conn` departs
+pub fn board_seated<T: Copy>(conn: &Connection, continued_from: &HashMap<usize, (usize, &Connection)>, in_connection: impl Fn(usize) -> Option<T>) -> Option<T> {
+    let &(from, last) = continued_from.get(&conn.trip_id)?;
+    if last.arr_stop == conn.dep_stop && last.arr_time <= conn.dep_time {
+        in_connection(from)
+    } else {
+        None
     }
+}
 
-    fn new(timetable: &'a Timetable) -> Self {
-        let mut connections = vec![];
-        for trip in &timetable.trips {
-            connections.extend(&trip.connections);
-        }
+pub type Journeys<'a> = HashMap<usize, (&'a Connection, &'a Connection, (usize, usize, u32))>;
 
-        connections.sort();
+impl<'a> CSAVec<'a> {
+    /// Earliest arrival time at every stop that can be reached from `dep_stop` when departing at `dep_time`
+    pub fn find_earliest_arrival_all(&self, dep_stop: usize, dep_time: u32) -> HashMap<usize, u32> {
+        let (earliest_arrival, _) = self.scan(dep_stop, None, dep_time);
 
-        CSAVec {
-            connections,
-            footpaths: &timetable.footpaths
-        }
+        earliest_arrival.into_iter().enumerate()
+            .filter(|&(_, time)| time != std::u32::MAX)
+            .collect()
     }
 
-    fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
-        let mut earliest_arrival = vec!(std::u32::MAX; MAX_STATIONS);
-        let mut in_connection = vec!(None; MAX_STATIONS * 10);
+    // Performs the connection scan, when an arrival stop is given the scan stops once its arrival can not be improved anymore
+    fn scan(&self, dep_stop: usize, arr_stop: Option<usize>, dep_time: u32) -> (Vec<u32>, Journeys<'a>) {
+        let mut earliest_arrival = vec!(std::u32::MAX; self.stop_count);
+        let mut in_connection = vec!(None; self.trip_index.len());
         let mut journeys = HashMap::new();
 
         for &(f_stop, dur) in self.footpaths.get(&dep_stop).unwrap() {
@@ -40,30 +64,78 @@ impl<'a> Benchable<'a> for CSAVec<'a> {
         }
 
         // TODO binary tree for start
-        for &conn in &self.connections {
-            if earliest_arrival[arr_stop] <= conn.dep_time {
-                break;
+        for &(conn, trip) in &self.connections {
+            if let Some(arr_stop) = arr_stop {
+                if earliest_arrival[arr_stop] <= conn.dep_time {
+                    break;
+                }
+            }
+
+            if in_connection[trip].is_none() {
+                in_connection[trip] = board_seated(conn, &self.continued_from, |trip_id| in_connection[self.trip_index[&trip_id]]);
             }
 
-            if in_connection[conn.trip_id].is_some() || earliest_arrival[conn.dep_stop] <= conn.dep_time {
-                if in_connection[conn.trip_id].is_none() {
-                    in_connection[conn.trip_id] = Some(conn);
+            if in_connection[trip].is_some() || can_board(self.timetable, conn, &earliest_arrival, &journeys) {
+                if in_connection[trip].is_none() {
+                    in_connection[trip] = Some(conn);
                 }
 
                 for &(f_stop, dur) in self.footpaths.get(&conn.arr_stop).unwrap() {
                     if conn.arr_time + dur < earliest_arrival[f_stop] {
                         earliest_arrival[f_stop] = conn.arr_time + dur;
-                        journeys.insert(f_stop, (in_connection[conn.trip_id].unwrap(), conn, (conn.arr_stop, f_stop, dur)));
+                        journeys.insert(f_stop, (in_connection[trip].unwrap(), conn, (conn.arr_stop, f_stop, dur)));
                     }
                 }
             }
         }
 
+        (earliest_arrival, journeys)
+    }
+}
+
+/// Whether a connection can be reached in time, a transfer time given for the trip we arrived with takes precedence over the footpaths
+pub fn can_board(timetable: &Timetable, conn: &Connection, earliest_arrival: &[u32], journeys: &Journeys) -> bool {
+    match journeys.get(&conn.dep_stop) {
+        Some((_, arrival, _)) if arrival.trip_id != conn.trip_id => match timetable.transfer_time(conn.dep_stop, arrival.trip_id, conn.trip_id) {
+            Some(time) => arrival.arr_time + time <= conn.dep_time,
+            None => earliest_arrival[conn.dep_stop] <= conn.dep_time
+        },
+        _ => earliest_arrival[conn.dep_stop] <= conn.dep_time
+    }
+}
+
+// Based on https://github.com/trainline-eu/csa-challenge/blob/master/csa.rs (WTFPL license)
+impl<'a> Benchable<'a> for CSAVec<'a> {
+    fn name(&self) -> &'static str {
+        "CSA with Vec"
+    }
+
+    fn new(timetable: &'a Timetable) -> Self {
+        let mut connections = vec![];
+        for (i, trip) in timetable.trips.iter().enumerate() {
+            connections.extend(trip.connections.iter().map(|conn| (conn, i)));
+        }
+
+        connections.sort();
+
+        CSAVec {
+            trip_index: trip_index(&timetable.trips),
+            connections,
+            footpaths: &timetable.footpaths,
+            continued_from: continued_from(timetable),
+            stop_count: timetable.stop_count(),
+            timetable
+        }
+    }
+
+    fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
+        let (_, journeys) = self.scan(dep_stop, Some(arr_stop), dep_time);
+
         let mut journey = vec![];
         let mut cur = arr_stop;
         while let Some((con1, con2, footpath)) = journeys.get(&cur) {
             journey.push(TripPart::Footpath(footpath.0, footpath.1, footpath.2));
-            journey.push(TripPart::Connection(con1, con2));
+            journey.extend(self.timetable.seated_legs(con1, con2).into_iter().rev());
             cur = con1.dep_stop;
         }
 
@@ -83,4 +155,5 @@ impl<'a> Benchable<'a> for CSAVec<'a> {
 
 }
 
-alg_test!(CSAVec);
\ No newline at end of file
+alg_test!(CSAVec);
+transfer_test!(CSAVec);
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use super::csa_vec::CSAVec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeetingObjective {
+    LatestArrival,
+    SumOfArrivals
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MeetingPoint {
+    pub stop: usize,
+
+    // Earliest arrival of each traveller, in the same order as the travellers were given
+    pub arrivals: Vec<u32>
+}
+
+impl MeetingPoint {
+    pub fn latest_arrival(&self) -> u32 {
+        *self.arrivals.iter().max().unwrap()
+    }
+
+    pub fn sum_of_arrivals(&self) -> u64 {
+        self.arrivals.iter().map(|&time| time as u64).sum()
+    }
+}
+
+/// Finds all stops every traveller (departure stop and time) can reach, best meeting point first.
+/// A one-to-all connection scan is performed per traveller, after which the arrival times are combined per stop.
+pub fn find_meeting_points(alg: &CSAVec, travellers: &[(usize, u32)], objective: MeetingObjective) -> Vec<MeetingPoint> {
+    let arrivals: Vec<HashMap<usize, u32>> = travellers.iter()
+        .map(|&(dep_stop, dep_time)| alg.find_earliest_arrival_all(dep_stop, dep_time))
+        .collect();
+
+    let mut points: Vec<MeetingPoint> = match arrivals.first() {
+        Some(first) => first.keys()
+            .filter_map(|&stop| Some(MeetingPoint {
+                stop,
+                arrivals: arrivals.iter().map(|a| a.get(&stop).cloned()).collect::<Option<Vec<u32>>>()?
+            }))
+            .collect(),
+        None => return vec![]
+    };
+
+    match objective {
+        MeetingObjective::LatestArrival => points.sort_by_key(|p| (p.latest_arrival() as u64, p.sum_of_arrivals(), p.stop)),
+        MeetingObjective::SumOfArrivals => points.sort_by_key(|p| (p.sum_of_arrivals(), p.latest_arrival() as u64, p.stop))
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{benchable::Benchable, types::{Connection, Timetable, Trip}};
+
+    #[test]
+    fn meeting_points() {
+        // Traveller at stop 0 can reach stop 1 and 2, traveller at stop 3 can only reach stop 2
+        let connections = vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 10, arr_time: 20, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 2, dep_time: 25, arr_time: 40, trip_id: 0 },
+            Connection { dep_stop: 3, arr_stop: 2, dep_time: 10, arr_time: 30, trip_id: 1 },
+        ];
+
+        let mut footpaths = HashMap::new();
+        for i in 0..=3 {
+            footpaths.insert(i, vec![(i, 0)]);
+        }
+
+        let timetable = Timetable {
+            trips: vec![
+                Trip { identifier: 0, connections: connections[0..2].to_vec() },
+                Trip { identifier: 1, connections: connections[2..].to_vec() }
+            ],
+            footpaths,
+            ..Default::default()
+        };
+
+        let alg = CSAVec::new(&timetable);
+        let points = find_meeting_points(&alg, &[(0, 0), (3, 0)], MeetingObjective::LatestArrival);
+
+        assert_eq!(points, vec![MeetingPoint { stop: 2, arrivals: vec![40, 30] }]);
+    }
+}
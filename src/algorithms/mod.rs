@@ -3,8 +3,10 @@ pub mod td_simple_vec;
 pub mod td_simple_btree;
 pub mod csa_btree;
 pub mod csa_vec;
+pub mod csa_reliable;
 pub mod raptor;
 pub mod raptor_btree;
+pub mod meeting_point;
 
 use td_simple_btree::TDSimpleBTree;
 use td_simple_vec::TDSimpleVec;
@@ -1,6 +1,6 @@
 use std::{collections::{BTreeSet, HashMap, HashSet}, ops::Range, cmp};
 
-use crate::{benchable::{Benchable}, types::{Connection, Timetable, Trip, TripPart, TripResult}};
+use crate::{benchable::{Benchable}, types::{Connection, Timetable, Trip, TripPart, TripResult, partition_point}};
 
 #[derive(Debug)]
 struct Route<'a> {
@@ -31,28 +31,10 @@ impl<'a> Route<'a> {
         panic!("Stop not found in stops list!");
     }
 
-    fn trip_from(&self, si: usize, start_time: u32) -> Option<&Trip> {
-        let mut left: usize = 0;
-        let mut right: usize = self.trips.len() - 1;
-        let mut ans = None;
-        while left <= right {
-            let mid = (left + right) / 2;
-
-            if &self.trips[mid].connections[si].dep_time < &start_time {
-                left = mid + 1;
-            } else {
-                ans = Some(self.trips[mid]);
-
-                if mid == 0 {
-                    break;
-                }
-
-                right = mid - 1;
-            }
-
-        };
-
-        ans
+    // First trip departing from the stop at the given index at or after the start time plus the time needed to transfer to it
+    fn trip_from(&self, si: usize, start_time: u32, transfer: impl Fn(&Trip) -> u32) -> Option<&Trip> {
+        let first = partition_point(&self.trips, |trip| trip.connections[si].dep_time < start_time);
+        self.trips[first..].iter().find(|trip| trip.connections[si].dep_time >= start_time + transfer(trip)).copied()
     }
 
     fn len(&self) -> usize {
@@ -61,27 +43,44 @@ impl<'a> Route<'a> {
 }
 
 const MAX_K: usize = 5;
-const MAX_STATIONS: usize = 100000;
 
 #[derive(Debug)]
 pub struct Raptor<'a> {
     routes: Vec<Route<'a>>,
     stops_routes: HashMap<usize, HashSet<usize>>,
-    footpaths: HashMap<usize, HashMap<usize, u32>>
+    footpaths: HashMap<usize, HashMap<usize, u32>>,
+    timetable: &'a Timetable,
+    stop_count: usize
+}
+
+impl<'a> Raptor<'a> {
+    // Time needed at a stop before boarding a trip after arriving with another trip (if any), which is the interchange time
+    // of the stop unless staying seated in a trip that continues as the trip to board or a transfer time is given for these trips
+    fn transfer_time(&self, stop: usize, arrived_with: Option<&usize>, trip: &Trip) -> u32 {
+        let interchange = *self.footpaths.get(&stop).unwrap().get(&stop).unwrap();
+        match arrived_with {
+            Some(from) if self.timetable.continuations.get(from) == Some(&trip.identifier) => 0,
+            Some(&from) => self.timetable.transfer_time(stop, from, trip.identifier).unwrap_or(interchange),
+            None => interchange
+        }
+    }
 }
 
 impl<'a> Benchable<'a> for Raptor<'a> {
 
     fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
-        let mut earliest_k_arrival: Vec<Vec<u32>> = vec![vec![u32::MAX - 3600 * 4; MAX_K]; MAX_STATIONS];
+        let mut earliest_k_arrival: Vec<Vec<u32>> = vec![vec![u32::MAX - 3600 * 4; MAX_K]; self.stop_count];
         earliest_k_arrival[dep_stop][0] = dep_time;
 
-        let mut earliest_arrival:  Vec<u32> = vec![u32::MAX - 3600 * 4; MAX_STATIONS];
+        let mut earliest_arrival:  Vec<u32> = vec![u32::MAX - 3600 * 4; self.stop_count];
         earliest_arrival[dep_stop] = dep_time;
 
         // For constructing the journey
-        let mut interchange: Vec<Option<(usize, usize, u32)>> = vec![None; MAX_STATIONS];
-        let mut prev: Vec<Option<(&Connection, &Connection, (usize, usize, u32))>> = vec![None; MAX_STATIONS];
+        let mut interchange: Vec<Option<(usize, usize, u32)>> = vec![None; self.stop_count];
+        let mut prev: Vec<Option<(&Connection, &Connection, (usize, usize, u32))>> = vec![None; self.stop_count];
+
+        // Trip with which a stop was reached in a round, as no transfer is needed to stay seated in the trip it continues as
+        let mut arrived_with: Vec<HashMap<usize, usize>> = vec![HashMap::new(); MAX_K];
 
         let mut marked = HashSet::new();
         marked.insert(dep_stop);
@@ -111,15 +110,20 @@ impl<'a> Benchable<'a> for Raptor<'a> {
                     if !t.is_none() && t.unwrap().connections[i-1].arr_time < cmp::min(earliest_arrival[arr_stop], earliest_arrival[pi]) {
                         earliest_k_arrival[pi][k] = t.unwrap().connections[i-1].arr_time;
                         earliest_arrival[pi] = t.unwrap().connections[i-1].arr_time;
+                        arrived_with[k].insert(pi, t.unwrap().identifier);
                         prev[pi] = Some((&t.unwrap().connections[t_from], &t.unwrap().connections[i-1], interchange[t.unwrap().connections[t_from].dep_stop].unwrap()));
                         marked.insert(pi);
                     }
 
-                    if i < self.routes[r].len() && (t.is_none() || 
-                       earliest_k_arrival[pi][k-1] + self.footpaths.get(&pi).unwrap().get(&pi).unwrap() < t.unwrap().connections[i].dep_time) {
-                        t = self.routes[r].trip_from(i, earliest_k_arrival[pi][k-1] + self.footpaths.get(&pi).unwrap().get(&pi).unwrap());
-                        interchange[pi] = Some((pi, pi, *self.footpaths.get(&pi).unwrap().get(&pi).unwrap()));
-                        t_from = i;
+                    if i < self.routes[r].len() && t.map_or(true, |t| earliest_k_arrival[pi][k-1] < t.connections[i].dep_time) {
+                        let transfer = |trip: &Trip| self.transfer_time(pi, arrived_with[k-1].get(&pi), trip);
+                        if let Some(trip) = self.routes[r].trip_from(i, earliest_k_arrival[pi][k-1], transfer) {
+                            if t.map_or(true, |t| trip.connections[i].dep_time < t.connections[i].dep_time) {
+                                interchange[pi] = Some((pi, pi, transfer(trip)));
+                                t = Some(trip);
+                                t_from = i;
+                            }
+                        }
                     }
                 }
             }
@@ -129,6 +133,7 @@ impl<'a> Benchable<'a> for Raptor<'a> {
                 for (&p2, &dur) in self.footpaths.get(&p).unwrap() {
                     if earliest_k_arrival[p][k] + dur < earliest_k_arrival[p2][k] {
                         earliest_k_arrival[p2][k] = earliest_k_arrival[p][k] + dur;
+                        arrived_with[k].remove(&p2);
                         interchange[p2] = Some((p, p2, dur))
                     }
                     marked.insert(p2);
@@ -218,10 +223,13 @@ impl<'a> Benchable<'a> for Raptor<'a> {
         Raptor {
             routes,
             stops_routes,
-            footpaths: timetable.footpaths.clone().into_iter().map(|(p1, p2s)| (p1, p2s.into_iter().collect())).collect()
+            footpaths: timetable.footpaths.clone().into_iter().map(|(p1, p2s)| (p1, p2s.into_iter().collect())).collect(),
+            stop_count: timetable.stop_count(),
+            timetable
         }
     }
 
 }
 
-alg_test!(Raptor);
\ No newline at end of file
+alg_test!(Raptor);
+transfer_test!(Raptor);
\ No newline at end of file
@@ -31,8 +31,9 @@ impl<'a> Route<'a> {
         panic!("Stop not found in stops list!");
     }
 
-    fn trip_from(&self, si: usize, start_time: u32) -> Option<&Trip> {
-        self.trips.iter().find(|t| t.connections[si].dep_time > start_time).map(|x| *x)
+    // First trip departing from the stop at the given index at or after the start time plus the time needed to transfer to it
+    fn trip_from(&self, si: usize, start_time: u32, transfer: impl Fn(&Trip) -> u32) -> Option<&Trip> {
+        self.trips.iter().find(|t| t.connections[si].dep_time >= start_time + transfer(t)).map(|x| *x)
     }
 
     fn len(&self) -> usize {
@@ -41,30 +42,47 @@ impl<'a> Route<'a> {
 }
 
 const MAX_K: usize = 5;
-const MAX_STATIONS: usize = 100000;
 
 #[derive(Debug)]
 pub struct RaptorBTree<'a> {
     routes: Vec<Route<'a>>,
     stops_routes: HashMap<usize, HashSet<usize>>,
     footpaths: HashMap<usize, HashMap<usize, u32>>,
+    timetable: &'a Timetable,
+    stop_count: usize,
 
     // For changes, we need to lookup the route it's a part of
     stops_route: HashMap<Vec<usize>, usize>
 }
 
+impl<'a> RaptorBTree<'a> {
+    // Time needed at a stop before boarding a trip after arriving with another trip (if any), which is the interchange time
+    // of the stop unless staying seated in a trip that continues as the trip to board or a transfer time is given for these trips
+    fn transfer_time(&self, stop: usize, arrived_with: Option<&usize>, trip: &Trip) -> u32 {
+        let interchange = *self.footpaths.get(&stop).unwrap().get(&stop).unwrap();
+        match arrived_with {
+            Some(from) if self.timetable.continuations.get(from) == Some(&trip.identifier) => 0,
+            Some(&from) => self.timetable.transfer_time(stop, from, trip.identifier).unwrap_or(interchange),
+            None => interchange
+        }
+    }
+}
+
 impl<'a> Benchable<'a> for RaptorBTree<'a> {
 
     fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
-        let mut earliest_k_arrival: Vec<Vec<u32>> = vec![vec![u32::MAX - 3600 * 4; MAX_K]; MAX_STATIONS];
+        let mut earliest_k_arrival: Vec<Vec<u32>> = vec![vec![u32::MAX - 3600 * 4; MAX_K]; self.stop_count];
         earliest_k_arrival[dep_stop][0] = dep_time;
 
-        let mut earliest_arrival:  Vec<u32> = vec![u32::MAX - 3600 * 4; MAX_STATIONS];
+        let mut earliest_arrival:  Vec<u32> = vec![u32::MAX - 3600 * 4; self.stop_count];
         earliest_arrival[dep_stop] = dep_time;
 
         // For constructing the journey
-        let mut interchange: Vec<Option<(usize, usize, u32)>> = vec![None; MAX_STATIONS];
-        let mut prev: Vec<Option<(&Connection, &Connection, (usize, usize, u32))>> = vec![None; MAX_STATIONS];
+        let mut interchange: Vec<Option<(usize, usize, u32)>> = vec![None; self.stop_count];
+        let mut prev: Vec<Option<(&Connection, &Connection, (usize, usize, u32))>> = vec![None; self.stop_count];
+
+        // Trip with which a stop was reached in a round, as no transfer is needed to stay seated in the trip it continues as
+        let mut arrived_with: Vec<HashMap<usize, usize>> = vec![HashMap::new(); MAX_K];
 
         let mut marked = HashSet::new();
         marked.insert(dep_stop);
@@ -94,6 +112,7 @@ impl<'a> Benchable<'a> for RaptorBTree<'a> {
                     if !t.is_none() && t.unwrap().connections[i-1].arr_time < cmp::min(earliest_arrival[arr_stop], earliest_arrival[pi]) {
                         earliest_k_arrival[pi][k] = t.unwrap().connections[i-1].arr_time;
                         earliest_arrival[pi] = t.unwrap().connections[i-1].arr_time;
+                        arrived_with[k].insert(pi, t.unwrap().identifier);
                         prev[pi] = Some((
                             &t.unwrap().connections[t_from],
                             &t.unwrap().connections[i-1],
@@ -102,11 +121,15 @@ impl<'a> Benchable<'a> for RaptorBTree<'a> {
                         marked.insert(pi);
                     }
 
-                    if i < self.routes[r].len() && (t.is_none() || 
-                       earliest_k_arrival[pi][k-1] + self.footpaths.get(&pi).unwrap().get(&pi).unwrap() < t.unwrap().connections[i].dep_time) {
-                        t = self.routes[r].trip_from(i, earliest_k_arrival[pi][k-1] + self.footpaths.get(&pi).unwrap().get(&pi).unwrap());
-                        interchange[pi] = Some((pi, pi, *self.footpaths.get(&pi).unwrap().get(&pi).unwrap()));
-                        t_from = i;
+                    if i < self.routes[r].len() && t.map_or(true, |t| earliest_k_arrival[pi][k-1] < t.connections[i].dep_time) {
+                        let transfer = |trip: &Trip| self.transfer_time(pi, arrived_with[k-1].get(&pi), trip);
+                        if let Some(trip) = self.routes[r].trip_from(i, earliest_k_arrival[pi][k-1], transfer) {
+                            if t.map_or(true, |t| trip.connections[i].dep_time < t.connections[i].dep_time) {
+                                interchange[pi] = Some((pi, pi, transfer(trip)));
+                                t = Some(trip);
+                                t_from = i;
+                            }
+                        }
                     }
                 }
             }
@@ -116,6 +139,7 @@ impl<'a> Benchable<'a> for RaptorBTree<'a> {
                 for (&p2, &dur) in self.footpaths.get(&p).unwrap() {
                     if earliest_k_arrival[p][k] + dur < earliest_k_arrival[p2][k] {
                         earliest_k_arrival[p2][k] = earliest_k_arrival[p][k] + dur;
+                        arrived_with[k].remove(&p2);
                         interchange[p2] = Some((p, p2, dur))
                     }
                     marked.insert(p2);
@@ -212,7 +236,9 @@ impl<'a> Benchable<'a> for RaptorBTree<'a> {
             routes,
             stops_routes,
             footpaths: timetable.footpaths.clone().into_iter().map(|(p1, p2s)| (p1, p2s.into_iter().collect())).collect(),
-            stops_route
+            stops_route,
+            stop_count: timetable.stop_count(),
+            timetable
         }
     }
 
@@ -270,4 +296,5 @@ impl<'a> BenchableLive<'a> for RaptorBTree<'a> {
     }
 }
 
-alg_test!(RaptorBTree);
\ No newline at end of file
+alg_test!(RaptorBTree);
+transfer_test!(RaptorBTree);
\ No newline at end of file
@@ -5,8 +5,6 @@ use std::cmp::Ordering;
 use crate::{benchable::{Benchable, BenchableLive}, types::{Timetable, TripPart, TripResult, TripUpdate}};
 use crate::types::Connection;
 
-pub const MAX_STATIONS: usize = 100000;
-
 #[derive(Debug)]
 pub struct Station<'a> {
     station: usize,
@@ -36,8 +34,11 @@ fn bin_search_arr<'a>(connections: &BTreeSet<&'a Connection>, start_time: u32) -
 }
 
 #[derive(Debug)]
+// Transfer times between specific trips (`Timetable::transfers`) are not supported, as the search only keeps track of
+// the arrival time at every station and not of the trip it was reached with
 pub struct TDSimpleBTree<'a> {
-    data: HashMap<usize, Station<'a>>
+    data: HashMap<usize, Station<'a>>,
+    stop_count: usize
 }
 
 impl<'a> Benchable<'a> for TDSimpleBTree<'a> {
@@ -61,7 +62,8 @@ impl<'a> Benchable<'a> for TDSimpleBTree<'a> {
         }
 
         TDSimpleBTree {
-            data: stations
+            data: stations,
+            stop_count: timetable.stop_count()
         }
     }
 
@@ -94,9 +96,9 @@ impl<'a> Benchable<'a> for TDSimpleBTree<'a> {
             }
         }
 
-        let mut dist: Vec<u32> = vec![u32::MAX - 3600 * 24; MAX_STATIONS];
+        let mut dist: Vec<u32> = vec![u32::MAX - 3600 * 24; self.stop_count];
         let mut heap: BinaryHeap<State> = BinaryHeap::new();
-        let mut prev: Vec<Option<TripPart>> = vec![None; MAX_STATIONS];
+        let mut prev: Vec<Option<TripPart>> = vec![None; self.stop_count];
 
         dist[dep_stop] = dep_time;
         heap.push(State {
@@ -5,8 +5,6 @@ use std::cmp::Ordering;
 use crate::{benchable::Benchable, types::{Timetable, TripPart, TripResult}};
 use crate::types::Connection;
 
-pub const MAX_STATIONS: usize = 100000;
-
 #[derive(Debug)]
 pub struct Station<'a> {
     station: usize,
@@ -85,8 +83,11 @@ fn bin_search_arr<'a>(connections: &Vec<&'a Connection>, start_time: u32) -> Opt
     return ans;
 }
 
+// Transfer times between specific trips (`Timetable::transfers`) are not supported, as the search only keeps track of
+// the arrival time at every station and not of the trip it was reached with
 pub struct TDSimpleVec<'a> {
-    data: HashMap<usize, Station<'a>>
+    data: HashMap<usize, Station<'a>>,
+    stop_count: usize
 }
 
 impl<'a> Benchable<'a> for TDSimpleVec<'a> {
@@ -114,15 +115,16 @@ impl<'a> Benchable<'a> for TDSimpleVec<'a> {
         }
 
         TDSimpleVec {
-            data: stations
+            data: stations,
+            stop_count: timetable.stop_count()
         }
     }
 
     fn find_earliest_arrival(&self, dep_stop: usize, arr_stop: usize, dep_time: u32) -> Option<TripResult> {
 
-        let mut dist: Vec<u32> = vec![u32::MAX - 3600 * 24; MAX_STATIONS];
+        let mut dist: Vec<u32> = vec![u32::MAX - 3600 * 24; self.stop_count];
         let mut heap: BinaryHeap<State> = BinaryHeap::new();
-        let mut prev: Vec<Option<TripPart>> = vec![None; MAX_STATIONS];
+        let mut prev: Vec<Option<TripPart>> = vec![None; self.stop_count];
 
         dist[dep_stop] = dep_time;
         heap.push(State {
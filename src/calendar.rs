@@ -0,0 +1,236 @@
+// Timetable storing every trip once as a pattern together with the days on which it runs, instead of a copy per day. Trips
+// are only expanded to connections for the dates they are needed, which can be a range of multiple days, or for the time
+// window of a query, which leaves out the trips of these days running before or after it.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Duration, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+
+use crate::types::{Connection, Stop, Timetable, Trip, TripDetails, day_trip_id, local_timestamp};
+
+/// Days on which a pattern runs, as a bitset where the first bit is the first day of the calendar
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validity {
+    bits: Vec<u64>
+}
+
+impl Validity {
+    /// Bitset of the given dates, dates before the first day are left out
+    pub fn from_dates<'a>(first_day: &NaiveDate, dates: impl IntoIterator<Item=&'a NaiveDate>) -> Self {
+        let mut bits = vec![];
+        for date in dates {
+            let day = (*date - *first_day).num_days();
+            if day < 0 {
+                continue;
+            }
+
+            let word = day as usize / 64;
+            if bits.len() <= word {
+                bits.resize(word + 1, 0);
+            }
+            bits[word] |= 1 << (day % 64);
+        }
+
+        Validity { bits }
+    }
+
+    /// Whether the pattern runs on the given amount of days after the first day
+    pub fn contains(&self, day: usize) -> bool {
+        self.bits.get(day / 64).map_or(false, |word| word & (1 << (day % 64)) != 0)
+    }
+}
+
+/// Connection of a pattern, with wall-clock times in seconds since midnight of the service day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternConnection {
+    pub dep_stop: usize,
+    pub arr_stop: usize,
+    pub dep_time: u32,
+    pub arr_time: u32
+}
+
+#[derive(Debug, Clone)]
+pub struct TripPattern {
+    pub identifier: usize,
+    pub validity: usize,
+    pub connections: Vec<PatternConnection>,
+
+    /// Pattern this trip continues as on the same day, e.g. a through-running train changing its number
+    pub continues_as: Option<usize>,
+    pub details: TripDetails
+}
+
+#[derive(Debug)]
+pub struct CalendarTimetable<S> {
+    pub first_day: NaiveDate,
+
+    /// Timezone of the times of the patterns
+    pub timezone: Tz,
+    pub stops: HashMap<usize, S>,
+
+    /// Hours the times at a stop differ from the timezone during periods (inclusive), e.g. for stations abroad
+    pub differences: HashMap<usize, Vec<(NaiveDate, NaiveDate, i64)>>,
+    pub validities: HashMap<usize, Validity>,
+    pub patterns: Vec<TripPattern>,
+    pub footpaths: HashMap<usize, Vec<(usize, u32)>>,
+
+    /// Minimum transfer times between trips as in `Timetable`, by the identifiers of their patterns
+    pub transfers: HashMap<(usize, Option<usize>, Option<usize>), u32>
+}
+
+impl<S: Stop + Clone + 'static> CalendarTimetable<S> {
+    pub fn runs_on(&self, pattern: &TripPattern, date: &NaiveDate) -> bool {
+        let day = (*date - self.first_day).num_days();
+        day >= 0 && self.validities.get(&pattern.validity).map_or(false, |validity| validity.contains(day as usize))
+    }
+
+    // Timestamp of a time at a stop on a service date
+    fn timestamp(&self, stop: usize, date: &NaiveDate, time: u32) -> u32 {
+        let difference = self.differences.get(&stop)
+            .and_then(|periods| periods.iter().find(|(from, to, _)| from <= date && date <= to))
+            .map_or(0, |&(_, _, difference)| difference);
+
+        (local_timestamp(&self.timezone, date, time) as i64 - difference * 3600) as u32
+    }
+
+    /// Trips running on a date, which are expanded while iterating. Their ids are those of the n-th day (see `day_trip_id`).
+    pub fn trips_on<'a>(&'a self, date: NaiveDate, day: usize) -> impl Iterator<Item=Trip> + 'a {
+        self.patterns.iter()
+            .filter(move |pattern| !pattern.connections.is_empty() && self.runs_on(pattern, &date))
+            .map(move |pattern| {
+                let trip_id = day_trip_id(day, pattern.identifier);
+                Trip {
+                    identifier: trip_id,
+                    connections: pattern.connections.iter().map(|connection| Connection {
+                        dep_stop: connection.dep_stop,
+                        arr_stop: connection.arr_stop,
+                        dep_time: self.timestamp(connection.dep_stop, &date, connection.dep_time),
+                        arr_time: self.timestamp(connection.arr_stop, &date, connection.arr_time),
+                        trip_id
+                    }).collect()
+                }
+            })
+    }
+
+    /// Timetable of the trips running from one date until another (inclusive), in which the stops and footpaths are only
+    /// present once. Trips on the n-th day of the range get the ids of that day (see `day_trip_id`).
+    pub fn timetable_for_range(&self, from: &NaiveDate, to: &NaiveDate) -> Timetable {
+        let dates = (0..=(*to - *from).num_days()).map(|day| (day as usize, *from + Duration::days(day)));
+        self.expand(dates, |_| true)
+    }
+
+    /// Timetable of only the trips running at some point between two timestamps, such that a query spanning multiple days
+    /// does not need every trip of these days. Trips get the ids of their date counted from the first day of the calendar.
+    pub fn timetable_for_window(&self, from: u32, to: u32) -> Timetable {
+        // Trips of a service day can run until well after midnight, and stations abroad can be an hour off
+        let length = self.patterns.iter()
+            .filter_map(|pattern| pattern.connections.last())
+            .map(|connection| connection.arr_time)
+            .max()
+            .unwrap_or(0);
+        let date = |timestamp: u32| self.timezone.timestamp(timestamp as i64, 0).naive_local().date();
+        let (first, last) = (date(from.saturating_sub(length)).pred().max(self.first_day), date(to).succ());
+
+        let dates = (0..=(last - first).num_days())
+            .map(|day| first + Duration::days(day))
+            .map(|date| ((date - self.first_day).num_days() as usize, date));
+
+        self.expand(dates, |trip| trip.connections[0].dep_time <= to && trip.connections.last().unwrap().arr_time >= from)
+    }
+
+    // Timetable of the trips on the given days and dates for which `keep` holds, with the continuations, details and
+    // transfers of these trips
+    fn expand(&self, dates: impl Iterator<Item=(usize, NaiveDate)>, keep: impl Fn(&Trip) -> bool) -> Timetable {
+        let mut trips = vec![];
+        let mut continuations = HashMap::new();
+        let mut transfers = HashMap::new();
+        let mut details = HashMap::new();
+
+        for (day, date) in dates {
+            let trip_id = |id| day_trip_id(day, id);
+            let day_trips: Vec<Trip> = self.trips_on(date, day).filter(|trip| keep(trip)).collect();
+            if day_trips.is_empty() {
+                continue;
+            }
+
+            let kept: HashSet<usize> = day_trips.iter().map(|trip| trip.identifier).collect();
+            for pattern in self.patterns.iter().filter(|pattern| kept.contains(&trip_id(pattern.identifier))) {
+                if let Some(next) = pattern.continues_as {
+                    continuations.insert(trip_id(pattern.identifier), trip_id(next));
+                }
+                details.insert(trip_id(pattern.identifier), pattern.details.clone());
+            }
+
+            // Transfer times are only needed for the trips that are kept
+            let involved = |trip: Option<usize>| trip.map_or(false, |trip| kept.contains(&trip));
+            transfers.extend(self.transfers.iter()
+                .map(|(&(stop, from_trip, to_trip), &duration)| ((stop, from_trip.map(trip_id), to_trip.map(trip_id)), duration))
+                .filter(|&((_, from_trip, to_trip), _)| (from_trip.is_none() && to_trip.is_none()) || involved(from_trip) || involved(to_trip)));
+
+            trips.extend(day_trips);
+        }
+
+        Timetable {
+            trips,
+            stops: self.stops.iter().map(|(&id, stop)| (id, Box::new(stop.clone()) as Box<dyn Stop>)).collect(),
+            footpaths: self.footpaths.clone(),
+            continuations,
+            sections: HashMap::new(),
+            transfers,
+            details
+        }
+    }
+
+    /// Timetable of a date together with the next date and the trips of the previous date still running after midnight,
+    /// see `Timetable::merge_day`. Trips of the date keep their identifiers, the next date is the first day and the previous
+    /// date the second day.
+    pub fn timetable_around(&self, date: &NaiveDate) -> Timetable {
+        let mut timetable = self.timetable_for_range(date, &date.succ());
+        let midnight = self.timezone.from_local_datetime(&date.and_hms(0, 0, 0)).unwrap().timestamp() as u32;
+        timetable.merge_day(2, self.timetable_for_range(&date.pred(), &date.pred()), midnight);
+        timetable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::railways_netherlands::iff::IFFStop;
+
+    #[test]
+    fn validity() {
+        let first_day = NaiveDate::from_ymd(2021, 1, 4);
+        let dates = vec![NaiveDate::from_ymd(2021, 1, 3), first_day, NaiveDate::from_ymd(2021, 3, 10)];
+        let validity = Validity::from_dates(&first_day, &dates);
+
+        assert!(validity.contains(0));
+        assert!(!validity.contains(1));
+        assert!(validity.contains(65));
+        assert!(!validity.contains(1000));
+    }
+
+    #[test]
+    fn times_across_dst() {
+        let date = NaiveDate::from_ymd(2021, 3, 28);
+        let mut differences = HashMap::new();
+        differences.insert(1, vec![(date, date, -1)]);
+
+        let calendar: CalendarTimetable<IFFStop> = CalendarTimetable {
+            first_day: date,
+            timezone: chrono_tz::Europe::Amsterdam,
+            stops: HashMap::new(),
+            differences,
+            validities: HashMap::new(),
+            patterns: vec![],
+            footpaths: HashMap::new(),
+            transfers: HashMap::new()
+        };
+        let utc = |hour| chrono::Utc.from_utc_datetime(&date.and_hms(hour, 0, 0)).timestamp() as u32;
+
+        // The clocks are turned forward at 02:00, stop 1 is an hour behind
+        assert_eq!(calendar.timestamp(0, &date, 3600), utc(0));
+        assert_eq!(calendar.timestamp(0, &date, 3 * 3600), utc(1));
+        assert_eq!(calendar.timestamp(1, &date, 3600), utc(1));
+    }
+}
@@ -45,6 +45,10 @@ pub fn get_data() -> Result<Timetable, Box<dyn Error>> {
     Ok(Timetable {
         trips,
         stops,
-        footpaths: HashMap::new()
+        footpaths: HashMap::new(),
+        continuations: HashMap::new(),
+        sections: HashMap::new(),
+        transfers: HashMap::new(),
+        details: HashMap::new()
     })
 }
\ No newline at end of file
@@ -0,0 +1,388 @@
+use std::{collections::{HashMap, HashSet}, error::Error, fs::{self, File}, io::Read, path::Path};
+
+use chrono::{Datelike, Duration, NaiveDate, TimeZone, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, de::DeserializeOwned};
+use zip::{ZipArchive, result::ZipError};
+
+use crate::types::{Connection, Stop, Timetable, Trip, haversine_distance};
+
+#[derive(Debug, Deserialize)]
+struct AgencyRecord {
+    agency_timezone: String
+}
+
+#[derive(Debug, Deserialize)]
+struct StopRecord {
+    stop_id: String,
+    stop_name: Option<String>,
+    stop_lat: Option<f64>,
+    stop_lon: Option<f64>,
+    parent_station: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: String,
+    exception_type: u8
+}
+
+#[derive(Debug, Deserialize)]
+struct TripRecord {
+    service_id: String,
+    trip_id: String
+}
+
+#[derive(Debug, Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    arrival_time: Option<String>,
+    departure_time: Option<String>,
+    stop_id: String,
+    stop_sequence: u32
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferRecord {
+    from_stop_id: String,
+    to_stop_id: String,
+    transfer_type: Option<u8>,
+    min_transfer_time: Option<u32>
+}
+
+/// Stop as defined in stops.txt, with WGS84 coordinates
+#[derive(Debug)]
+pub struct GTFSStop {
+    pub id: String,
+    pub name: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub parent_station: Option<String>
+}
+
+impl Stop for GTFSStop {
+    fn to_string(&self) -> String {
+        self.id.clone()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.id.clone())
+    }
+
+    fn coords(&self) -> Option<(f64, f64)> {
+        Some((self.lat?, self.lon?))
+    }
+
+    fn distance(&self, other: &Box<dyn Stop>) -> Option<f64> {
+        Some(haversine_distance(self.coords()?, other.coords()?))
+    }
+}
+
+/// Reads a file from a GTFS feed, which is either a zip file or an extracted directory. Returns none if the file is not in the feed.
+pub fn read_feed_file(path: &Path, file: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let mut text = String::new();
+
+    if path.is_dir() {
+        let file = path.join(file);
+        if !file.exists() {
+            return Ok(None);
+        }
+
+        text = fs::read_to_string(file)?;
+    } else {
+        let mut zip = ZipArchive::new(File::open(path)?)?;
+        match zip.by_name(file) {
+            Ok(mut file) => { file.read_to_string(&mut text)?; },
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(err.into())
+        };
+    }
+
+    // Some feeds start their files with a byte order mark
+    Ok(Some(text.trim_start_matches('\u{feff}').to_string()))
+}
+
+fn read_records<T: DeserializeOwned>(path: &Path, file: &str) -> Result<Vec<T>, Box<dyn Error>> {
+    match read_feed_file(path, file)? {
+        Some(text) => Ok(csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(text.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<T>, _>>()?),
+        None => Ok(vec![])
+    }
+}
+
+/// Parses a GTFS time (H:MM:SS) to seconds since the start of the service day, times can exceed 24:00:00
+fn parse_time(time: &str) -> Option<u32> {
+    let mut split = time.split(':').map(|t| t.parse::<u32>());
+    Some(split.next()?.ok()? * 3600 + split.next()?.ok()? * 60 + split.next()?.ok()?)
+}
+
+/// Timezone in which the times of the feed are given, which is the same for every agency in agency.txt
+fn feed_timezone(path: &Path) -> Result<Tz, Box<dyn Error>> {
+    let agency = read_records::<AgencyRecord>(path, "agency.txt")?.into_iter().next().ok_or("Missing agency in agency.txt")?;
+    Ok(agency.agency_timezone.parse::<Tz>()?)
+}
+
+/// Timestamp from which the times of a service day are counted, which is noon minus 12 hours in the timezone of the feed.
+/// On days with a DST change this differs from midnight, as specified for stop_times.txt.
+fn start_of_service_day(tz: &Tz, date: &NaiveDate) -> u32 {
+    (tz.from_local_datetime(&date.and_hms(12, 0, 0)).unwrap() - Duration::hours(12)).timestamp() as u32
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    Ok(NaiveDate::parse_from_str(date, "%Y%m%d")?)
+}
+
+/// Service ids running on a date according to calendar.txt and the exceptions in calendar_dates.txt
+fn active_services(calendar: &[CalendarRecord], calendar_dates: &[CalendarDateRecord], date: &NaiveDate) -> Result<HashSet<String>, Box<dyn Error>> {
+    let mut services = HashSet::new();
+
+    for record in calendar {
+        let runs = match date.weekday() {
+            Weekday::Mon => record.monday,
+            Weekday::Tue => record.tuesday,
+            Weekday::Wed => record.wednesday,
+            Weekday::Thu => record.thursday,
+            Weekday::Fri => record.friday,
+            Weekday::Sat => record.saturday,
+            Weekday::Sun => record.sunday
+        } == 1;
+
+        if runs && &parse_date(&record.start_date)? <= date && date <= &parse_date(&record.end_date)? {
+            services.insert(record.service_id.clone());
+        }
+    }
+
+    for record in calendar_dates {
+        if &parse_date(&record.date)? != date {
+            continue;
+        }
+
+        match record.exception_type {
+            1 => { services.insert(record.service_id.clone()); },
+            2 => { services.remove(&record.service_id); },
+            other => Err(format!("Unknown exception type in calendar_dates.txt: {}", other))?
+        }
+    }
+
+    Ok(services)
+}
+
+/// Trip identifiers as used in the timetable, which are the position of the trip in trips.txt
+pub fn read_trip_ids(path: &Path) -> Result<HashMap<String, usize>, Box<dyn Error>> {
+    Ok(read_records::<TripRecord>(path, "trips.txt")?.into_iter()
+        .enumerate()
+        .map(|(i, trip)| (trip.trip_id, i))
+        .collect())
+}
+
+// Stop and times of the timetable, where stops without times are not timepoints and treated as passing stops
+fn timed_stop(stop_time: &StopTimeRecord, stops_lookup: &HashMap<String, usize>) -> Option<(usize, u32, u32)> {
+    let arr_time = stop_time.arrival_time.as_deref().and_then(parse_time);
+    let dep_time = stop_time.departure_time.as_deref().and_then(parse_time);
+
+    Some((
+        *stops_lookup.get(&stop_time.stop_id)?,
+        arr_time.or(dep_time)?,
+        dep_time.or(arr_time)?
+    ))
+}
+
+/// Stop sequences of the stops of every trip which are in its connections (see `get_timetable_for_day`), by trip identifier.
+/// GTFS-RT updates can refer to a stop of a trip by its stop sequence, as a trip can call at the same stop more than once.
+pub fn read_stop_sequences(path: &Path) -> Result<HashMap<usize, Vec<u32>>, Box<dyn Error>> {
+    let stops_lookup: HashMap<String, usize> = read_records::<StopRecord>(path, "stops.txt")?.into_iter()
+        .enumerate()
+        .map(|(i, stop)| (stop.stop_id, i))
+        .collect();
+    let trip_ids = read_trip_ids(path)?;
+
+    let mut sequences: HashMap<usize, Vec<u32>> = HashMap::new();
+    let mut stop_times = read_records::<StopTimeRecord>(path, "stop_times.txt")?;
+    stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+
+    for stop_time in stop_times.iter().filter(|stop_time| timed_stop(stop_time, &stops_lookup).is_some()) {
+        if let Some(&trip_id) = trip_ids.get(&stop_time.trip_id) {
+            sequences.entry(trip_id).or_insert_with(Vec::new).push(stop_time.stop_sequence);
+        }
+    }
+
+    Ok(sequences)
+}
+
+/// Builds the timetable of a single service date from a GTFS feed (zip file or directory)
+pub fn get_timetable_for_day(path: &Path, date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
+    let stops: Vec<GTFSStop> = read_records::<StopRecord>(path, "stops.txt")?.into_iter()
+        .map(|stop| GTFSStop {
+            id: stop.stop_id,
+            name: stop.stop_name,
+            lat: stop.stop_lat,
+            lon: stop.stop_lon,
+            parent_station: stop.parent_station.filter(|parent| !parent.is_empty())
+        })
+        .collect();
+
+    let stops_lookup: HashMap<String, usize> = stops.iter().enumerate().map(|(i, stop)| (stop.id.clone(), i)).collect();
+
+    let services = active_services(
+        &read_records::<CalendarRecord>(path, "calendar.txt")?,
+        &read_records::<CalendarDateRecord>(path, "calendar_dates.txt")?,
+        date
+    )?;
+
+    let trip_ids: HashMap<String, usize> = read_records::<TripRecord>(path, "trips.txt")?.into_iter()
+        .enumerate()
+        .filter(|(_, trip)| services.contains(&trip.service_id))
+        .map(|(i, trip)| (trip.trip_id, i))
+        .collect();
+
+    let mut stop_times: HashMap<usize, Vec<StopTimeRecord>> = HashMap::new();
+    for stop_time in read_records::<StopTimeRecord>(path, "stop_times.txt")? {
+        if let Some(&trip_id) = trip_ids.get(&stop_time.trip_id) {
+            stop_times.entry(trip_id).or_insert_with(Vec::new).push(stop_time);
+        }
+    }
+
+    let start_of_day = start_of_service_day(&feed_timezone(path)?, date);
+
+    let mut trips = vec![];
+    for (identifier, mut stop_times) in stop_times {
+        stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+
+        let timed: Vec<(usize, u32, u32)> = stop_times.iter().filter_map(|stop_time| timed_stop(stop_time, &stops_lookup)).collect();
+
+        let connections: Vec<Connection> = timed.windows(2).map(|w| Connection {
+            dep_stop: w[0].0,
+            arr_stop: w[1].0,
+            dep_time: start_of_day + w[0].2,
+            arr_time: start_of_day + w[1].1,
+            trip_id: identifier
+        }).collect();
+
+        if !connections.is_empty() {
+            trips.push(Trip {
+                identifier,
+                connections
+            });
+        }
+    }
+
+    // Every stop gets a 'loopback' footpath, which is replaced by the minimum transfer time at the stop when given
+    let mut footpaths: HashMap<usize, Vec<(usize, u32)>> = (0..stops.len()).map(|stop| (stop, vec![(stop, 0)])).collect();
+    for transfer in read_records::<TransferRecord>(path, "transfers.txt")? {
+        let (from, to) = match (stops_lookup.get(&transfer.from_stop_id), stops_lookup.get(&transfer.to_stop_id)) {
+            (Some(&from), Some(&to)) => (from, to),
+            _ => continue
+        };
+
+        // Transfers of type 3 are not possible, without a minimum time only the loopback can be used
+        if transfer.transfer_type == Some(3) {
+            continue;
+        }
+
+        let duration = match transfer.min_transfer_time {
+            Some(duration) => duration,
+            None if from == to => continue,
+            None => 0
+        };
+
+        let paths = footpaths.get_mut(&from).unwrap();
+        match paths.iter_mut().find(|(stop, _)| *stop == to) {
+            Some(path) => path.1 = duration,
+            None => paths.push((to, duration))
+        }
+    }
+
+    Ok(Timetable {
+        trips,
+        stops: stops.into_iter().enumerate().map(|(i, stop)| (i, Box::new(stop) as Box<dyn Stop>)).collect(),
+        footpaths,
+        continuations: HashMap::new(),
+        sections: HashMap::new(),
+        transfers: HashMap::new(),
+        details: HashMap::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time("08:05:30"), Some(8 * 3600 + 5 * 60 + 30));
+        assert_eq!(parse_time("25:00:00"), Some(25 * 3600));
+        assert_eq!(parse_time(""), None);
+    }
+
+    #[test]
+    fn services() {
+        let calendar = vec![CalendarRecord {
+            service_id: "weekdays".to_string(),
+            monday: 1, tuesday: 1, wednesday: 1, thursday: 1, friday: 1, saturday: 0, sunday: 0,
+            start_date: "20210101".to_string(),
+            end_date: "20211231".to_string()
+        }];
+
+        let calendar_dates = vec![
+            CalendarDateRecord { service_id: "weekdays".to_string(), date: "20210118".to_string(), exception_type: 2 },
+            CalendarDateRecord { service_id: "extra".to_string(), date: "20210118".to_string(), exception_type: 1 },
+        ];
+
+        let friday = active_services(&calendar, &calendar_dates, &NaiveDate::from_ymd(2021, 1, 15)).unwrap();
+        assert!(friday.contains("weekdays") && !friday.contains("extra"));
+
+        let saturday = active_services(&calendar, &calendar_dates, &NaiveDate::from_ymd(2021, 1, 16)).unwrap();
+        assert!(saturday.is_empty());
+
+        let monday = active_services(&calendar, &calendar_dates, &NaiveDate::from_ymd(2021, 1, 18)).unwrap();
+        assert!(!monday.contains("weekdays") && monday.contains("extra"));
+    }
+
+    #[test]
+    fn feed_timetable() {
+        let feed = tempfile::tempdir().unwrap();
+        let files = [
+            ("agency.txt", "agency_id,agency_name,agency_url,agency_timezone\n1,Transit,https://example.com,America/New_York\n"),
+            ("stops.txt", "stop_id,stop_name,stop_lat,stop_lon,parent_station\nA,Alpha,40.70,-74.00,\nB,Beta,40.75,-73.99,\n"),
+            ("calendar.txt", "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\nweekdays,1,1,1,1,1,0,0,20210101,20211231\n"),
+            ("trips.txt", "route_id,service_id,trip_id\nr,weekends,t0\nr,weekdays,t1\n"),
+            ("stop_times.txt", "trip_id,arrival_time,departure_time,stop_id,stop_sequence\nt0,09:00:00,09:00:00,A,1\nt0,09:30:00,09:30:00,B,2\nt1,08:00:00,08:00:00,A,1\nt1,24:30:00,24:30:00,B,2\n"),
+            ("transfers.txt", "from_stop_id,to_stop_id,transfer_type,min_transfer_time\nA,B,2,300\n")
+        ];
+
+        for (file, contents) in files.iter() {
+            fs::write(feed.path().join(file), contents).unwrap();
+        }
+
+        let timetable = get_timetable_for_day(feed.path(), &NaiveDate::from_ymd(2021, 1, 15)).unwrap();
+
+        // Only the weekday trip runs, its times are in the timezone of the agency (UTC-5 in January)
+        let utc = |day, hour, minute| chrono::Utc.ymd(2021, 1, day).and_hms(hour, minute, 0).timestamp() as u32;
+        assert_eq!(timetable.trips, vec![Trip {
+            identifier: 1,
+            connections: vec![Connection { dep_stop: 0, arr_stop: 1, dep_time: utc(15, 13, 0), arr_time: utc(16, 5, 30), trip_id: 1 }]
+        }]);
+
+        assert_eq!(timetable.stops[&1].name(), "Beta");
+        assert_eq!(timetable.footpaths[&0], vec![(0, 0), (1, 300)]);
+        assert_eq!(read_stop_sequences(feed.path()).unwrap()[&1], vec![1, 2]);
+    }
+}
@@ -0,0 +1,358 @@
+use std::{collections::{BTreeSet, HashMap}, error::Error, fs};
+
+use chrono::NaiveDate;
+use prost::Message;
+
+use super::gtfs_realtime_types::{self as rt, FeedMessage, StopScheduleRelationship, TripScheduleRelationship};
+use crate::types::{Connection, Timetable, Trip, TripUpdate};
+
+/// Reads a GTFS-RT feed from a file, or over HTTP when given an URL
+pub async fn read_feed(source: &str) -> Result<FeedMessage, Box<dyn Error>> {
+    let bytes = if source.starts_with("http://") || source.starts_with("https://") {
+        reqwest::get(source).await?.bytes().await?.to_vec()
+    } else {
+        fs::read(source)?
+    };
+
+    Ok(FeedMessage::decode(&bytes[..])?)
+}
+
+/// Converts GTFS-RT trip updates to updates of a timetable loaded from the corresponding static GTFS feed
+pub struct TripUpdateConverter {
+    date: NaiveDate,
+    trip_ids: HashMap<String, usize>,
+    stops: HashMap<String, usize>,
+
+    // Planned stops of every trip in the timetable: stop, arrival time and departure time
+    planned: HashMap<usize, Vec<(usize, u32, u32)>>,
+
+    // Stop sequences of the planned stops of every trip, see `gtfs::read_stop_sequences`
+    stop_sequences: HashMap<usize, Vec<u32>>,
+
+    // This list should always contain the 'newest' known timetable
+    trips: HashMap<usize, BTreeSet<Connection>>
+}
+
+impl TripUpdateConverter {
+    /// Creates a converter for a timetable of the given date, using the trip identifiers from `gtfs::read_trip_ids` and the
+    /// stop sequences from `gtfs::read_stop_sequences`
+    pub fn new(timetable: &Timetable, trip_ids: HashMap<String, usize>, stop_sequences: HashMap<usize, Vec<u32>>, date: NaiveDate) -> Self {
+        let planned = timetable.trips.iter().map(|trip| {
+            let mut stops: Vec<(usize, u32, u32)> = vec![];
+            for conn in &trip.connections {
+                match stops.last_mut() {
+                    Some(last) => last.2 = conn.dep_time,
+                    None => stops.push((conn.dep_stop, conn.dep_time, conn.dep_time))
+                }
+                stops.push((conn.arr_stop, conn.arr_time, conn.arr_time));
+            }
+
+            (trip.identifier, stops)
+        }).collect();
+
+        TripUpdateConverter {
+            date,
+            trip_ids,
+            stops: timetable.stops.iter().map(|(stop_id, stop)| (stop.to_string(), *stop_id)).collect(),
+            planned,
+            stop_sequences,
+            trips: timetable.trips.iter().map(|trip| (trip.identifier, trip.connections.clone().into_iter().collect())).collect()
+        }
+    }
+
+    /// Converts all trip updates in a feed, updates for other service dates or unknown trips are ignored.
+    /// Feeds should be converted in the order they were received.
+    pub fn convert(&mut self, feed: &FeedMessage) -> Vec<Vec<TripUpdate>> {
+        let mut updates = vec![];
+
+        for trip_update in feed.entity.iter().filter(|e| !e.is_deleted.unwrap_or(false)).filter_map(|e| e.trip_update.as_ref()) {
+            let descriptor = &trip_update.trip;
+
+            if let Some(start_date) = &descriptor.start_date {
+                if NaiveDate::parse_from_str(start_date, "%Y%m%d").ok() != Some(self.date) {
+                    continue;
+                }
+            }
+
+            let trip_id = match &descriptor.trip_id {
+                Some(trip_id) => trip_id,
+                None => continue
+            };
+
+            let relationship = descriptor.schedule_relationship
+                .and_then(TripScheduleRelationship::from_i32)
+                .unwrap_or(TripScheduleRelationship::Scheduled);
+
+            let identifier = match self.trip_ids.get(trip_id) {
+                Some(&identifier) => identifier,
+                None if relationship == TripScheduleRelationship::Added => {
+                    let identifier = self.trip_ids.values().max().map_or(0, |max| max + 1);
+                    self.trip_ids.insert(trip_id.clone(), identifier);
+                    identifier
+                },
+                None => continue
+            };
+
+            let connections = match relationship {
+                TripScheduleRelationship::Canceled | TripScheduleRelationship::Deleted => vec![],
+                _ => match self.planned.get(&identifier) {
+                    Some(planned) => self.updated_connections(identifier, planned, trip_update),
+                    None => self.added_connections(identifier, trip_update)
+                }
+            };
+
+            if connections.is_empty() && !self.trips.contains_key(&identifier) {
+                continue;
+            }
+
+            updates.push(TripUpdate::from_new_trip(&mut self.trips, Trip { identifier, connections }));
+        }
+
+        updates
+    }
+
+    // Applies the stop time updates to the planned stops of a trip. Updates are matched on stop sequence when given and
+    // otherwise on stop id (in order of the trip), such that a trip calling at a stop twice gets the update of the right call.
+    // A delay propagates to later stops without an update of their own and skipped stops are removed from the trip.
+    fn updated_connections(&self, identifier: usize, planned: &[(usize, u32, u32)], trip_update: &rt::TripUpdate) -> Vec<Connection> {
+        let mut delay = trip_update.delay.unwrap_or(0) as i64;
+        let mut stops = vec![];
+
+        let sequences = self.stop_sequences.get(&identifier);
+        let mut pending = trip_update.stop_time_update.iter()
+            .filter_map(|update| match (update.stop_sequence, sequences) {
+                (Some(sequence), Some(sequences)) => sequences.iter().position(|&s| s == sequence).map(|i| (StopRef::Call(i), update)),
+                _ => Some((StopRef::Stop(*self.stops.get(update.stop_id.as_ref()?)?), update))
+            })
+            .peekable();
+
+        for (i, &(stop, arr_time, dep_time)) in planned.iter().enumerate() {
+            let update = match pending.peek() {
+                Some(&(StopRef::Call(call), update)) if call == i => Some(update),
+                Some(&(StopRef::Stop(update_stop), update)) if update_stop == stop => Some(update),
+                _ => None
+            };
+            if update.is_some() {
+                pending.next();
+            }
+
+            if let Some(update) = update {
+                match update.schedule_relationship.and_then(StopScheduleRelationship::from_i32) {
+                    Some(StopScheduleRelationship::Skipped) => continue,
+                    Some(StopScheduleRelationship::NoData) => delay = 0,
+                    _ => {
+                        if let Some(event) = &update.arrival {
+                            delay = event_time(event, arr_time) - arr_time as i64;
+                        }
+                    }
+                }
+            }
+
+            let arr = arr_time as i64 + delay;
+
+            if let Some(event) = update.and_then(|update| update.departure.as_ref()) {
+                delay = event_time(event, dep_time) - dep_time as i64;
+            }
+
+            let dep = (dep_time as i64 + delay).max(arr);
+            stops.push((stop, arr.max(0) as u32, dep.max(0) as u32));
+        }
+
+        stops_to_connections(identifier, &stops)
+    }
+
+    // Trips which are not in the static timetable only have absolute times
+    fn added_connections(&self, identifier: usize, trip_update: &rt::TripUpdate) -> Vec<Connection> {
+        let stops: Vec<(usize, u32, u32)> = trip_update.stop_time_update.iter()
+            .filter(|update| update.schedule_relationship.and_then(StopScheduleRelationship::from_i32) != Some(StopScheduleRelationship::Skipped))
+            .filter_map(|update| {
+                let arr_time = update.arrival.as_ref().and_then(|event| event.time);
+                let dep_time = update.departure.as_ref().and_then(|event| event.time);
+
+                Some((
+                    *self.stops.get(update.stop_id.as_ref()?)?,
+                    arr_time.or(dep_time)? as u32,
+                    dep_time.or(arr_time)? as u32
+                ))
+            })
+            .collect();
+
+        stops_to_connections(identifier, &stops)
+    }
+}
+
+// Stop of a trip a stop time update refers to, either the n-th planned stop or the first pending call at a stop
+enum StopRef {
+    Call(usize),
+    Stop(usize)
+}
+
+// Absolute time of a stop time event, based on the planned time when only a delay is given
+fn event_time(event: &rt::StopTimeEvent, planned: u32) -> i64 {
+    match (event.time, event.delay) {
+        (Some(time), _) => time,
+        (None, Some(delay)) => planned as i64 + delay as i64,
+        (None, None) => planned as i64
+    }
+}
+
+fn stops_to_connections(identifier: usize, stops: &[(usize, u32, u32)]) -> Vec<Connection> {
+    stops.windows(2).map(|w| Connection {
+        dep_stop: w[0].0,
+        arr_stop: w[1].0,
+        dep_time: w[0].2,
+        arr_time: w[1].1,
+        trip_id: identifier
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::gtfs::GTFSStop, types::Stop};
+
+    fn stop_time_update(stop_id: &str, delay: Option<i32>, skipped: bool) -> rt::StopTimeUpdate {
+        rt::StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            arrival: delay.map(|delay| rt::StopTimeEvent { delay: Some(delay), time: None, uncertainty: None }),
+            schedule_relationship: if skipped { Some(StopScheduleRelationship::Skipped as i32) } else { None },
+            ..Default::default()
+        }
+    }
+
+    fn timetable(connections: Vec<Connection>) -> Timetable {
+        Timetable {
+            stops: (0..4).map(|i| (i, Box::new(GTFSStop {
+                id: format!("s{}", i), name: None, lat: None, lon: None, parent_station: None
+            }) as Box<dyn Stop>)).collect(),
+            trips: vec![Trip { identifier: 0, connections }],
+            ..Default::default()
+        }
+    }
+
+    fn feed(trip_update: rt::TripUpdate) -> FeedMessage {
+        let feed = FeedMessage {
+            header: rt::FeedHeader { gtfs_realtime_version: "2.0".to_string(), ..Default::default() },
+            entity: vec![rt::FeedEntity {
+                id: "1".to_string(),
+                is_deleted: None,
+                trip_update: Some(trip_update)
+            }]
+        };
+
+        // Make sure the message survives encoding
+        let mut bytes = vec![];
+        feed.encode(&mut bytes).unwrap();
+        FeedMessage::decode(&bytes[..]).unwrap()
+    }
+
+    // Connections of trip 0 after applying the connection updates of a trip update
+    fn updated_trip(timetable: &Timetable, updates: &[TripUpdate]) -> Vec<Connection> {
+        let mut connections: BTreeSet<Connection> = timetable.trips[0].connections.iter().cloned().collect();
+        for update in updates {
+            match update {
+                TripUpdate::AddConnection { connection, .. } => { connections.insert(connection.clone()); },
+                TripUpdate::DeleteConnection { connection, .. } => { connections.remove(connection); },
+                other => panic!("Unexpected update: {:?}", other)
+            }
+        }
+
+        connections.into_iter().collect()
+    }
+
+    #[test]
+    fn delays_and_skipped_stops() {
+        let timetable = timetable(vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 100, arr_time: 200, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 2, dep_time: 300, arr_time: 400, trip_id: 0 },
+            Connection { dep_stop: 2, arr_stop: 3, dep_time: 500, arr_time: 600, trip_id: 0 },
+        ]);
+
+        let mut trip_ids = HashMap::new();
+        trip_ids.insert("t0".to_string(), 0);
+
+        let mut converter = TripUpdateConverter::new(&timetable, trip_ids, HashMap::new(), NaiveDate::from_ymd(2021, 1, 15));
+
+        // Delay of 60 seconds from stop 1 onwards, stop 2 is skipped
+        let updates = converter.convert(&feed(rt::TripUpdate {
+            trip: rt::TripDescriptor { trip_id: Some("t0".to_string()), start_date: Some("20210115".to_string()), ..Default::default() },
+            stop_time_update: vec![stop_time_update("s1", Some(60), false), stop_time_update("s2", None, true)],
+            ..Default::default()
+        }));
+        assert_eq!(updates.len(), 1);
+
+        assert_eq!(updated_trip(&timetable, &updates[0]), vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 100, arr_time: 260, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 3, dep_time: 360, arr_time: 660, trip_id: 0 },
+        ]);
+    }
+
+    #[test]
+    fn loop_route_stop_sequences() {
+        // A loop calling at stop 1 twice
+        let timetable = timetable(vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 100, arr_time: 200, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 2, dep_time: 300, arr_time: 400, trip_id: 0 },
+            Connection { dep_stop: 2, arr_stop: 1, dep_time: 500, arr_time: 600, trip_id: 0 },
+        ]);
+
+        let mut trip_ids = HashMap::new();
+        trip_ids.insert("t0".to_string(), 0);
+
+        let mut stop_sequences = HashMap::new();
+        stop_sequences.insert(0, vec![10, 20, 30, 40]);
+
+        let mut converter = TripUpdateConverter::new(&timetable, trip_ids, stop_sequences, NaiveDate::from_ymd(2021, 1, 15));
+
+        // Only the second call at stop 1 is delayed
+        let updates = converter.convert(&feed(rt::TripUpdate {
+            trip: rt::TripDescriptor { trip_id: Some("t0".to_string()), ..Default::default() },
+            stop_time_update: vec![rt::StopTimeUpdate { stop_sequence: Some(40), ..stop_time_update("s1", Some(60), false) }],
+            ..Default::default()
+        }));
+        assert_eq!(updates.len(), 1);
+
+        assert_eq!(updated_trip(&timetable, &updates[0]), vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 100, arr_time: 200, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 2, dep_time: 300, arr_time: 400, trip_id: 0 },
+            Connection { dep_stop: 2, arr_stop: 1, dep_time: 500, arr_time: 660, trip_id: 0 },
+        ]);
+    }
+
+    #[test]
+    fn added_trip() {
+        let timetable = timetable(vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 100, arr_time: 200, trip_id: 0 },
+        ]);
+
+        let mut trip_ids = HashMap::new();
+        trip_ids.insert("t0".to_string(), 0);
+
+        let mut converter = TripUpdateConverter::new(&timetable, trip_ids, HashMap::new(), NaiveDate::from_ymd(2021, 1, 15));
+
+        // An extra trip only has absolute times, its skipped stops are left out
+        let event = |time| Some(rt::StopTimeEvent { time: Some(time), delay: None, uncertainty: None });
+        let updates = converter.convert(&feed(rt::TripUpdate {
+            trip: rt::TripDescriptor {
+                trip_id: Some("extra".to_string()),
+                schedule_relationship: Some(TripScheduleRelationship::Added as i32),
+                ..Default::default()
+            },
+            stop_time_update: vec![
+                rt::StopTimeUpdate { departure: event(1000), ..stop_time_update("s2", None, false) },
+                rt::StopTimeUpdate { arrival: event(1100), departure: event(1200), ..stop_time_update("s1", None, true) },
+                rt::StopTimeUpdate { arrival: event(1300), ..stop_time_update("s3", None, false) },
+            ],
+            ..Default::default()
+        }));
+
+        assert_eq!(updates.len(), 1);
+        match &updates[0][..] {
+            [TripUpdate::AddTrip { trip }] => assert_eq!(trip, &Trip {
+                identifier: 1,
+                connections: vec![Connection { dep_stop: 2, arr_stop: 3, dep_time: 1000, arr_time: 1300, trip_id: 1 }]
+            }),
+            other => panic!("Unexpected updates: {:?}", other)
+        }
+    }
+}
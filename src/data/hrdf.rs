@@ -0,0 +1,316 @@
+use std::{cell::Cell, collections::HashMap, error::Error, fs::{self, File}, io::Read, iter::Peekable, path::Path};
+
+use chrono::NaiveDate;
+use encoding_rs::mem;
+use zip::{ZipArchive, result::ZipError};
+
+use super::railways_netherlands::{iff::{parse_services, service_time, services_to_trips, validity_from_bits}, iff_types::{Service, Stop as ServiceStop}};
+use crate::types::{Stop, Timetable, TripDetails, haversine_distance};
+
+/// Stop as defined in BAHNHOF, with WGS84 coordinates from BFKOORD_WGS
+#[derive(Debug)]
+pub struct HRDFStop {
+    pub id: String,
+    pub name: String,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>
+}
+
+impl Stop for HRDFStop {
+    fn to_string(&self) -> String {
+        self.id.clone()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn coords(&self) -> Option<(f64, f64)> {
+        Some((self.lat?, self.lon?))
+    }
+
+    fn distance(&self, other: &Box<dyn Stop>) -> Option<f64> {
+        Some(haversine_distance(self.coords()?, other.coords()?))
+    }
+}
+
+/// Reads a file from a HRDF export, which is either a zip file or an extracted directory. Older exports are Latin-1 encoded.
+fn read_file(path: &Path, file: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let mut bytes = vec![];
+
+    if path.is_dir() {
+        let file = path.join(file);
+        if !file.exists() {
+            return Ok(None);
+        }
+
+        bytes = fs::read(file)?;
+    } else {
+        let mut zip = ZipArchive::new(File::open(path)?)?;
+        match zip.by_name(file) {
+            Ok(mut file) => { file.read_to_end(&mut bytes)?; },
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(err.into())
+        };
+    }
+
+    Ok(Some(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(err) => mem::decode_latin1(err.as_bytes()).to_string()
+    }))
+}
+
+fn require_file(path: &Path, file: &str) -> Result<String, Box<dyn Error>> {
+    read_file(path, file)?.ok_or_else(|| format!("{} is missing from the HRDF export", file).into())
+}
+
+// Fixed width field of a line, which might be cut short when the rest of the line is empty. Columns are counted in
+// characters, as names can contain non-ASCII characters.
+fn field(line: &str, start: usize, end: usize) -> &str {
+    let byte = |column| line.char_indices().nth(column).map_or(line.len(), |(i, _)| i);
+    line[byte(start)..byte(end)].trim()
+}
+
+/// Parses a HRDF time ([-]HHHMM) to the HHMM format used by IFF, together with whether boarding or alighting is possible
+/// at that time, which a leading minus rules out
+fn parse_time(time: &str) -> Result<Option<(u16, bool)>, Box<dyn Error>> {
+    match time.trim_start_matches('-') {
+        "" => Ok(None),
+        digits => Ok(Some((digits.parse()?, !time.starts_with('-'))))
+    }
+}
+
+/// Parses a single service (*Z record and its stops) from FPLAN, where train number changes along the route become
+/// separate train numbers just like in IFF. Services without a validity (*A VE) run every day, which is validity 0.
+fn parse_service<'a>(lines: &mut Peekable<impl Iterator<Item=&'a str>>, identification: usize) -> Result<Service, Box<dyn Error>> {
+    let header = match lines.next() {
+        Some(line) if !line.trim().is_empty() => line,
+        _ => Err("End of file")?
+    };
+
+    if !header.starts_with("*Z") {
+        Err(format!("Expected a *Z record in FPLAN, got: {}", header))?
+    }
+
+    let mut validity = None;
+    let mut trns_modes = vec![];
+    let mut attributes = vec![];
+    let mut stop_lines = vec![];
+
+    while let Some(line) = lines.peek() {
+        if line.starts_with("*Z") || line.trim().is_empty() {
+            break;
+        }
+
+        let line = lines.next().unwrap();
+        if line.starts_with("*G") {
+            trns_modes.push(field(line, 3, 6).to_string());
+        } else if line.starts_with("*A") {
+            match field(line, 3, 5) {
+                "VE" => validity = Some(match field(line, 22, 28) { "" => 0, bitfield => bitfield.parse()? }),
+                attribute => attributes.push(attribute.to_string())
+            }
+        } else if !line.starts_with('*') {
+            stop_lines.push(line);
+        }
+    }
+
+    let mut stops = vec![];
+    let mut service_number: Vec<(usize, std::ops::Range<usize>)> = vec![(field(header, 3, 9).parse()?, 1..1)];
+    let mut halts = 0;
+
+    // Stops at which boarding or alighting is not possible are passed, as a trip cannot rule out only one of them
+    let mut times = vec![];
+    for line in &stop_lines {
+        let (arr_time, dep_time) = (parse_time(field(line, 29, 35))?, parse_time(field(line, 36, 42))?);
+        times.push(match (arr_time, dep_time) {
+            (Some((_, false)), _) | (_, Some((_, false))) => (None, None),
+            (arr_time, dep_time) => (arr_time.map(|(time, _)| time), dep_time.map(|(time, _)| time))
+        });
+    }
+
+    let first = times.iter().position(|&(arr_time, dep_time)| arr_time.or(dep_time).is_some()).ok_or("Service without times")?;
+    let last = times.iter().rposition(|&(arr_time, dep_time)| arr_time.or(dep_time).is_some()).unwrap();
+
+    for (i, (line, &(arr_time, dep_time))) in stop_lines.iter().zip(&times).enumerate() {
+        let station = field(line, 0, 7).to_string();
+
+        let stop = match (i, arr_time, dep_time) {
+            (i, arr_time, dep_time) if i == first => ServiceStop::Departure { dep_time: dep_time.or(arr_time).unwrap(), station, track: None },
+            (i, arr_time, dep_time) if i == last => ServiceStop::Arrival { arr_time: arr_time.or(dep_time).unwrap(), station, track: None },
+            (_, Some(arr_time), dep_time) => ServiceStop::Intermediate { arr_time, dep_time: dep_time.unwrap_or(arr_time), station, track: None },
+            (_, None, Some(dep_time)) => ServiceStop::ShortIntermediate { dep_time, station, track: None },
+            (_, None, None) => ServiceStop::Pass { station }
+        };
+
+        if let ServiceStop::Pass { .. } = stop {
+            stops.push(stop);
+            continue;
+        }
+
+        halts += 1;
+        stops.push(stop);
+
+        // A new train number starts at this stop, ranges are inclusive and refer to stops at which the service halts
+        match field(line, 43, 48) {
+            "" => {},
+            number if halts > 1 => {
+                service_number.last_mut().unwrap().1.end = halts;
+                service_number.push((number.parse()?, halts..halts));
+            },
+            _ => {}
+        }
+    }
+
+    service_number.last_mut().unwrap().1.end = halts;
+
+    Ok(Service {
+        identification,
+        company: field(header, 10, 16).parse().unwrap_or(0),
+        service_number,
+        validity: validity.unwrap_or(0),
+        trns_modes,
+        attributes,
+        stops
+    })
+}
+
+/// Builds the timetable of a single date from a HRDF export (zip file or directory), e.g. the Swiss timetable
+pub fn get_timetable_for_day(path: &Path, date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
+    // First and last day of the timetable period, lines can contain comments after the date
+    let eckdaten = require_file(path, "ECKDATEN")?;
+    let mut period = eckdaten.lines().filter(|line| !line.starts_with('%') && !line.trim().is_empty());
+    let from = NaiveDate::parse_from_str(field(period.next().ok_or("ECKDATEN is empty")?, 0, 10), "%d.%m.%Y")?;
+    let to = NaiveDate::parse_from_str(field(period.next().ok_or("ECKDATEN has no end date")?, 0, 10), "%d.%m.%Y")?;
+
+    // Bitfields are hexadecimal, with the first bit corresponding to the first day of the timetable period
+    let mut validity = HashMap::new();
+    validity.insert(0, validity_from_bits(from, (0..=(to - from).num_days()).map(|_| true)));
+
+    for line in read_file(path, "BITFELD")?.unwrap_or_default().lines().filter(|line| !line.trim().is_empty()) {
+        let bits = field(line, 7, line.len()).chars()
+            .map(|c| c.to_digit(16).ok_or("Invalid bitfield"))
+            .collect::<Result<Vec<u32>, _>>()?
+            .into_iter()
+            .flat_map(|digit| (0..4).rev().map(move |bit| digit & (1 << bit) != 0));
+
+        validity.insert(field(line, 0, 6).parse::<usize>()?, validity_from_bits(from, bits));
+    }
+
+    let mut stops: Vec<HRDFStop> = require_file(path, "BAHNHOF")?.lines()
+        .filter(|line| !line.starts_with('%') && !line.trim().is_empty())
+        .map(|line| HRDFStop {
+            id: field(line, 0, 7).to_string(),
+            name: field(line, 12, line.len()).split('$').next().unwrap_or("").to_string(),
+            lat: None,
+            lon: None
+        })
+        .collect();
+
+    let coordinates: HashMap<String, (f64, f64)> = read_file(path, "BFKOORD_WGS")?.unwrap_or_default().lines()
+        .filter_map(|line| {
+            let mut values = line.split('%').next()?.split_whitespace();
+            let id = values.next()?.to_string();
+            let lon = values.next()?.parse().ok()?;
+            let lat = values.next()?.parse().ok()?;
+            Some((id, (lat, lon)))
+        })
+        .collect();
+
+    for stop in &mut stops {
+        if let Some(&(lat, lon)) = coordinates.get(&stop.id) {
+            stop.lat = Some(lat);
+            stop.lon = Some(lon);
+        }
+    }
+
+    let stops_lookup: HashMap<&str, usize> = stops.iter().enumerate().map(|(i, stop)| (stop.id.as_str(), i)).collect();
+
+    let fplan = require_file(path, "FPLAN")?;
+    let identification = Cell::new(0);
+    let services = parse_services(&mut fplan.lines().filter(|line| !line.starts_with('%')).peekable(), |lines| {
+        identification.set(identification.get() + 1);
+        parse_service(lines, identification.get())
+    })?;
+
+    // Train numbers of different operators overlap, so trips are numbered in order and the train number is kept as a detail
+    let mut details = vec![];
+    let (trips, continuations) = services_to_trips(&services, &validity, &stops_lookup, date, |_, time| service_time(&chrono_tz::Europe::Zurich, date, time, 0), |service, number| {
+        details.push(TripDetails {
+            mode: service.trns_modes.first().cloned(),
+            number: Some(number),
+            ..TripDetails::default()
+        });
+        details.len() - 1
+    });
+
+    // Now we create a 'loopback' footpath for each station
+    let footpaths = (0..stops.len()).map(|stop| (stop, vec![(stop, 0)])).collect();
+
+    Ok(Timetable {
+        trips,
+        stops: stops.into_iter().enumerate().map(|(i, stop)| (i, Box::new(stop) as Box<dyn Stop>)).collect(),
+        footpaths,
+        continuations,
+        sections: HashMap::new(),
+        transfers: HashMap::new(),
+        details: details.into_iter().enumerate().collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timetable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+
+        fs::write(path.join("ECKDATEN"), "13.12.2020\n11.12.2021\nFahrplan 2021\n").unwrap();
+        fs::write(path.join("BITFELD"), "000001 C0\n").unwrap();
+        fs::write(path.join("BAHNHOF"), "8500010     Basel SBB$<1>\n8500023     Liestal$<1>\n8503000     Zürich HB$<1>\n8506000     Winterthur$<1>\n").unwrap();
+        fs::write(path.join("BFKOORD_WGS"), "8500010    7.589563   47.547412      277 % Basel SBB\n").unwrap();
+
+        // The IC 1 runs on the first two days from Basel to Liestal, continuing as IC 2 to Winterthur, just like the S 1 of another
+        // operator. Passengers cannot alight in Zürich.
+        fs::write(path.join("FPLAN"), [
+            "*Z 000001 000011   101                                     % ",
+            "*G IC  8500010 8506000                                     % ",
+            "*A VE 8500010 8506000 000001                               % ",
+            "8500010 Basel SBB                    00641                  % ",
+            "8500023 Liestal               00651  00652 00002            % ",
+            "8503000 Zürich HB            -00744  00746                  % ",
+            "8506000 Winterthur            00803                         % ",
+            "*Z 000001 000085                                           % ",
+            "*G S   8500010 8500023                                     % ",
+            "*A VE 8500010 8500023 000001                               % ",
+            "8500010 Basel SBB                    00715                  % ",
+            "8500023 Liestal               00727                         % ",
+        ].join("\n")).unwrap();
+
+        let timetable = get_timetable_for_day(path, &NaiveDate::from_ymd(2020, 12, 14)).unwrap();
+        assert_eq!(timetable.stops.len(), 4);
+        assert_eq!(timetable.trips.len(), 3);
+
+        // The S 1 of another operator shares its train number with the IC 1, but is a different trip
+        let trip = |name: &str| timetable.trips.iter().find(|trip| timetable.details[&trip.identifier].to_string() == name).unwrap();
+        assert_ne!(trip("IC 1").identifier, trip("S 1").identifier);
+        assert_eq!(timetable.continuations.get(&trip("IC 1").identifier), Some(&trip("IC 2").identifier));
+        assert_eq!(timetable.continuations.len(), 1);
+
+        let basel = timetable.stops.values().find(|stop| stop.to_string() == "8500010").unwrap();
+        assert_eq!(basel.name(), "Basel SBB");
+        assert_eq!(basel.coords(), Some((47.547412, 7.589563)));
+
+        // The IC 2 passes Zürich
+        let ic2 = trip("IC 2");
+        let zurich = *timetable.stops.iter().find(|(_, stop)| stop.to_string() == "8503000").unwrap().0;
+        assert_eq!(ic2.connections.len(), 1);
+        assert_eq!(ic2.connections[0].arr_time - ic2.connections[0].dep_time, 71 * 60);
+        assert!(timetable.trips.iter().flat_map(|trip| &trip.connections).all(|conn| conn.dep_stop != zurich && conn.arr_stop != zurich));
+
+        let third_day = get_timetable_for_day(path, &NaiveDate::from_ymd(2020, 12, 15)).unwrap();
+        assert!(third_day.trips.is_empty());
+    }
+}
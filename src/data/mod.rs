@@ -1,3 +1,8 @@
 mod zeromq;
+mod gtfs_realtime_types;
 pub mod railways_netherlands;
-pub mod generic_data;
\ No newline at end of file
+pub mod generic_data;
+pub mod gtfs;
+pub mod gtfs_realtime;
+pub mod netex;
+pub mod hrdf;
\ No newline at end of file
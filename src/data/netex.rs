@@ -0,0 +1,696 @@
+use std::{collections::{HashMap, HashSet}, error::Error, fs, path::Path};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use quick_xml::{Reader, de::from_str, events::Event};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::types::{Connection, Stop, Timetable, Trip, haversine_distance, local_timestamp};
+
+/// Transfer time in seconds between quays of the same stop place, NeTEx feeds rarely contain connection times
+pub const QUAY_TRANSFER_TIME: u32 = 120;
+
+#[derive(Debug, Deserialize)]
+struct Ref {
+    #[serde(rename = "ref")]
+    id: String
+}
+
+#[derive(Debug, Deserialize)]
+struct DefaultLocale {
+    #[serde(rename = "TimeZone")]
+    timezone: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct FrameDefaultsElement {
+    #[serde(rename = "DefaultLocale")]
+    default_locale: Option<DefaultLocale>
+}
+
+#[derive(Debug, Deserialize)]
+struct Location {
+    #[serde(rename = "Longitude")]
+    longitude: f64,
+
+    #[serde(rename = "Latitude")]
+    latitude: f64
+}
+
+#[derive(Debug, Deserialize)]
+struct Centroid {
+    #[serde(rename = "Location")]
+    location: Location
+}
+
+#[derive(Debug, Deserialize)]
+struct QuayElement {
+    id: String,
+
+    #[serde(rename = "Name")]
+    name: Option<String>,
+
+    #[serde(rename = "Centroid")]
+    centroid: Option<Centroid>
+}
+
+#[derive(Debug, Deserialize)]
+struct Quays {
+    #[serde(rename = "Quay", default)]
+    quays: Vec<QuayElement>
+}
+
+#[derive(Debug, Deserialize)]
+struct StopPlaceElement {
+    id: String,
+
+    #[serde(rename = "Name")]
+    name: Option<String>,
+
+    #[serde(rename = "Centroid")]
+    centroid: Option<Centroid>,
+
+    // Multimodal stop place this stop place is part of
+    #[serde(rename = "ParentSiteRef")]
+    parent: Option<Ref>,
+
+    quays: Option<Quays>
+}
+
+#[derive(Debug, Deserialize)]
+struct StopPlaceRefs {
+    #[serde(rename = "StopPlaceRef", default)]
+    stop_places: Vec<Ref>
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupOfStopPlacesElement {
+    id: String,
+    members: Option<StopPlaceRefs>
+}
+
+#[derive(Debug, Deserialize)]
+struct PassengerStopAssignmentElement {
+    #[serde(rename = "ScheduledStopPointRef")]
+    scheduled_stop_point: Ref,
+
+    #[serde(rename = "StopPlaceRef")]
+    stop_place: Option<Ref>,
+
+    #[serde(rename = "QuayRef")]
+    quay: Option<Ref>
+}
+
+#[derive(Debug, Deserialize)]
+struct StopPointInJourneyPatternElement {
+    id: String,
+
+    #[serde(rename = "ScheduledStopPointRef")]
+    scheduled_stop_point: Ref
+}
+
+#[derive(Debug, Deserialize)]
+struct PointsInSequence {
+    #[serde(rename = "StopPointInJourneyPattern", default)]
+    points: Vec<StopPointInJourneyPatternElement>
+}
+
+#[derive(Debug, Deserialize)]
+struct JourneyPatternElement {
+    id: String,
+
+    #[serde(rename = "pointsInSequence")]
+    points_in_sequence: PointsInSequence
+}
+
+#[derive(Debug, Deserialize)]
+struct DayTypeRefs {
+    #[serde(rename = "DayTypeRef", default)]
+    day_types: Vec<Ref>
+}
+
+#[derive(Debug, Deserialize)]
+struct TimetabledPassingTimeElement {
+    #[serde(rename = "StopPointInJourneyPatternRef")]
+    stop_point: Ref,
+
+    #[serde(rename = "ArrivalTime")]
+    arr_time: Option<String>,
+
+    #[serde(rename = "ArrivalDayOffset")]
+    arr_day_offset: Option<u32>,
+
+    #[serde(rename = "DepartureTime")]
+    dep_time: Option<String>,
+
+    #[serde(rename = "DepartureDayOffset")]
+    dep_day_offset: Option<u32>
+}
+
+#[derive(Debug, Deserialize)]
+struct PassingTimes {
+    #[serde(rename = "TimetabledPassingTime", default)]
+    passing_times: Vec<TimetabledPassingTimeElement>
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceJourneyElement {
+    id: String,
+
+    #[serde(rename = "dayTypes")]
+    day_types: Option<DayTypeRefs>,
+
+    // The Nordic profile uses a ServiceJourneyPatternRef, EPIP a JourneyPatternRef
+    #[serde(rename = "ServiceJourneyPatternRef")]
+    service_journey_pattern: Option<Ref>,
+
+    #[serde(rename = "JourneyPatternRef")]
+    journey_pattern: Option<Ref>,
+
+    #[serde(rename = "passingTimes")]
+    passing_times: PassingTimes
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertyOfDay {
+    #[serde(rename = "DaysOfWeek")]
+    days_of_week: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertiesOfDay {
+    #[serde(rename = "PropertyOfDay", default)]
+    properties: Vec<PropertyOfDay>
+}
+
+#[derive(Debug, Deserialize)]
+struct DayTypeElement {
+    id: String,
+    properties: Option<PropertiesOfDay>
+}
+
+#[derive(Debug, Deserialize)]
+struct DayTypeAssignmentElement {
+    #[serde(rename = "DayTypeRef")]
+    day_type: Ref,
+
+    #[serde(rename = "OperatingPeriodRef")]
+    operating_period: Option<Ref>,
+
+    #[serde(rename = "Date")]
+    date: Option<String>,
+
+    #[serde(rename = "isAvailable")]
+    is_available: Option<bool>
+}
+
+#[derive(Debug, Deserialize)]
+struct OperatingPeriodElement {
+    id: String,
+
+    #[serde(rename = "FromDate")]
+    from_date: String,
+
+    #[serde(rename = "ToDate")]
+    to_date: String
+}
+
+/// Kind of stop in the NeTEx stop hierarchy, groups of stop places only group stops and are never stopped at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeTExStopKind {
+    Quay,
+    StopPlace
+}
+
+/// Quay or stop place from a NeTEx site frame, including its place in the hierarchy
+#[derive(Debug, Clone)]
+pub struct NeTExStop {
+    pub id: String,
+    pub kind: NeTExStopKind,
+    pub name: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+
+    /// Stop place of a quay, or the multimodal stop place of a stop place
+    pub parent: Option<String>,
+
+    /// Groups of stop places the stop (or its stop place) is part of
+    pub groups: Vec<String>
+}
+
+impl Stop for NeTExStop {
+    fn to_string(&self) -> String {
+        self.id.clone()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.id.clone())
+    }
+
+    fn coords(&self) -> Option<(f64, f64)> {
+        Some((self.lat?, self.lon?))
+    }
+
+    fn distance(&self, other: &Box<dyn Stop>) -> Option<f64> {
+        Some(haversine_distance(self.coords()?, other.coords()?))
+    }
+}
+
+/// All elements with one of the given names (ignoring namespace prefixes) in a document, regardless of the frame they are in.
+/// Elements are not searched for further elements, such that e.g. quays are only found as part of their stop place.
+fn find_elements<'a>(xml: &'a str, names: &[&str]) -> Result<Vec<(String, &'a str)>, Box<dyn Error>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut elements = vec![];
+    let mut buf = vec![];
+    loop {
+        let start = reader.buffer_position();
+        match reader.read_event(&mut buf)? {
+            Event::Start(element) => {
+                let local_name = String::from_utf8(element.local_name().to_vec())?;
+                if names.contains(&local_name.as_str()) {
+                    let name = element.name().to_vec();
+                    reader.read_to_end(&name, &mut vec![])?;
+
+                    // The position before the start tag can include whitespace that was trimmed
+                    let start = start + xml[start..].find('<').ok_or("Start of element not found")?;
+                    elements.push((local_name, &xml[start..reader.buffer_position()]));
+                }
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(elements)
+}
+
+fn parse<T: DeserializeOwned>(element: &str) -> Result<T, Box<dyn Error>> {
+    Ok(from_str(element)?)
+}
+
+/// Parses a NeTEx time (HH:MM:SS) to seconds since the start of the service day
+fn parse_time(time: &str, day_offset: Option<u32>) -> Option<u32> {
+    let mut split = time.split(':').map(|t| t.parse::<u32>());
+    Some(split.next()?.ok()? * 3600 + split.next()?.ok()? * 60 + split.next()?.ok()? + day_offset.unwrap_or(0) * 86400)
+}
+
+// Dates can be given with or without a time
+fn parse_date(date: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    Ok(NaiveDate::parse_from_str(date.get(0..10).ok_or("Invalid date")?, "%Y-%m-%d")?)
+}
+
+fn runs_on_weekday(days_of_week: &str, weekday: Weekday) -> bool {
+    days_of_week.split_whitespace().any(|day| match day {
+        "Everyday" => true,
+        "Weekdays" => weekday.number_from_monday() <= 5,
+        "Weekend" => weekday.number_from_monday() > 5,
+        day => day.get(0..3) == Some(&format!("{:?}", weekday)[..])
+    })
+}
+
+/// Reads all NeTEx documents of a feed, which is either a single XML file or a directory of XML files
+pub fn read_documents(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    if !path.is_dir() {
+        return Ok(vec![fs::read_to_string(path)?]);
+    }
+
+    let mut files = fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    files.retain(|file| file.extension().map_or(false, |extension| extension == "xml"));
+    files.sort();
+
+    files.into_iter().map(|file| Ok(fs::read_to_string(file)?)).collect()
+}
+
+/// Builds the timetable of a single date from NeTEx documents. Every quay and stop place becomes a stop, with footpaths
+/// between the quays of a stop place and between the quays and the stop place itself. Every service journey running on the date becomes a trip.
+pub fn timetable_from_documents(documents: &[String], date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
+    let mut stop_places = vec![];
+    let mut groups = vec![];
+    let mut assignments = HashMap::new();
+    let mut journey_patterns = HashMap::new();
+    let mut journeys = vec![];
+    let mut day_types = HashMap::new();
+    let mut day_type_assignments = vec![];
+    let mut operating_periods = HashMap::new();
+    let mut timezone = None;
+
+    let names = [
+        "FrameDefaults", "StopPlace", "GroupOfStopPlaces", "PassengerStopAssignment", "ServiceJourneyPattern", "JourneyPattern",
+        "ServiceJourney", "DayType", "DayTypeAssignment", "OperatingPeriod"
+    ];
+
+    for document in documents {
+        for (name, element) in find_elements(document, &names)? {
+            match name.as_str() {
+                "FrameDefaults" => {
+                    let defaults: FrameDefaultsElement = parse(element)?;
+                    timezone = timezone.or(defaults.default_locale.and_then(|locale| locale.timezone));
+                },
+                "StopPlace" => stop_places.push(parse::<StopPlaceElement>(element)?),
+                "GroupOfStopPlaces" => groups.push(parse::<GroupOfStopPlacesElement>(element)?),
+                "PassengerStopAssignment" => {
+                    let assignment: PassengerStopAssignmentElement = parse(element)?;
+                    if let Some(stop) = assignment.quay.or(assignment.stop_place) {
+                        assignments.insert(assignment.scheduled_stop_point.id, stop.id);
+                    }
+                },
+                "ServiceJourneyPattern" | "JourneyPattern" => {
+                    let pattern: JourneyPatternElement = parse(element)?;
+                    journey_patterns.insert(pattern.id, pattern.points_in_sequence.points);
+                },
+                "ServiceJourney" => journeys.push(parse::<ServiceJourneyElement>(element)?),
+                "DayType" => {
+                    let day_type: DayTypeElement = parse(element)?;
+                    day_types.insert(day_type.id, day_type.properties.map_or(vec![], |properties| properties.properties));
+                },
+                "DayTypeAssignment" => day_type_assignments.push(parse::<DayTypeAssignmentElement>(element)?),
+                "OperatingPeriod" => {
+                    let period: OperatingPeriodElement = parse(element)?;
+                    operating_periods.insert(period.id, (parse_date(&period.from_date)?, parse_date(&period.to_date)?));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // Groups refer to stop places, which are inherited by their quays
+    let mut stop_place_groups: HashMap<&String, Vec<String>> = HashMap::new();
+    for group in &groups {
+        for member in group.members.iter().flat_map(|members| &members.stop_places) {
+            stop_place_groups.entry(&member.id).or_insert_with(Vec::new).push(group.id.clone());
+        }
+    }
+
+    let mut stops = vec![];
+    for stop_place in &stop_places {
+        let mut place_groups = stop_place_groups.get(&stop_place.id).cloned().unwrap_or_default();
+        if let Some(parent) = &stop_place.parent {
+            place_groups.extend(stop_place_groups.get(&parent.id).cloned().unwrap_or_default());
+        }
+
+        let location = stop_place.centroid.as_ref().map(|centroid| &centroid.location);
+        stops.push(NeTExStop {
+            id: stop_place.id.clone(),
+            kind: NeTExStopKind::StopPlace,
+            name: stop_place.name.clone(),
+            lat: location.map(|location| location.latitude),
+            lon: location.map(|location| location.longitude),
+            parent: stop_place.parent.as_ref().map(|parent| parent.id.clone()),
+            groups: place_groups.clone()
+        });
+
+        for quay in stop_place.quays.iter().flat_map(|quays| &quays.quays) {
+            let location = quay.centroid.as_ref().map(|centroid| &centroid.location).or(location);
+            stops.push(NeTExStop {
+                id: quay.id.clone(),
+                kind: NeTExStopKind::Quay,
+                name: quay.name.clone().or_else(|| stop_place.name.clone()),
+                lat: location.map(|location| location.latitude),
+                lon: location.map(|location| location.longitude),
+                parent: Some(stop_place.id.clone()),
+                groups: place_groups.clone()
+            });
+        }
+    }
+
+    let stops_lookup: HashMap<String, usize> = stops.iter().enumerate().map(|(i, stop)| (stop.id.clone(), i)).collect();
+
+    // Day types running on the date, a date assignment can also make a day type unavailable
+    let mut active_day_types = HashSet::new();
+    let mut unavailable_day_types = HashSet::new();
+    for assignment in &day_type_assignments {
+        let applies = match (&assignment.date, &assignment.operating_period) {
+            (Some(assigned), _) => &parse_date(assigned)? == date,
+            (None, Some(period)) => {
+                let &(from, to) = operating_periods.get(&period.id).ok_or("Unknown operating period")?;
+                let properties = day_types.get(&assignment.day_type.id).ok_or("Unknown day type")?;
+
+                // A day type applies on the days of any of its properties, without properties it applies every day
+                from <= *date && *date <= to && (properties.is_empty() || properties.iter().any(|property: &PropertyOfDay| {
+                    property.days_of_week.as_deref().map_or(true, |days_of_week| runs_on_weekday(days_of_week, date.weekday()))
+                }))
+            },
+            (None, None) => false
+        };
+
+        if applies {
+            match assignment.is_available {
+                Some(false) => unavailable_day_types.insert(&assignment.day_type.id),
+                _ => active_day_types.insert(&assignment.day_type.id)
+            };
+        }
+    }
+
+    // Passing times are local times in the timezone of the frame defaults
+    let timezone: Tz = timezone.ok_or("Missing timezone in the frame defaults")?.parse()?;
+
+    let mut trips = vec![];
+    for (identifier, journey) in journeys.iter().enumerate() {
+        let runs = journey.day_types.iter().flat_map(|day_types| &day_types.day_types)
+            .any(|day_type| active_day_types.contains(&day_type.id) && !unavailable_day_types.contains(&day_type.id));
+
+        if !runs {
+            continue;
+        }
+
+        let pattern = journey.service_journey_pattern.as_ref().or_else(|| journey.journey_pattern.as_ref())
+            .and_then(|pattern| journey_patterns.get(&pattern.id))
+            .ok_or(format!("Journey pattern of {} not found", journey.id))?;
+
+        let points: HashMap<&String, &String> = pattern.iter().map(|point| (&point.id, &point.scheduled_stop_point.id)).collect();
+
+        let timed: Vec<(usize, u32, u32)> = journey.passing_times.passing_times.iter().filter_map(|passing_time| {
+            let arr_time = passing_time.arr_time.as_deref().and_then(|time| parse_time(time, passing_time.arr_day_offset));
+            let dep_time = passing_time.dep_time.as_deref().and_then(|time| parse_time(time, passing_time.dep_day_offset));
+            let stop = assignments.get(*points.get(&passing_time.stop_point.id)?)?;
+
+            Some((*stops_lookup.get(stop)?, arr_time.or(dep_time)?, dep_time.or(arr_time)?))
+        }).collect();
+
+        let connections: Vec<Connection> = timed.windows(2).map(|w| Connection {
+            dep_stop: w[0].0,
+            arr_stop: w[1].0,
+            dep_time: local_timestamp(&timezone, date, w[0].2),
+            arr_time: local_timestamp(&timezone, date, w[1].1),
+            trip_id: identifier
+        }).collect();
+
+        if !connections.is_empty() {
+            trips.push(Trip {
+                identifier,
+                connections
+            });
+        }
+    }
+
+    // Besides the 'loopback' footpath every quay can be reached from the other quays of its stop place, and from the stop
+    // place itself as journeys can also be assigned to the stop place
+    let mut stop_place_quays: HashMap<&Option<String>, Vec<usize>> = HashMap::new();
+    for (i, stop) in stops.iter().enumerate().filter(|(_, stop)| stop.kind == NeTExStopKind::Quay) {
+        stop_place_quays.entry(&stop.parent).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut footpaths: HashMap<usize, Vec<(usize, u32)>> = (0..stops.len()).map(|stop| (stop, vec![(stop, 0)])).collect();
+    for quays in stop_place_quays.values() {
+        for &i in quays {
+            footpaths.get_mut(&i).unwrap().extend(quays.iter().filter(|&&j| j != i).map(|&j| (j, QUAY_TRANSFER_TIME)));
+        }
+    }
+
+    for (i, stop) in stops.iter().enumerate().filter(|(_, stop)| stop.kind == NeTExStopKind::Quay) {
+        if let Some(&parent) = stop.parent.as_ref().and_then(|parent| stops_lookup.get(parent)) {
+            footpaths.get_mut(&i).unwrap().push((parent, QUAY_TRANSFER_TIME));
+            footpaths.get_mut(&parent).unwrap().push((i, QUAY_TRANSFER_TIME));
+        }
+    }
+
+    Ok(Timetable {
+        trips,
+        stops: stops.into_iter().enumerate().map(|(i, stop)| (i, Box::new(stop) as Box<dyn Stop>)).collect(),
+        footpaths,
+        continuations: HashMap::new(),
+        sections: HashMap::new(),
+        transfers: HashMap::new(),
+        details: HashMap::new()
+    })
+}
+
+/// Builds the timetable of a single date from a NeTEx feed (XML file or directory)
+pub fn get_timetable_for_day(path: &Path, date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
+    timetable_from_documents(&read_documents(path)?, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<PublicationDelivery xmlns="http://www.netex.org.uk/netex" version="1.0">
+  <dataObjects>
+    <CompositeFrame id="NSR:CompositeFrame:1" version="1">
+      <FrameDefaults>
+        <DefaultLocale>
+          <TimeZone>Europe/Oslo</TimeZone>
+        </DefaultLocale>
+      </FrameDefaults>
+      <frames>
+        <SiteFrame id="NSR:SiteFrame:1" version="1">
+          <groupsOfStopPlaces>
+            <GroupOfStopPlaces id="NSR:GroupOfStopPlaces:1" version="1">
+              <Name>Oslo</Name>
+              <members>
+                <StopPlaceRef ref="NSR:StopPlace:1"/>
+              </members>
+            </GroupOfStopPlaces>
+          </groupsOfStopPlaces>
+          <stopPlaces>
+            <StopPlace id="NSR:StopPlace:1" version="1">
+              <Name>Oslo S</Name>
+              <Centroid><Location><Longitude>10.75</Longitude><Latitude>59.91</Latitude></Location></Centroid>
+              <quays>
+                <Quay id="NSR:Quay:1" version="1"><PublicCode>1</PublicCode></Quay>
+                <Quay id="NSR:Quay:2" version="1"><PublicCode>2</PublicCode></Quay>
+              </quays>
+            </StopPlace>
+            <StopPlace id="NSR:StopPlace:2" version="1">
+              <Name>Lillestrøm</Name>
+              <Centroid><Location><Longitude>11.05</Longitude><Latitude>59.95</Latitude></Location></Centroid>
+              <quays>
+                <Quay id="NSR:Quay:3" version="1"/>
+              </quays>
+            </StopPlace>
+          </stopPlaces>
+        </SiteFrame>
+        <ServiceCalendarFrame id="VY:ServiceCalendarFrame:1" version="1">
+          <dayTypes>
+            <DayType id="VY:DayType:weekdays" version="1">
+              <properties>
+                <PropertyOfDay><DaysOfWeek>Monday Tuesday Wednesday Thursday Friday</DaysOfWeek></PropertyOfDay>
+              </properties>
+            </DayType>
+          </dayTypes>
+          <operatingPeriods>
+            <OperatingPeriod id="VY:OperatingPeriod:1" version="1">
+              <FromDate>2021-01-01T00:00:00</FromDate>
+              <ToDate>2021-12-31T00:00:00</ToDate>
+            </OperatingPeriod>
+          </operatingPeriods>
+          <dayTypeAssignments>
+            <DayTypeAssignment id="VY:DayTypeAssignment:1" version="1" order="1">
+              <OperatingPeriodRef ref="VY:OperatingPeriod:1"/>
+              <DayTypeRef ref="VY:DayType:weekdays"/>
+            </DayTypeAssignment>
+            <DayTypeAssignment id="VY:DayTypeAssignment:2" version="1" order="2">
+              <Date>2021-01-18</Date>
+              <DayTypeRef ref="VY:DayType:weekdays"/>
+              <isAvailable>false</isAvailable>
+            </DayTypeAssignment>
+          </dayTypeAssignments>
+        </ServiceCalendarFrame>
+        <ServiceFrame id="VY:ServiceFrame:1" version="1">
+          <stopAssignments>
+            <PassengerStopAssignment id="VY:PassengerStopAssignment:1" version="1" order="1">
+              <ScheduledStopPointRef ref="VY:ScheduledStopPoint:1"/>
+              <QuayRef ref="NSR:Quay:1"/>
+            </PassengerStopAssignment>
+            <PassengerStopAssignment id="VY:PassengerStopAssignment:2" version="1" order="2">
+              <ScheduledStopPointRef ref="VY:ScheduledStopPoint:2"/>
+              <QuayRef ref="NSR:Quay:3"/>
+            </PassengerStopAssignment>
+          </stopAssignments>
+          <journeyPatterns>
+            <ServiceJourneyPattern id="VY:ServiceJourneyPattern:1" version="1">
+              <pointsInSequence>
+                <StopPointInJourneyPattern id="VY:StopPointInJourneyPattern:1" version="1" order="1">
+                  <ScheduledStopPointRef ref="VY:ScheduledStopPoint:1"/>
+                </StopPointInJourneyPattern>
+                <StopPointInJourneyPattern id="VY:StopPointInJourneyPattern:2" version="1" order="2">
+                  <ScheduledStopPointRef ref="VY:ScheduledStopPoint:2"/>
+                </StopPointInJourneyPattern>
+              </pointsInSequence>
+            </ServiceJourneyPattern>
+          </journeyPatterns>
+        </ServiceFrame>
+        <TimetableFrame id="VY:TimetableFrame:1" version="1">
+          <vehicleJourneys>
+            <ServiceJourney id="VY:ServiceJourney:1" version="1">
+              <dayTypes><DayTypeRef ref="VY:DayType:weekdays"/></dayTypes>
+              <ServiceJourneyPatternRef ref="VY:ServiceJourneyPattern:1"/>
+              <passingTimes>
+                <TimetabledPassingTime version="1">
+                  <StopPointInJourneyPatternRef ref="VY:StopPointInJourneyPattern:1"/>
+                  <DepartureTime>23:50:00</DepartureTime>
+                </TimetabledPassingTime>
+                <TimetabledPassingTime version="1">
+                  <StopPointInJourneyPatternRef ref="VY:StopPointInJourneyPattern:2"/>
+                  <ArrivalTime>00:05:00</ArrivalTime>
+                  <ArrivalDayOffset>1</ArrivalDayOffset>
+                </TimetabledPassingTime>
+              </passingTimes>
+            </ServiceJourney>
+          </vehicleJourneys>
+        </TimetableFrame>
+      </frames>
+    </CompositeFrame>
+  </dataObjects>
+</PublicationDelivery>"#;
+
+    #[test]
+    fn stop_hierarchy() {
+        let timetable = timetable_from_documents(&[DOCUMENT.to_string()], &NaiveDate::from_ymd(2021, 1, 15)).unwrap();
+        assert_eq!(timetable.stops.len(), 5);
+
+        let quay = timetable.stops.values().find(|stop| stop.to_string() == "NSR:Quay:2").unwrap();
+        assert_eq!(quay.name(), "Oslo S");
+        assert_eq!(quay.coords(), Some((59.91, 10.75)));
+
+        // Quays of Oslo S are connected to each other and to Oslo S itself
+        let stop_id = |id: &str| *timetable.stops.iter().find(|(_, stop)| stop.to_string() == id).unwrap().0;
+        let quay_id = stop_id("NSR:Quay:1");
+        assert_eq!(timetable.footpaths[&quay_id].len(), 3);
+        assert!(timetable.footpaths[&quay_id].contains(&(stop_id("NSR:StopPlace:1"), QUAY_TRANSFER_TIME)));
+        assert!(timetable.footpaths[&stop_id("NSR:StopPlace:1")].contains(&(quay_id, QUAY_TRANSFER_TIME)));
+    }
+
+    #[test]
+    fn journeys_on_date() {
+        let friday = timetable_from_documents(&[DOCUMENT.to_string()], &NaiveDate::from_ymd(2021, 1, 15)).unwrap();
+        assert_eq!(friday.trips.len(), 1);
+
+        // The journey runs past midnight, times are in the timezone of Oslo (UTC+1 in January)
+        let connection = &friday.trips[0].connections[0];
+        assert_eq!(connection.dep_time, chrono::Utc.ymd(2021, 1, 15).and_hms(22, 50, 0).timestamp() as u32);
+        assert_eq!(connection.arr_time - connection.dep_time, 15 * 60);
+
+        let saturday = timetable_from_documents(&[DOCUMENT.to_string()], &NaiveDate::from_ymd(2021, 1, 16)).unwrap();
+        assert!(saturday.trips.is_empty());
+
+        let monday = timetable_from_documents(&[DOCUMENT.to_string()], &NaiveDate::from_ymd(2021, 1, 18)).unwrap();
+        assert!(monday.trips.is_empty());
+    }
+
+    #[test]
+    fn day_type_properties() {
+        let runs = |properties: &str, date| {
+            let document = DOCUMENT.replace("<PropertyOfDay><DaysOfWeek>Monday Tuesday Wednesday Thursday Friday</DaysOfWeek></PropertyOfDay>", properties);
+            !timetable_from_documents(&[document], &date).unwrap().trips.is_empty()
+        };
+
+        // The journey runs on the days of either property
+        let weekend = "<PropertyOfDay><DaysOfWeek>Saturday</DaysOfWeek></PropertyOfDay><PropertyOfDay><DaysOfWeek>Sunday</DaysOfWeek></PropertyOfDay>";
+        assert!(!runs(weekend, NaiveDate::from_ymd(2021, 1, 15)));
+        assert!(runs(weekend, NaiveDate::from_ymd(2021, 1, 16)));
+        assert!(runs(weekend, NaiveDate::from_ymd(2021, 1, 17)));
+
+        // Without properties it runs every day
+        assert!(runs("", NaiveDate::from_ymd(2021, 1, 16)));
+    }
+}
@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize};
 use serde::de::{self, Deserializer, Unexpected};
 use serde_repr::{Serialize_repr, Deserialize_repr};
 use chrono::{DateTime, Local, NaiveDate};
 
+use crate::types::TrainSection;
+
 #[derive(Deserialize, Debug)]
 pub struct DVSMessage {
     #[serde(rename = "ReisInformatieProductDVS")]
@@ -96,14 +100,36 @@ pub struct DVSTrainSection {
     pub changes: Vec<DVSChange>,
 }
 
+impl DVSTrainSection {
+    /// Converts the section to the stops it currently calls at, stations unknown to the timetable are skipped
+    pub fn to_section(&self, stops: &HashMap<String, usize>) -> Option<TrainSection> {
+        let stations = self.stations.iter().find(|s| s.state == DVSState::Current)
+            .or_else(|| self.stations.iter().find(|s| s.state == DVSState::Planned))?;
+
+        let section_stops: Vec<usize> = stations.stations.iter()
+            .filter_map(|station| stops.get(&station.code.to_lowercase()).cloned())
+            .collect();
+
+        let track = self.dep_track.iter().find(|t| t.state == DVSState::Current)
+            .or_else(|| self.dep_track.iter().find(|t| t.state == DVSState::Planned))
+            .map(|t| format!("{}{}", t.track_number, t.track_part.map(|p| p.to_string()).unwrap_or_default()));
+
+        Some(TrainSection {
+            destination: *section_stops.last()?,
+            stops: section_stops,
+            track
+        })
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DVSTrainSectionStations {
     #[serde(rename = "InfoStatus", deserialize_with = "dvs_state")]
-    state: DVSState,
+    pub state: DVSState,
 
     #[serde(rename = "Station")]
     #[serde(default = "Vec::new")]
-    stations: Vec<DVSStation>
+    pub stations: Vec<DVSStation>
 }
 
 #[derive(Deserialize, Debug)]
@@ -145,13 +171,13 @@ pub struct DVSDateTime {
 #[derive(Debug, Deserialize)]
 pub struct DVSTrack {
     #[serde(rename = "InfoStatus", deserialize_with = "dvs_state")]
-    state: DVSState,
+    pub state: DVSState,
 
     #[serde(rename = "SpoorNummer")]
-    track_number: u8,
+    pub track_number: u8,
 
     #[serde(rename = "SpoorFase")]
-    track_part: Option<char>
+    pub track_part: Option<char>
 }
 
 #[derive(Debug, Deserialize)]
@@ -186,7 +212,7 @@ pub enum DVSUpdateType {
     TrainReplacingTransport = 51
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum DVSState {
     Planned, Current
 }
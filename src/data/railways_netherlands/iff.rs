@@ -1,34 +1,197 @@
-use std::{collections::{HashMap, HashSet}, error::Error, fmt::Debug, io::{self, Cursor, Read}, iter::{FromIterator, Peekable}, ops::Range};
+use std::{collections::{HashMap, HashSet}, error::Error, fmt::{self, Debug}, fs::{self, File}, hash::Hasher, io::{self, Cursor, Read, Write}, iter::{FromIterator, Peekable}, ops::Range, path::Path};
 
-use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use chrono::{Datelike, Duration, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use fnv::FnvHasher;
 use smol_str::SmolStr;
 use zip::{ZipArchive, result::ZipError};
 use lazy_static::lazy_static;
 use regex::Regex;
 use encoding_rs::mem;
-use diesel::{prelude::*, sql_types::Date};
+use diesel::{prelude::*, sql_types::Date, Connection as _};
 use itertools::Itertools;
 
-use super::iff_types::{IFF, Station, Service, Stop};
-use crate::{database::types::ServiceStopType, types::{Connection, Timetable, Trip}};
+use super::{iff_gtfs::gtfs_trip_id, iff_types::{Change, Company, ContinuousConnection, Country, IFF, Station, Service, Stop, TimezonePeriod, Version}};
+use crate::calendar::{CalendarTimetable, PatternConnection, TripPattern, Validity};
+use crate::{database::{DbConnection, DbI8, DbU16, DbU32, DbU64, Storage, types::ServiceStopType}, types::{Connection, Timetable, Trip, TripDetails, local_timestamp}};
 use crate::database::schema::service_stops;
 
+/// Timezone in which the times of an IFF timetable are given
+pub const IFF_TIMEZONE: Tz = chrono_tz::Europe::Amsterdam;
+
 lazy_static! {
     static ref RE_TRNSMODE: Regex = Regex::new(r"(?P<id>[A-Z]+)\s*,(?P<description>[\w \.]*[\w\.]+)\s*").unwrap();
     static ref RE_IDENT: Regex    = Regex::new(r"@(?P<company>\d{3}),(?P<valid_from>\d{8}),(?P<valid_till>\d{8}),(?P<version>\d{4}),(?P<description>[\w\-,_ ]*[\w+])").unwrap();
 }
 
-impl IFF {
-    fn from_zip<R: Read+ io::Seek>(mut zip: ZipArchive<R>) -> Result<Self, Box<dyn Error>> {
+// Comma separated fields of a line, without the padding
+fn fields(line: &str) -> Vec<&str> {
+    line.split(',').map(|field| field.trim()).collect()
+}
+
+/// Converts a time (HHMM, which can exceed 2400) on a service date to a timestamp, `difference` is the difference in hours
+/// between the station and the timezone of the timetable. Times are wall-clock times in the timezone of the timetable, such
+/// that they are correct on days with a DST change, independent of the timezone of this machine (see `local_timestamp`).
+pub fn service_time(tz: &Tz, date: &NaiveDate, time: u16, difference: i64) -> u32 {
+    let seconds = (time / 100) as u32 * 3600 + (time % 100) as u32 * 60;
+    (local_timestamp(tz, date, seconds) as i64 - difference * 3600) as u32
+}
+
+/// Parses a single service from a timetable file, returns an "End of file" error when there are no more services
+fn parse_service<'a>(service: &mut Peekable<impl Iterator<Item=&'a str> + Debug>) -> Result<Service, Box<dyn Error>> {
+    if service.peek().unwrap().is_empty() {
+        Err("End of file")?
+    }
+
+    let identification: usize = service.next().unwrap()[1..].parse()?;
+    let mut validity = None;
+    let mut company = None;
+
+    let mut trns_modes = vec![];
+    let mut attributes = vec![];
+    let mut stops = vec![];
+    let mut service_number = vec![];
+    
+    // while let Some(line) = service.next_if(|line| line.chars().next().unwrap() != '#') (currently unstable, same as below)
+    while service.peek().is_some() && service.peek().unwrap().len() != 0 && service.peek().unwrap().chars().next().unwrap() != '#' {
+        let line = service.next().unwrap();
+
+        match line.chars().next().unwrap() {
+            '%' => {
+                if company.is_none() {
+                    company = Some(line[1..4].parse()?);
+                }
+
+                service_number.push((
+                    line[5..10].parse()?,
+                    line[18..21].parse()?..line[22..25].parse()?
+                ));
+            },
+            '-' => validity = Some(line[1..6].parse()?),
+            '&' => trns_modes.push(line[1..5].trim_end().to_string()),
+            '*' => attributes.push(line[1..5].trim_end().to_string()),
+            '>' => {
+                stops.push(Stop::Departure {
+                    dep_time: line[9..13].parse()?,
+                    station: line[1..8].trim_end().to_string(),
+                    track: None
+                });
+            },
+            '.' => stops.push(Stop::ShortIntermediate {
+                    dep_time: line[9..13].parse()?,
+                    station: line[1..8].trim_end().to_string(),
+                    track: None
+                }),
+            ';' => stops.push(Stop::Pass {
+                    station: line[1..8].trim_end().to_string()
+                }),
+            '+' => stops.push(Stop::Intermediate {
+                    dep_time: line[14..18].parse()?,
+                    arr_time: line[9..13].parse()?,
+                    station: line[1..8].trim_end().to_string(),
+                    track: None
+                }),
+            '?' => {
+                let first_track = Some(line[1..6].trim_end().to_string());
+                let sec_track = Some(line[7..12].trim_end().to_string());
+
+                match stops.last_mut().ok_or("Got track information before stops")? {
+                    Stop::Departure { track, .. } => *track = sec_track,
+                    Stop::ShortIntermediate { track, .. } => *track = sec_track,
+                    Stop::Intermediate { track, .. } => *track = sec_track,
+                    Stop::Arrival { track, .. } => *track = first_track,
+                    Stop::Pass { .. } => Err("Tried to add track information to pass")?
+                };
+            },
+            '<' => stops.push(Stop::Arrival {
+                    arr_time: line[9..13].parse()?,
+                    station: line[1..8].trim_end().to_string(),
+                    track: None
+                }),
+            _ => Err(format!("Unknown line in timetable: {}", line))?
+        }
+    }
+
+    Ok(Service {
+        identification,
+        company: company.unwrap_or(0),
+        service_number,
+        validity: validity.ok_or("No validity set")?,
+        trns_modes,
+        attributes,
+        stops
+    })
+}
 
-        fn get_file<R: Read + io::Seek>(file: &str, z: &mut ZipArchive<R>) -> Result<String, ZipError> {
-            let mut file = z.by_name(file)?;
+/// Parses all services of a timetable file using a parser for a single service, which is shared between IFF and HRDF
+pub fn parse_services<'a, I: Iterator<Item=&'a str>>(
+    lines: &mut Peekable<I>,
+    parse_service: impl Fn(&mut Peekable<I>) -> Result<Service, Box<dyn Error>>
+) -> Result<HashMap<usize, Service>, Box<dyn Error>> {
+    let mut services = HashMap::new();
+
+    loop {
+        let service = parse_service(lines);
+        if let Ok(service) = service {
+            services.insert(service.identification, service);
+        } else if let Err(err) = service {
+            if err.to_string() == "End of file" { // Should probably make this an error type
+                break;
+            } else {
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(services)
+}
+
+/// Contents of a file that is optional, which is empty when the file is missing
+fn optional_file(file: Result<String, ZipError>) -> Result<String, ZipError> {
+    match file {
+        Err(ZipError::FileNotFound) => Ok(String::new()),
+        file => file
+    }
+}
+
+/// Dates on which a validity (footnote or bitfield) is valid, where the first bit corresponds to `from`
+pub fn validity_from_bits(from: NaiveDate, bits: impl Iterator<Item=bool>) -> HashSet<NaiveDate> {
+    bits.enumerate().filter(|&(_, bit)| bit).map(|(i, _)| from + Duration::days(i as i64)).collect()
+}
+
+impl IFF {
+    /// Parses an IFF file from a zip archive, as published by the NDOV Loket
+    pub fn from_zip<R: Read + io::Seek>(mut zip: ZipArchive<R>) -> Result<Self, Box<dyn Error>> {
+        Self::from_files(|file| {
+            let mut file = zip.by_name(file)?;
             let mut text = Vec::with_capacity(file.size() as usize);
             file.read_to_end(&mut text)?;
             Ok(mem::decode_latin1(&text).to_string())
+        })
+    }
+
+    /// Parses an IFF file of which the files are extracted to a directory
+    pub fn from_dir(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::from_files(|file| match fs::read(path.join(file)) {
+            Ok(text) => Ok(mem::decode_latin1(&text).to_string()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(ZipError::FileNotFound),
+            Err(err) => Err(ZipError::Io(err))
+        })
+    }
+
+    /// Parses an IFF file from either a zip file or a directory with the extracted files
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if path.is_dir() {
+            Self::from_dir(path)
+        } else {
+            Self::from_zip(ZipArchive::new(File::open(path)?)?)
         }
+    }
 
-        let stations: HashMap<SmolStr, Station> = get_file("stations.dat", &mut zip)?.lines().skip(1).map(|line| {
+    // Parses the IFF files, which are retrieved by name. Files which are optional might be missing in older IFF files, the
+    // attributes, companies and countries only describe services, so services without them are kept without a description.
+    fn from_files(mut get_file: impl FnMut(&str) -> Result<String, ZipError>) -> Result<Self, Box<dyn Error>> {
+        let stations: HashMap<SmolStr, Station> = get_file("stations.dat")?.lines().skip(1).map(|line| {
             (
                 SmolStr::new(&line[2..9].trim_end()),
                 Station {
@@ -46,274 +209,526 @@ impl IFF {
 
         // std::process::exit(0x0);
 
-        let trns_modes: HashMap<SmolStr, String> = RE_TRNSMODE.captures_iter(&get_file("trnsmode.dat", &mut zip)?).map(|cap| {
+        let trns_modes: HashMap<SmolStr, String> = RE_TRNSMODE.captures_iter(&get_file("trnsmode.dat")?).map(|cap| {
             (
                 SmolStr::new(cap.name("id").unwrap().as_str()),
                 cap.name("description").unwrap().as_str().to_string()
             )
         }).collect();
 
-        fn parse_service<'a>(service: &mut Peekable<impl Iterator<Item=&'a str> + Debug>) -> Result<Service, Box<dyn Error>> {
-            if service.peek().unwrap().is_empty() {
-                Err("End of file")?
-            }
+        // Attributes: code, processing code, description
+        let attributes: HashMap<SmolStr, String> = optional_file(get_file("trnsattr.dat"))?.lines().skip(1)
+            .map(|line| fields(line))
+            .filter(|fields| fields.len() >= 3)
+            .map(|fields| (SmolStr::new(fields[0]), fields[2].to_string()))
+            .collect();
 
-            let identification: usize = service.next().unwrap()[1..].parse()?;
-            let mut validity = None;
-
-            let mut trns_modes = vec![];
-            let mut attributes = vec![];
-            let mut stops = vec![];
-            let mut service_number = vec![];
-            
-            // while let Some(line) = service.next_if(|line| line.chars().next().unwrap() != '#') (currently unstable, same as below)
-            while service.peek().is_some() && service.peek().unwrap().len() != 0 && service.peek().unwrap().chars().next().unwrap() != '#' {
-                let line = service.next().unwrap();
-
-                match line.chars().next().unwrap() {
-                    '%' => {
-                        service_number.push((
-                            line[5..10].parse()?,
-                            line[18..21].parse()?..line[22..25].parse()?
-                        ));
-                    },
-                    '-' => validity = Some(line[1..6].parse()?),
-                    '&' => trns_modes.push(line[1..5].trim_end().to_string()),
-                    '*' => attributes.push(line[1..5].trim_end().to_string()),
-                    '>' => {
-                        stops.push(Stop::Departure {
-                            dep_time: line[9..13].parse()?,
-                            station: line[1..8].trim_end().to_string(),
-                            track: None
-                        });
-                    },
-                    '.' => stops.push(Stop::ShortIntermediate {
-                            dep_time: line[9..13].parse()?,
-                            station: line[1..8].trim_end().to_string(),
-                            track: None
-                        }),
-                    ';' => stops.push(Stop::Pass {
-                            station: line[1..8].trim_end().to_string()
-                        }),
-                    '+' => stops.push(Stop::Intermediate {
-                            dep_time: line[14..18].parse()?,
-                            arr_time: line[9..13].parse()?,
-                            station: line[1..8].trim_end().to_string(),
-                            track: None
-                        }),
-                    '?' => {
-                        let first_track = Some(line[1..6].trim_end().to_string());
-                        let sec_track = Some(line[7..12].trim_end().to_string());
-
-                        match stops.last_mut().ok_or("Got track information before stops")? {
-                            Stop::Departure { track, .. } => *track = sec_track,
-                            Stop::ShortIntermediate { track, .. } => *track = sec_track,
-                            Stop::Intermediate { track, .. } => *track = sec_track,
-                            Stop::Arrival { track, .. } => *track = first_track,
-                            Stop::Pass { .. } => Err("Tried to add track information to pass")?
-                        };
-                    },
-                    '<' => stops.push(Stop::Arrival {
-                            arr_time: line[9..13].parse()?,
-                            station: line[1..8].trim_end().to_string(),
-                            track: None
-                        }),
-                    _ => Err(format!("Unknown line in timetable: {}", line))?
-                }
+        // Companies: number, code, name, time turn
+        let companies = optional_file(get_file("company.dat"))?.lines().skip(1).filter(|line| !line.trim().is_empty()).map(|line| -> Result<(usize, Company), Box<dyn Error>> {
+            let fields = fields(line);
+            if fields.len() < 4 {
+                Err(format!("Invalid line in company file: {}", line))?
             }
 
-            Ok(Service {
-                identification,
-                service_number,
-                validity: validity.ok_or("No validity set")?,
-                trns_modes,
-                attributes,
-                stops
-            })
-        }
+            Ok((fields[0].parse()?, Company {
+                id: fields[0].parse()?,
+                code: fields[1].to_string(),
+                name: fields[2].to_string(),
+                time_turn: fields[3].parse()?
+            }))
+        }).collect::<Result<HashMap<usize, Company>, Box<dyn Error>>>()?;
 
-        let timetable = get_file("timetbls.dat", &mut zip)?;
-        let mut timetable_iterator = timetable.split("\r\n").skip(1).peekable();
-        let mut services = HashMap::new();
-        
-        loop {
-            let service = parse_service(&mut timetable_iterator);
-            if let Ok(service) = service {
-                services.insert(service.identification, service);
-            } else if let Err(err) = service {
-                if err.to_string() == "End of file" { // Should probably make this an error type
-                    break;
-                } else {
-                    return Err(err);
-                }
-            }
-        }
+        // Countries: code, inland, name
+        let countries: HashMap<SmolStr, Country> = optional_file(get_file("country.dat"))?.lines().skip(1)
+            .map(|line| fields(line))
+            .filter(|fields| fields.len() >= 3)
+            .map(|fields| (SmolStr::new(fields[0]), Country {
+                code: fields[0].to_string(),
+                inland: fields[1] == "1",
+                name: fields[2].to_string()
+            }))
+            .collect();
+
+        let timetable = get_file("timetbls.dat")?;
+        let services = parse_services(&mut timetable.split("\r\n").skip(1).peekable(), parse_service)?;
 
         // Validity
         let mut validity: HashMap<usize, HashSet<NaiveDate>> = HashMap::new();
-        let validity_file = get_file("footnote.dat", &mut zip)?;
+        let validity_file = get_file("footnote.dat")?;
         let mut validity_iter = validity_file.lines();
         let validity_information = RE_IDENT.captures(validity_iter.next().ok_or("Footnote file empty")?).ok_or("Invalid footnote file")?;
 
-        let validity_from = NaiveDate::parse_from_str(validity_information.name("valid_from").unwrap().as_str(), "%d%m%Y")?;
+        let version = Version {
+            company: validity_information.name("company").unwrap().as_str().parse()?,
+            version: validity_information.name("version").unwrap().as_str().parse()?,
+            valid_from: NaiveDate::parse_from_str(validity_information.name("valid_from").unwrap().as_str(), "%d%m%Y")?,
+            valid_till: NaiveDate::parse_from_str(validity_information.name("valid_till").unwrap().as_str(), "%d%m%Y")?,
+            description: validity_information.name("description").unwrap().as_str().to_string()
+        };
+
         while let Some(validity_id) = validity_iter.next() {
             validity.insert(validity_id[1..].parse::<usize>()?,
-                validity_iter.next().unwrap().chars().enumerate().filter(|(_, c)| c == &'1').map(|(i, _)| validity_from + Duration::days(i as i64)).collect());
+                validity_from_bits(version.valid_from, validity_iter.next().unwrap().chars().map(|c| c == '1')));
+        }
+
+        // Transfer times between specific trains or stations: from station, from service, to station, to service, duration
+        let changes = match get_file("changes.dat") {
+            Ok(file) => file.lines().skip(1).filter(|line| !line.trim().is_empty()).map(|line| -> Result<Change, Box<dyn Error>> {
+                let fields = fields(line);
+                if fields.len() < 5 {
+                    Err(format!("Invalid line in changes file: {}", line))?
+                }
+
+                Ok(Change {
+                    from_station: fields[0].to_string(),
+                    from_service: fields[1].parse()?,
+                    to_station: fields[2].to_string(),
+                    to_service: fields[3].parse()?,
+                    duration: fields[4].parse()?
+                })
+            }).collect::<Result<Vec<Change>, Box<dyn Error>>>()?,
+            Err(ZipError::FileNotFound) => vec![],
+            Err(err) => Err(err)?
+        };
+
+        // Continuous connections between stations: from station, to station, duration, transport mode
+        let continuous_connections = match get_file("contconn.dat") {
+            Ok(file) => file.lines().skip(1).filter(|line| !line.trim().is_empty()).map(|line| -> Result<ContinuousConnection, Box<dyn Error>> {
+                let fields = fields(line);
+                if fields.len() < 4 {
+                    Err(format!("Invalid line in continuous connections file: {}", line))?
+                }
+
+                Ok(ContinuousConnection {
+                    from_station: fields[0].to_string(),
+                    to_station: fields[1].to_string(),
+                    duration: fields[2].parse()?,
+                    mode: fields[3].to_string()
+                })
+            }).collect::<Result<Vec<ContinuousConnection>, Box<dyn Error>>>()?,
+            Err(ZipError::FileNotFound) => vec![],
+            Err(err) => Err(err)?
+        };
+
+        // Timezones: a line with the timezone id followed by its periods with difference, from and to date
+        let timezone_file = optional_file(get_file("timezone.dat"))?;
+
+        let mut timezones = vec![];
+        let mut id = None;
+        for line in timezone_file.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+            if line.starts_with('#') {
+                id = Some(line[1..].trim().parse()?);
+                continue;
+            }
+
+            let fields = fields(line);
+            if fields.len() < 3 {
+                Err(format!("Invalid line in timezone file: {}", line))?
+            }
+
+            timezones.push(TimezonePeriod {
+                id: id.ok_or("Timezone period before timezone")?,
+                difference: fields[0].parse()?,
+                from_date: NaiveDate::parse_from_str(fields[1], "%d%m%Y")?,
+                to_date: NaiveDate::parse_from_str(fields[2], "%d%m%Y")?
+            });
         }
 
         Ok(IFF {
             stations,
             trns_modes,
+            attributes,
+            companies,
+            countries,
+            timezones,
             services,
-            validity
+            validity,
+            changes,
+            continuous_connections,
+            version
         })
     }
 }
 
+/// Location of the most up-to-date IFF file at the NDOV Loket
+pub const IFF_URL: &str = "http://data.ndovloket.nl/iff/ns-latest.zip";
+
+/// Downloads and parses an IFF zip file, e.g. the most up-to-date one at `IFF_URL`
+pub async fn download_iff(url: &str) -> Result<IFF, Box<dyn Error + 'static>> {
+    IFF::from_zip(ZipArchive::new(Cursor::new(reqwest::get(url).await?.bytes().await?))?)
+}
+
 #[allow(dead_code)]
-/// Truncates the IFF data from the database and downloads a new version which is than inserted to the database
-pub async fn update_iff_database() -> Result<IFF, Box<dyn Error + 'static>> {
+/// Downloads a new version of the IFF data which is then inserted to the database
+pub async fn update_iff_database(url: &str) -> Result<ImportReport, Box<dyn Error + 'static>> {
+    import_iff(&download_iff(url).await?)
+}
 
-    // Load IFF file
-    let iff = IFF::from_zip(ZipArchive::new(Cursor::new(reqwest::get("http://data.ndovloket.nl/iff/ns-latest.zip").await?.bytes().await?))?)?;
+/// Checksum of everything that is stored of a service including the dates on which it runs, to find the services that
+/// changed between two versions of the IFF data. The checksums are stored, so fields are hashed one by one with FNV instead
+/// of using `Hash`, of which neither the hasher nor the implementations are guaranteed to stay the same between releases.
+pub fn service_checksum(service: &Service, dates: Option<&HashSet<NaiveDate>>) -> u64 {
+    fn number(hasher: &mut FnvHasher, number: usize) {
+        hasher.write(&(number as u64).to_le_bytes());
+    }
 
-    let connection = crate::database::establish_connection();
+    fn string(hasher: &mut FnvHasher, string: &str) {
+        number(hasher, string.len());
+        hasher.write(string.as_bytes());
+    }
 
-    // Upload stations to database
-    // use crate::database::schema::stations;
-    diesel::replace_into(stations::table).values(Vec::from_iter(iff.stations.values())).execute(&connection)?;
-    
-    // Upload trns_modes to database
-    use crate::database::schema::trns_modes;
-    #[derive(Debug, Insertable)]
-    #[table_name = "trns_modes"]
-    struct TrnsModeInsertable<'a> { id: &'a str, description: &'a str }
-    diesel::replace_into(trns_modes::table).values(
-        iff.trns_modes.iter().map(|(id, des)| TrnsModeInsertable { id: id.as_str(), description: des }).collect::<Vec<TrnsModeInsertable>>()
-    ).execute(&connection)?;
-
-    // Validities
-    use crate::database::schema::validities;
-    #[derive(Debug, Insertable)]
-    #[table_name = "validities"]
-    struct ValidityInsertable<'a> { id: u32, date: &'a NaiveDate }
-
-    let validities = iff.validity.iter().map(|(id, dates)| dates.iter().map(move |date| {
-        ValidityInsertable { id: *id as u32, date }
-    })).flatten().collect::<Vec<ValidityInsertable>>();
-
-    for i in (0..validities.len()).step_by(1000) {
-        diesel::replace_into(validities::table).values(&validities[i..(i+1000).min(validities.len())]).execute(&connection)?;
-    }
-
-    // Services
-    use crate::database::schema::services;
-    #[derive(Debug, Insertable)]
-    #[table_name = "services"]
-    struct ServiceInsertable { id: u32, validity_id: u32 }
-    let services = iff.services.values().map(|service| ServiceInsertable { id: service.identification as u32, validity_id: service.validity as u32 }).collect::<Vec<ServiceInsertable>>();
-    for i in (0..services.len()).step_by(1000) {
-        diesel::replace_into(services::table).values(&services[i..(i+1000).min(services.len())]).execute(&connection)?;
-    }
-
-    // Service identifiers
-    use crate::database::schema::service_identifier;
-    #[derive(Debug, Insertable)]
-    #[table_name = "service_identifier"]
-    struct ServiceIdentifierInsertable { service_id: u32, identifier: u32, from_index: u16, to_index: u16 };
+    fn option(hasher: &mut FnvHasher, value: Option<&String>) {
+        number(hasher, value.is_some() as usize);
+        value.into_iter().for_each(|value| string(hasher, value));
+    }
 
-    let service_identifiers = iff.services.values()
-        .map(|service| service.service_number.iter().map(move |id| ServiceIdentifierInsertable {
-            service_id: service.identification as u32,
-            identifier: id.0 as u32,
-            from_index: id.1.start as u16,
-            to_index: id.1.end as u16
-        })).flatten().collect::<Vec<ServiceIdentifierInsertable>>();
+    let mut hasher = FnvHasher::default();
+    number(&mut hasher, service.company);
+    number(&mut hasher, service.validity);
 
-    for i in (0..service_identifiers.len()).step_by(1000) {
-        diesel::replace_into(service_identifier::table).values(&service_identifiers[i..(i+1000).min(service_identifiers.len())]).execute(&connection)?;
+    number(&mut hasher, service.service_number.len());
+    for (id, range) in &service.service_number {
+        number(&mut hasher, *id);
+        number(&mut hasher, range.start);
+        number(&mut hasher, range.end);
     }
 
-    #[derive(Debug, Insertable)]
-    #[table_name = "service_stops"]
-    struct StopInsertable<'a> {
-        service_id: u32,
-        ordering: u32,
-        type_: ServiceStopType,
-        station_code: Option<&'a String>,
-        arr_time: Option<u16>,
-        dep_time: Option<u16>,
-        platform: Option<&'a String>
-    }
-
-    fn service_stops_to_stop_insertable(service: &Service) -> Vec<StopInsertable> {
-        let mut res = vec![];
-        for (i, stop) in service.stops.iter().enumerate() {
-            res.push(match stop {
-                Stop::Departure { dep_time, station, track } => StopInsertable {
-                    service_id: service.identification as u32,
-                    ordering: i as u32,
-                    type_: ServiceStopType::Departure,
-                    station_code: Some(station),
-                    arr_time: None,
-                    dep_time: Some(*dep_time),
-                    platform: track.as_ref()
-                },
-                Stop::ShortIntermediate { dep_time, station, track } => StopInsertable {
-                    service_id: service.identification as u32,
-                    ordering: i as u32,
-                    type_: ServiceStopType::ShortIntermediate,
-                    station_code: Some(station),
-                    arr_time: None,
-                    dep_time: Some(*dep_time),
-                    platform: track.as_ref()
-                },
-                Stop::Intermediate { arr_time, dep_time, station, track } => StopInsertable {
-                    service_id: service.identification as u32,
-                    ordering: i as u32,
-                    type_: ServiceStopType::Intermediate,
-                    station_code: Some(station),
-                    arr_time: Some(*arr_time),
-                    dep_time: Some(*dep_time),
-                    platform: track.as_ref()
-                },
-                Stop::Pass { station } => StopInsertable {
-                    service_id: service.identification as u32,
-                    ordering: i as u32,
-                    type_: ServiceStopType::Pass,
-                    station_code: Some(station),
-                    arr_time: None,
-                    dep_time: None,
-                    platform: None
-                },
-                Stop::Arrival { arr_time, station, track } => StopInsertable {
-                    service_id: service.identification as u32,
-                    ordering: i as u32,
-                    type_: ServiceStopType::Arrival,
-                    station_code: Some(station),
-                    arr_time: Some(*arr_time),
-                    dep_time: None,
-                    platform: track.as_ref()
-                }
-            });
+    for list in &[&service.trns_modes, &service.attributes] {
+        number(&mut hasher, list.len());
+        list.iter().for_each(|value| string(&mut hasher, value));
+    }
+
+    number(&mut hasher, service.stops.len());
+    for stop in &service.stops {
+        let (kind, arr_time, dep_time, station, track) = match stop {
+            Stop::Departure { dep_time, station, track } => (0, None, Some(dep_time), station, track.as_ref()),
+            Stop::ShortIntermediate { dep_time, station, track } => (1, None, Some(dep_time), station, track.as_ref()),
+            Stop::Intermediate { arr_time, dep_time, station, track } => (2, Some(arr_time), Some(dep_time), station, track.as_ref()),
+            Stop::Pass { station } => (3, None, None, station, None),
+            Stop::Arrival { arr_time, station, track } => (4, Some(arr_time), None, station, track.as_ref())
+        };
+
+        number(&mut hasher, kind);
+        for time in &[arr_time, dep_time] {
+            number(&mut hasher, time.map_or(usize::MAX, |&time| time as usize));
+        }
+        string(&mut hasher, station);
+        option(&mut hasher, track);
+    }
+
+    match dates {
+        Some(dates) => {
+            number(&mut hasher, dates.len());
+            dates.iter().sorted().for_each(|date| number(&mut hasher, date.num_days_from_ce() as usize));
+        },
+        None => number(&mut hasher, usize::MAX)
+    }
+
+    hasher.finish()
+}
+
+/// Services that were added, removed or changed between two versions, based on their checksums. Services are keyed on their
+/// identification, which is only a record number within an export instead of a property of the train. It is the id of the
+/// stored rows of a service though, so a service that got another identification has to be removed and added again anyway,
+/// while keying on the train instead could keep rows with an identification that is used by another service of the export.
+#[derive(Debug, Default, PartialEq)]
+pub struct ServicesDiff {
+    pub added: Vec<usize>,
+    pub removed: Vec<usize>,
+    pub changed: Vec<usize>
+}
+
+impl ServicesDiff {
+    pub fn new(previous: &HashMap<usize, u64>, current: &HashMap<usize, u64>) -> Self {
+        let mut diff = ServicesDiff::default();
+        for (&id, checksum) in current {
+            match previous.get(&id) {
+                None => diff.added.push(id),
+                Some(previous) if previous != checksum => diff.changed.push(id),
+                _ => {}
+            }
+        }
+
+        diff.removed = previous.keys().filter(|id| !current.contains_key(id)).copied().collect();
+
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+}
+
+/// Report of an import: the imported version, the version it replaced and the services that were written
+#[derive(Debug)]
+pub struct ImportReport {
+    pub previous: Option<Version>,
+    pub version: Version,
+    pub services: ServicesDiff
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |version: &Version| format!("version {} of company {} ({} until {})",
+            version.version, version.company, version.valid_from, version.valid_till);
+
+        write!(f, "Imported IFF {}", version(&self.version))?;
+        if let Some(previous) = &self.previous {
+            write!(f, ", replacing {}", version(previous))?;
         }
 
-        res
+        write!(f, ": {} services added, {} removed and {} changed",
+            self.services.added.len(), self.services.removed.len(), self.services.changed.len())
     }
+}
 
-    let stops = iff.services.values().map(|service| service_stops_to_stop_insertable(service)).flatten().collect::<Vec<StopInsertable>>();
-    for i in (0..stops.len()).step_by(1000) {
-        diesel::replace_into(service_stops::table).values(&stops[i..(i+1000).min(stops.len())]).execute(&connection)?;
+/// Inserts rows in batches, reporting the progress of the table as it can take a while
+fn insert_batches<T>(table: &str, rows: &[T], insert: impl Fn(&[T]) -> QueryResult<usize>) -> Result<(), Box<dyn Error>> {
+    for (i, batch) in rows.chunks(DbConnection::BATCH_SIZE).enumerate() {
+        insert(batch)?;
+        print!("\rImporting {}: {}/{} rows", table, i * DbConnection::BATCH_SIZE + batch.len(), rows.len());
+        io::stdout().flush()?;
     }
 
-    Ok(iff)
+    println!("\rImporting {}: {}/{} rows", table, rows.len(), rows.len());
+    Ok(())
+}
+
+/// Imports IFF data to the database in a single transaction, so a failed import leaves the previous version intact. Only the services that were added, removed or changed since
+/// the previously imported version are written, the other tables are replaced.
+pub fn import_iff(iff: &IFF) -> Result<ImportReport, Box<dyn Error + 'static>> {
+    let connection = crate::database::establish_connection();
+    connection.transaction::<_, Box<dyn Error>, _>(|| {
+        use crate::database::schema::{iff_versions, services, service_attributes, service_identifier, service_trns_modes, validities};
+
+        let previous = iff_versions::table
+            .select((iff_versions::company, iff_versions::version, iff_versions::valid_from, iff_versions::valid_till, iff_versions::description))
+            .order(iff_versions::imported_at.desc())
+            .first::<Version>(&connection)
+            .optional()?;
+
+        let checksums: HashMap<usize, u64> = iff.services.iter()
+            .map(|(_, service)| (service.identification, service_checksum(service, iff.validity.get(&service.validity))))
+            .collect();
+
+        let previous_checksums: HashMap<usize, u64> = services::table.select((services::id, services::checksum))
+            .load::<(DbU32, DbU64)>(&connection)?
+            .into_iter()
+            .map(|(id, checksum)| (id as usize, checksum as u64))
+            .collect();
+
+        let diff = ServicesDiff::new(&previous_checksums, &checksums);
+
+        // Services that were removed or changed are deleted first, as they refer to the stations and validities
+        let outdated_services = diff.removed.iter().chain(&diff.changed).map(|&id| id as DbU32).collect::<Vec<DbU32>>();
+        println!("Deleting {} outdated services...", outdated_services.len());
+        for ids in outdated_services.chunks(DbConnection::BATCH_SIZE) {
+            diesel::delete(service_stops::table.filter(service_stops::service_id.eq_any(ids))).execute(&connection)?;
+            diesel::delete(service_identifier::table.filter(service_identifier::service_id.eq_any(ids))).execute(&connection)?;
+            diesel::delete(service_trns_modes::table.filter(service_trns_modes::service_id.eq_any(ids))).execute(&connection)?;
+            diesel::delete(service_attributes::table.filter(service_attributes::service_id.eq_any(ids))).execute(&connection)?;
+            diesel::delete(services::table.filter(services::id.eq_any(ids))).execute(&connection)?;
+        }
+
+        // Upload stations to database
+        // use crate::database::schema::stations;
+        diesel::replace_into(stations::table).values(Vec::from_iter(iff.stations.values())).execute(&connection)?;
+
+        // Upload trns_modes to database
+        use crate::database::schema::trns_modes;
+        #[derive(Debug, Insertable)]
+        #[table_name = "trns_modes"]
+        struct TrnsModeInsertable<'a> { id: &'a str, description: &'a str }
+        diesel::replace_into(trns_modes::table).values(
+            iff.trns_modes.iter().map(|(id, des)| TrnsModeInsertable { id: id.as_str(), description: des }).collect::<Vec<TrnsModeInsertable>>()
+        ).execute(&connection)?;
+
+        // Attributes, companies and countries
+        use crate::database::schema::{attributes, companies, countries};
+        #[derive(Debug, Insertable)]
+        #[table_name = "attributes"]
+        struct AttributeInsertable<'a> { id: &'a str, description: &'a str }
+        diesel::replace_into(attributes::table).values(
+            iff.attributes.iter().map(|(id, des)| AttributeInsertable { id: id.as_str(), description: des }).collect::<Vec<AttributeInsertable>>()
+        ).execute(&connection)?;
+
+        diesel::replace_into(companies::table).values(Vec::from_iter(iff.companies.values())).execute(&connection)?;
+        diesel::replace_into(countries::table).values(Vec::from_iter(iff.countries.values())).execute(&connection)?;
+
+        // Timezones, continuous connections and transfer times are replaced completely, so rows that disappeared are removed
+        use crate::database::schema::{changes, continuous_connections, timezones};
+        diesel::delete(timezones::table).execute(&connection)?;
+        diesel::insert_into(timezones::table).values(&iff.timezones).execute(&connection)?;
+
+        diesel::delete(continuous_connections::table).execute(&connection)?;
+        diesel::insert_into(continuous_connections::table).values(&iff.continuous_connections).execute(&connection)?;
+
+        diesel::delete(changes::table).execute(&connection)?;
+        insert_batches("changes", &iff.changes, |batch| diesel::insert_into(changes::table).values(batch).execute(&connection))?;
+
+        // Validities of which the dates changed are replaced, the services using them have changed as well and are deleted
+        #[derive(Debug, Insertable)]
+        #[table_name = "validities"]
+        struct ValidityInsertable<'a> { id: DbU32, date: &'a NaiveDate }
+
+        let mut previous_validity: HashMap<usize, HashSet<NaiveDate>> = HashMap::new();
+        for (id, date) in validities::table.select((validities::id, validities::date)).load::<(DbU32, NaiveDate)>(&connection)? {
+            previous_validity.entry(id as usize).or_default().insert(date);
+        }
+
+        let outdated_validities = previous_validity.iter()
+            .filter(|(id, dates)| iff.validity.get(*id) != Some(*dates))
+            .map(|(&id, _)| id as DbU32)
+            .collect::<Vec<DbU32>>();
+
+        for ids in outdated_validities.chunks(DbConnection::BATCH_SIZE) {
+            diesel::delete(validities::table.filter(validities::id.eq_any(ids))).execute(&connection)?;
+        }
+
+        let validities = iff.validity.iter()
+            .filter(|(id, dates)| previous_validity.get(*id) != Some(*dates))
+            .flat_map(|(id, dates)| dates.iter().map(move |date| ValidityInsertable { id: *id as DbU32, date }))
+            .collect::<Vec<ValidityInsertable>>();
+
+        insert_batches("validities", &validities, |batch| diesel::insert_into(validities::table).values(batch).execute(&connection))?;
+
+        // Services that were added or changed
+        let written: Vec<&Service> = diff.added.iter().chain(&diff.changed).map(|id| &iff.services[id]).collect();
+
+        #[derive(Debug, Insertable)]
+        #[table_name = "services"]
+        struct ServiceInsertable { id: DbU32, validity_id: DbU32, company_id: DbU32, checksum: DbU64 }
+        let services = written.iter().map(|service| ServiceInsertable {
+            id: service.identification as DbU32,
+            validity_id: service.validity as DbU32,
+            company_id: service.company as DbU32,
+            checksum: checksums[&service.identification] as DbU64
+        }).collect::<Vec<ServiceInsertable>>();
+
+        insert_batches("services", &services, |batch| diesel::insert_into(services::table).values(batch).execute(&connection))?;
+
+        // Transport modes and attributes of services, unknown codes are skipped
+        #[derive(Debug, Insertable)]
+        #[table_name = "service_trns_modes"]
+        struct ServiceTrnsModeInsertable<'a> { service_id: DbU32, trns_mode_id: &'a str }
+
+        let service_trns_modes = written.iter()
+            .flat_map(|service| service.trns_modes.iter().unique()
+                .filter(|mode| iff.trns_modes.contains_key(mode.as_str()))
+                .map(move |mode| ServiceTrnsModeInsertable { service_id: service.identification as DbU32, trns_mode_id: mode })
+            ).collect::<Vec<ServiceTrnsModeInsertable>>();
+
+        insert_batches("service_trns_modes", &service_trns_modes, |batch| diesel::insert_into(service_trns_modes::table).values(batch).execute(&connection))?;
+
+        #[derive(Debug, Insertable)]
+        #[table_name = "service_attributes"]
+        struct ServiceAttributeInsertable<'a> { service_id: DbU32, attribute_id: &'a str }
+
+        let service_attributes = written.iter()
+            .flat_map(|service| service.attributes.iter().unique()
+                .filter(|attribute| iff.attributes.contains_key(attribute.as_str()))
+                .map(move |attribute| ServiceAttributeInsertable { service_id: service.identification as DbU32, attribute_id: attribute })
+            ).collect::<Vec<ServiceAttributeInsertable>>();
+
+        insert_batches("service_attributes", &service_attributes, |batch| diesel::insert_into(service_attributes::table).values(batch).execute(&connection))?;
+
+        // Service identifiers
+        #[derive(Debug, Insertable)]
+        #[table_name = "service_identifier"]
+        struct ServiceIdentifierInsertable { service_id: DbU32, identifier: DbU32, from_index: DbU16, to_index: DbU16 };
+
+        let service_identifiers = written.iter()
+            .flat_map(|service| service.service_number.iter().map(move |id| ServiceIdentifierInsertable {
+                service_id: service.identification as DbU32,
+                identifier: id.0 as DbU32,
+                from_index: id.1.start as DbU16,
+                to_index: id.1.end as DbU16
+            })).collect::<Vec<ServiceIdentifierInsertable>>();
+
+        insert_batches("service_identifier", &service_identifiers, |batch| diesel::insert_into(service_identifier::table).values(batch).execute(&connection))?;
+
+        #[derive(Debug, Insertable)]
+        #[table_name = "service_stops"]
+        struct StopInsertable<'a> {
+            service_id: DbU32,
+            ordering: DbU32,
+            type_: ServiceStopType,
+            station_code: Option<&'a String>,
+            arr_time: Option<DbU16>,
+            dep_time: Option<DbU16>,
+            platform: Option<&'a String>
+        }
+
+        fn service_stops_to_stop_insertable(service: &Service) -> Vec<StopInsertable> {
+            let mut res = vec![];
+            for (i, stop) in service.stops.iter().enumerate() {
+                res.push(match stop {
+                    Stop::Departure { dep_time, station, track } => StopInsertable {
+                        service_id: service.identification as DbU32,
+                        ordering: i as DbU32,
+                        type_: ServiceStopType::Departure,
+                        station_code: Some(station),
+                        arr_time: None,
+                        dep_time: Some(*dep_time as DbU16),
+                        platform: track.as_ref()
+                    },
+                    Stop::ShortIntermediate { dep_time, station, track } => StopInsertable {
+                        service_id: service.identification as DbU32,
+                        ordering: i as DbU32,
+                        type_: ServiceStopType::ShortIntermediate,
+                        station_code: Some(station),
+                        arr_time: None,
+                        dep_time: Some(*dep_time as DbU16),
+                        platform: track.as_ref()
+                    },
+                    Stop::Intermediate { arr_time, dep_time, station, track } => StopInsertable {
+                        service_id: service.identification as DbU32,
+                        ordering: i as DbU32,
+                        type_: ServiceStopType::Intermediate,
+                        station_code: Some(station),
+                        arr_time: Some(*arr_time as DbU16),
+                        dep_time: Some(*dep_time as DbU16),
+                        platform: track.as_ref()
+                    },
+                    Stop::Pass { station } => StopInsertable {
+                        service_id: service.identification as DbU32,
+                        ordering: i as DbU32,
+                        type_: ServiceStopType::Pass,
+                        station_code: Some(station),
+                        arr_time: None,
+                        dep_time: None,
+                        platform: None
+                    },
+                    Stop::Arrival { arr_time, station, track } => StopInsertable {
+                        service_id: service.identification as DbU32,
+                        ordering: i as DbU32,
+                        type_: ServiceStopType::Arrival,
+                        station_code: Some(station),
+                        arr_time: Some(*arr_time as DbU16),
+                        dep_time: None,
+                        platform: track.as_ref()
+                    }
+                });
+            }
+
+            res
+        }
+
+        let stops = written.iter().flat_map(|service| service_stops_to_stop_insertable(service)).collect::<Vec<StopInsertable>>();
+        insert_batches("service_stops", &stops, |batch| diesel::insert_into(service_stops::table).values(batch).execute(&connection))?;
+
+        diesel::replace_into(iff_versions::table).values(&iff.version).execute(&connection)?;
+
+        Ok(ImportReport { previous, version: iff.version.clone(), services: diff })
+    })
 }
 
 use crate::database::schema::stations;
 #[derive(Debug, PartialEq, Eq, Hash, QueryableByName, Clone)]
 #[table_name = "stations"]
-struct IFFStop {
+pub struct IFFStop {
     code: String,
+    name: String,
+    timezone: DbI8,
 
     // Currently the latitude and longitude ar in the rijksdriehoekscoördinatensystem, should probably be converted in to WG84
     // However RD has the added benefit of being semi-distance-accurate which means we don't need to do difficult
@@ -333,6 +748,10 @@ impl crate::types::Stop for IFFStop {
         format!("{}", self.code)
     }
 
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
     fn distance(&self, other: &Box<dyn crate::types::Stop>) -> Option<f64> {
         let c1 = self.coords().unwrap();
         let c2 = other.coords().unwrap();
@@ -343,37 +762,282 @@ impl crate::types::Stop for IFFStop {
     fn coords(&self) -> Option<(f64, f64)> {
         Some((self.lat as f64, self.lng as f64))
     }
+
+    // Rijksdriehoek coordinates
+    fn planar(&self) -> bool {
+        true
+    }
+}
+
+// Train numbers of a service that follow each other, on which passengers can stay seated
+fn service_continuations(service_ids: &Vec<(usize, Range<usize>)>) -> Vec<(usize, usize)> {
+    let mut ids: Vec<&(usize, Range<usize>)> = service_ids.iter().collect();
+    ids.sort_by_key(|(_, range)| range.start);
+
+    // Train numbers covering the same part of a service are coupled trains instead
+    ids.windows(2)
+        .filter(|w| w[0].1.start < w[1].1.start && w[0].0 != w[1].0)
+        .map(|w| (w[0].0, w[1].0))
+        .collect()
+}
+
+// Difference in hours between the stations in every timezone and the times in the timetable on a date
+fn timezone_differences(periods: &[TimezonePeriod], date: &NaiveDate) -> HashMap<u32, i64> {
+    periods.iter()
+        .filter(|period| period.from_date <= *date && *date <= period.to_date)
+        .map(|period| (period.id as u32, period.difference as i64))
+        .collect()
+}
+
+// Footpaths of the stations, being a 'loopback' for each station, the continuous connections (the fastest mode is used) and the
+// transfer times between stations. Transfer times between specific trains are returned separately, as the algorithms look them up.
+fn footpaths_and_transfers(stops: &HashMap<&String, usize>, continuous_connections: &[ContinuousConnection], changes: &[Change])
+    -> (HashMap<usize, Vec<(usize, u32)>>, HashMap<(usize, Option<usize>, Option<usize>), u32>) {
+    let mut footpaths: HashMap<usize, Vec<(usize, u32)>> = stops.values().map(|&stop| (stop, vec![(stop, 0)])).collect();
+
+    for connection in continuous_connections {
+        if let (Some(&from), Some(&to)) = (stops.get(&connection.from_station), stops.get(&connection.to_station)) {
+            let duration = connection.duration as u32 * 60;
+            let paths = footpaths.entry(from).or_insert_with(Vec::new);
+            match paths.iter_mut().find(|(stop, _)| *stop == to) {
+                Some(path) => path.1 = path.1.min(duration),
+                None => paths.push((to, duration))
+            }
+        }
+    }
+
+    let mut transfers = HashMap::new();
+    for change in changes {
+        let (from, to) = match (stops.get(&change.from_station), stops.get(&change.to_station)) {
+            (Some(&from), Some(&to)) => (from, to),
+            _ => continue
+        };
+
+        let duration = change.duration as u32 * 60;
+        let trip = |service| if service == 0 { None } else { Some(service as usize) };
+
+        match (trip(change.from_service), trip(change.to_service)) {
+            (None, None) => {
+                let paths = footpaths.entry(from).or_insert_with(Vec::new);
+                paths.retain(|&(stop, _)| stop != to);
+                paths.push((to, duration));
+            },
+            (from_trip, to_trip) => { transfers.insert((to, from_trip, to_trip), duration); }
+        }
+    }
+
+    (footpaths, transfers)
+}
+
+// Station, arrival and departure time of the stops at which a service halts, with the range of these halts every train
+// number of the service covers
+fn service_halts(service: &Service) -> (Vec<(&String, u16, u16)>, Vec<(usize, Range<usize>)>) {
+    let halts: Vec<(&String, u16, u16)> = service.stops.iter().filter_map(|stop| match stop {
+        Stop::Departure { dep_time, station, .. } => Some((station, *dep_time, *dep_time)),
+        Stop::ShortIntermediate { dep_time, station, .. } => Some((station, *dep_time, *dep_time)),
+        Stop::Intermediate { arr_time, dep_time, station, .. } => Some((station, *arr_time, *dep_time)),
+        Stop::Arrival { arr_time, station, .. } => Some((station, *arr_time, *arr_time)),
+        Stop::Pass { .. } => None
+    }).collect();
+
+    let ids = service.service_number.iter()
+        .map(|(id, range)| (*id, (range.start - 1).min(halts.len())..range.end.min(halts.len())))
+        .collect();
+
+    (halts, ids)
+}
+
+/// Trips and continuations of the services valid on a date, without the need for a database. A service becomes a trip for
+/// every train number it runs as, stations are looked up by their code and `time` converts the time at a station to a timestamp.
+/// `trip_id` gives the id of the trip of a service by its train number, as train numbers are only unique within IFF.
+pub fn services_to_trips(
+    services: &HashMap<usize, Service>,
+    validity: &HashMap<usize, HashSet<NaiveDate>>,
+    stops: &HashMap<&str, usize>,
+    date: &NaiveDate,
+    time: impl Fn(&str, u16) -> u32,
+    mut trip_id: impl FnMut(&Service, usize) -> usize
+) -> (Vec<Trip>, HashMap<usize, usize>) {
+    let mut trips = vec![];
+    let mut continuations = HashMap::new();
+
+    let mut services: Vec<&Service> = services.values()
+        .filter(|service| validity.get(&service.validity).map_or(false, |dates| dates.contains(date)))
+        .collect();
+    services.sort_by_key(|service| service.identification);
+
+    for service in services {
+        let (halts, ids) = service_halts(service);
+        let trip_ids: HashMap<usize, usize> = ids.iter().map(|(number, _)| (*number, trip_id(service, *number))).collect();
+
+        for (number, range) in &ids {
+            let identifier = trip_ids[number];
+            let connections: Vec<Connection> = halts[range.clone()].windows(2).filter_map(|w| Some(Connection {
+                dep_stop: *stops.get(w[0].0.as_str())?,
+                arr_stop: *stops.get(w[1].0.as_str())?,
+                dep_time: time(w[0].0, w[0].2),
+                arr_time: time(w[1].0, w[1].1),
+                trip_id: identifier
+            })).collect();
+
+            if !connections.is_empty() {
+                trips.push(Trip { identifier, connections });
+            }
+        }
+
+        continuations.extend(service_continuations(&ids).into_iter().map(|(from, to)| (trip_ids[&from], trip_ids[&to])));
+    }
+
+    (trips, continuations)
+}
+
+impl IFF {
+    /// Builds the timetable of a date without the database, just like `get_timetable_for_day`: services are filtered on their
+    /// validity, passes are skipped and every train number of a service becomes a trip
+    pub fn timetable_for_day(&self, date: &NaiveDate) -> Timetable {
+        self.calendar().timetable_for_range(date, date)
+    }
+
+    /// Timetable with every train number of a service as a pattern, running on the dates of the validity of the service
+    pub fn calendar(&self) -> CalendarTimetable<IFFStop> {
+        let mut stations: Vec<&Station> = self.stations.values().collect();
+        stations.sort_by(|a, b| a.code.cmp(&b.code));
+
+        let stops_lookup: HashMap<&String, usize> = stations.iter().enumerate().map(|(i, station)| (&station.code, i)).collect();
+        let (footpaths, transfers) = footpaths_and_transfers(&stops_lookup, &self.continuous_connections, &self.changes);
+
+        let differences = stations.iter().enumerate().map(|(i, station)| (i, self.timezones.iter()
+            .filter(|period| period.id as u32 == station.timezone as u32)
+            .map(|period| (period.from_date, period.to_date, period.difference as i64))
+            .collect::<Vec<_>>()))
+            .filter(|(_, periods)| !periods.is_empty())
+            .collect();
+
+        let first_day = self.validity.values().flatten().min().copied().unwrap_or(self.version.valid_from);
+        let validities = self.validity.iter().map(|(&id, dates)| (id, Validity::from_dates(&first_day, dates))).collect();
+
+        let mut services: Vec<&Service> = self.services.values().collect();
+        services.sort_by_key(|service| service.identification);
+
+        let seconds = |time: u16| (time / 100) as u32 * 3600 + (time % 100) as u32 * 60;
+
+        let mut patterns = vec![];
+        for service in services {
+            let (halts, ids) = service_halts(service);
+            let continuations: HashMap<usize, usize> = service_continuations(&ids).into_iter().collect();
+
+            let details = TripDetails {
+                operator: self.companies.get(&service.company).map(|company| company.name.clone()),
+                mode: service.trns_modes.first().and_then(|mode| self.trns_modes.get(mode.as_str())).cloned(),
+                attributes: service.attributes.iter().unique().filter_map(|attribute| self.attributes.get(attribute.as_str())).cloned().collect(),
+                number: None
+            };
+
+            for (id, range) in &ids {
+                patterns.push(TripPattern {
+                    identifier: *id,
+                    validity: service.validity,
+                    connections: halts[range.clone()].windows(2).filter_map(|w| Some(PatternConnection {
+                        dep_stop: *stops_lookup.get(w[0].0)?,
+                        arr_stop: *stops_lookup.get(w[1].0)?,
+                        dep_time: seconds(w[0].2),
+                        arr_time: seconds(w[1].1)
+                    })).collect(),
+                    continues_as: continuations.get(id).copied(),
+                    details: details.clone()
+                });
+            }
+        }
+
+        CalendarTimetable {
+            first_day,
+            timezone: IFF_TIMEZONE,
+            stops: stations.into_iter().enumerate().map(|(i, station)| (i, IFFStop {
+                code: station.code.clone(),
+                name: station.name.clone(),
+                timezone: station.timezone,
+                lat: station.lat,
+                lng: station.lng
+            })).collect(),
+            differences,
+            validities,
+            patterns,
+            footpaths,
+            transfers
+        }
+    }
+}
+
+// Operator, transport mode and attribute descriptions of the train numbers of the given services
+fn trip_details(conn: &DbConnection, service_ids: &HashMap<usize, Vec<(usize, Range<usize>)>>, day_services: &[usize]) -> Result<HashMap<usize, TripDetails>, Box<dyn Error>> {
+    use crate::database::schema::{attributes, companies, service_attributes, service_trns_modes, services, trns_modes};
+
+    let companies: HashMap<DbU32, String> = companies::table.select((companies::id, companies::name)).load::<(DbU32, String)>(conn)?.into_iter().collect();
+    let operators: HashMap<DbU32, DbU32> = services::table.select((services::id, services::company_id)).load::<(DbU32, DbU32)>(conn)?.into_iter().collect();
+
+    let modes: HashMap<DbU32, String> = service_trns_modes::table.inner_join(trns_modes::table)
+        .select((service_trns_modes::service_id, trns_modes::description))
+        .load::<(DbU32, Option<String>)>(conn)?
+        .into_iter()
+        .filter_map(|(id, description)| Some((id, description?)))
+        .collect();
+
+    let mut attributes: HashMap<DbU32, Vec<String>> = HashMap::new();
+    for (id, description) in service_attributes::table.inner_join(attributes::table)
+        .select((service_attributes::service_id, attributes::description))
+        .load::<(DbU32, Option<String>)>(conn)? {
+        if let Some(description) = description {
+            attributes.entry(id).or_default().push(description);
+        }
+    }
+
+    let mut details = HashMap::new();
+    for &service in day_services {
+        let id = service as DbU32;
+        let service_details = TripDetails {
+            operator: operators.get(&id).and_then(|company| companies.get(company)).cloned(),
+            mode: modes.get(&id).cloned(),
+            attributes: attributes.get(&id).cloned().unwrap_or_default(),
+            number: None
+        };
+
+        for (identifier, _) in service_ids.get(&service).into_iter().flatten() {
+            details.insert(*identifier, service_details.clone());
+        }
+    }
+
+    Ok(details)
 }
 
 pub fn get_timetable_for_day(date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
 
-    fn query_to_trips(connections: Vec<QueryConnection>, stops: &HashMap<&String, usize>, service_ids: &Vec<(usize, Range<usize>)>, datetime: &DateTime<Local>) -> Vec<Trip> {
+    fn query_to_trips(connections: Vec<QueryConnection>, stops: &HashMap<&String, usize>, service_ids: &Vec<(usize, Range<usize>)>, time: &impl Fn(&String, u16) -> u32) -> Vec<Trip> {
         service_ids.iter().map(|(id, range)| {
             Trip {
                 identifier: *id,
-                connections: query_to_trip(&connections[range.clone()], &stops, datetime, *id)
+                connections: query_to_trip(&connections[range.clone()], &stops, time, *id)
             }
         }).collect()
     }
 
-    fn query_to_trip(query_connections: &[QueryConnection], stops: &HashMap<&String, usize>, datetime: &DateTime<Local>, id: usize) -> Vec<Connection> {
+    fn query_to_trip(query_connections: &[QueryConnection], stops: &HashMap<&String, usize>, time: &impl Fn(&String, u16) -> u32, id: usize) -> Vec<Connection> {
         let mut connections = vec![];
         let mut prev_connection = &query_connections[0];
         for next_connection in &query_connections[1..] {
 
-            let dep_time = prev_connection.dep_time.unwrap() as i64;
+            let dep_time = prev_connection.dep_time.unwrap();
             let arr_time = if let Some(arr_time) = next_connection.arr_time {
                 arr_time
             } else {
                 next_connection.dep_time.unwrap()
-            } as i64;
+            };
 
             connections.push(Connection {
                 dep_stop: *stops.get(&prev_connection.station_code).unwrap(),
                 arr_stop: *stops.get(&next_connection.station_code).unwrap(),
                 
-                dep_time: (*datetime + Duration::hours(dep_time / 100) + Duration::minutes(dep_time % 100)).timestamp() as u32,
-                arr_time: (*datetime + Duration::hours(arr_time / 100) + Duration::minutes(arr_time % 100)).timestamp() as u32,
+                dep_time: time(&prev_connection.station_code, dep_time as u16),
+                arr_time: time(&next_connection.station_code, arr_time as u16),
 
                 trip_id: id
             });
@@ -385,7 +1049,7 @@ pub fn get_timetable_for_day(date: &NaiveDate) -> Result<Timetable, Box<dyn Erro
     let conn = crate::database::establish_connection();
 
     #[derive(Debug, Queryable)]
-    struct QueryServiceIdentifier{ service_id: u32, identifier: u32, from_index: u16, to_index: u16 };
+    struct QueryServiceIdentifier{ service_id: DbU32, identifier: DbU32, from_index: DbU16, to_index: DbU16 };
 
     use crate::database::schema::service_identifier::dsl::*;
     let service_ids: HashMap<usize, Vec<(usize, Range<usize>)>> = service_identifier.load::<QueryServiceIdentifier>(&conn)?
@@ -400,11 +1064,11 @@ pub fn get_timetable_for_day(date: &NaiveDate) -> Result<Timetable, Box<dyn Erro
     #[derive(Debug, QueryableByName)]
     #[table_name = "service_stops"]
     struct QueryConnection {
-        service_id: u32,
+        service_id: DbU32,
         type_: ServiceStopType,
         station_code: String,
-        arr_time: Option<u16>,
-        dep_time: Option<u16>,
+        arr_time: Option<DbU16>,
+        dep_time: Option<DbU16>,
         platform: Option<String>
     }
 
@@ -416,26 +1080,297 @@ pub fn get_timetable_for_day(date: &NaiveDate) -> Result<Timetable, Box<dyn Erro
         .collect();
 
     let stops_lookup: HashMap<&String, usize> = stops.iter().map(|(stop, id)| (&stop.code, *id)).collect();
-    let datetime = Local.from_utc_date(&date).and_hms(0, 0, 0);
 
+    // Stations in another timezone have their times in the timetable in local time
+    let timezones = crate::database::schema::timezones::table.load::<TimezonePeriod>(&conn)?;
+    let differences = timezone_differences(&timezones, date);
+    let station_differences: HashMap<&String, i64> = stops.keys()
+        .filter_map(|stop| Some((&stop.code, *differences.get(&(stop.timezone as u32))?)))
+        .collect();
+
+    let time = |station: &String, time: u16| service_time(&IFF_TIMEZONE, date, time, station_differences.get(station).copied().unwrap_or(0));
+
+    let mut continuations = HashMap::new();
+    let mut day_services = vec![];
     let trips = diesel::sql_query(include_str!("timetable_for_day.sql"))
         .bind::<Date, _>(&date)
         .load::<QueryConnection>(&conn)?
         .into_iter()
         .group_by(|stop| stop.service_id)
         .into_iter()
-        .map(|(id, connections)| query_to_trips(connections.collect(), &stops_lookup, service_ids.get(&(id as usize)).unwrap(), &datetime))
+        .map(|(id, connections)| {
+            let ids = service_ids.get(&(id as usize)).unwrap();
+            continuations.extend(service_continuations(ids));
+            day_services.push(id as usize);
+            query_to_trips(connections.collect(), &stops_lookup, ids, &time)
+        })
         .flatten().collect::<Vec<Trip>>();
 
-    // Now we create a 'loopback' footpath for each station
-    let mut footpaths = HashMap::new();
-    for (_, &stop) in &stops {
-        footpaths.insert(stop, vec![(stop, 0)]);
-    }
+    let continuous_connections = crate::database::schema::continuous_connections::table.load::<ContinuousConnection>(&conn)?;
+    let changes = crate::database::schema::changes::table.load::<Change>(&conn)?;
+    let (footpaths, transfers) = footpaths_and_transfers(&stops_lookup, &continuous_connections, &changes);
 
     Ok(Timetable {
         trips,
         stops: stops.into_iter().map(|(stop, i)| (i, Box::new(stop) as Box<dyn crate::types::Stop>)).collect(),
-        footpaths
+        footpaths,
+        continuations,
+        sections: HashMap::new(),
+        transfers,
+        details: trip_details(&conn, &service_ids, &day_services)?
     })
-}
\ No newline at end of file
+}
+
+/// Timetable of a date together with the adjacent dates from the database, like `CalendarTimetable::timetable_around`
+pub fn get_timetable_around(date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
+    let mut timetable = get_timetable_for_day(date)?;
+    timetable.merge_day(1, get_timetable_for_day(&date.succ())?, 0);
+
+    let midnight = IFF_TIMEZONE.from_local_datetime(&date.and_hms(0, 0, 0)).unwrap().timestamp() as u32;
+    timetable.merge_day(2, get_timetable_for_day(&date.pred())?, midnight);
+    Ok(timetable)
+}
+
+/// Trip identifiers in the GTFS export (see `IFF::write_gtfs`) of the train numbers running on a date
+pub fn gtfs_trip_ids_for_day(date: &NaiveDate) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    use crate::database::schema::service_identifier;
+
+    #[derive(Debug, QueryableByName)]
+    #[table_name = "service_identifier"]
+    struct QueryServiceIdentifier { service_id: DbU32, identifier: DbU32 }
+
+    let conn = crate::database::establish_connection();
+    Ok(diesel::sql_query(include_str!("service_identifiers_for_day.sql"))
+        .bind::<Date, _>(&date)
+        .load::<QueryServiceIdentifier>(&conn)?
+        .into_iter()
+        .map(|id| (id.identifier as usize, gtfs_trip_id(id.service_id as usize, id.identifier as usize)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{algorithms::csa_vec::CSAVec, benchable::Benchable, types::TRIP_IDS_PER_DAY};
+
+    #[test]
+    fn services_diff() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let iff = IFF::from_path(&path).unwrap();
+        let checksums = |iff: &IFF| iff.services.values()
+            .map(|service| (service.identification, service_checksum(service, iff.validity.get(&service.validity))))
+            .collect::<HashMap<usize, u64>>();
+
+        // Service 1 gets another departure time, service 2 disappears and service 3 is new
+        let mut next = iff.clone();
+        next.services.get_mut(&1).unwrap().stops[0] = Stop::Departure { dep_time: 1201, station: "asd".to_string(), track: None };
+        let mut service = next.services.remove(&2).unwrap();
+        service.identification = 3;
+        next.services.insert(3, service);
+
+        let diff = ServicesDiff::new(&checksums(&iff), &checksums(&next));
+        assert_eq!(diff, ServicesDiff { added: vec![3], removed: vec![2], changed: vec![1] });
+
+        // Changing the dates of a footnote changes the services using it
+        let previous = checksums(&next);
+        next.validity.get_mut(&2).unwrap().clear();
+        assert_eq!(ServicesDiff::new(&previous, &checksums(&next)).changed, vec![3]);
+        assert_eq!(ServicesDiff::new(&checksums(&iff), &checksums(&iff)), ServicesDiff::default());
+
+        // Using another footnote with the same dates changes the service as well, as the footnote is stored
+        let previous = checksums(&next);
+        next.validity.insert(3, next.validity[&2].clone());
+        next.services.get_mut(&3).unwrap().validity = 3;
+        assert_eq!(ServicesDiff::new(&previous, &checksums(&next)).changed, vec![3]);
+
+        // Checksums are stored, so they stay the same between builds
+        assert_eq!(checksums(&iff)[&1], 18341291989857664800);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("timetable.sqlite").to_str().unwrap().to_string();
+        DbConnection::open(&url).unwrap().run_migrations().unwrap();
+        std::env::set_var("DATABASE_URL", &url);
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let iff = IFF::from_path(&path).unwrap();
+        let report = import_iff(&iff).unwrap();
+        assert_eq!(report.services.added.len(), iff.services.len());
+
+        // The timetable from the database is the one built from the services directly
+        let monday = NaiveDate::from_ymd(2021, 1, 4);
+        let timetable = get_timetable_for_day(&monday).unwrap();
+        let expected = iff.timetable_for_day(&monday);
+        assert_eq!(timetable.stops.len(), expected.stops.len());
+        assert_eq!(timetable.trips.iter().sorted().collect::<Vec<_>>(), expected.trips.iter().sorted().collect::<Vec<_>>());
+        assert_eq!(timetable.continuations, expected.continuations);
+
+        // Importing the same version again writes no services
+        assert_eq!(import_iff(&iff).unwrap().services, ServicesDiff::default());
+    }
+
+    #[test]
+    fn fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let iff = IFF::from_zip(ZipArchive::new(File::open(&path).unwrap()).unwrap()).unwrap();
+
+        assert_eq!(iff.stations.len(), 5);
+        assert_eq!(iff.stations["ledn"].name, "Leiden Centraal");
+        assert_eq!(iff.trns_modes["IC"], "Intercity");
+        assert_eq!(iff.attributes["TOES"], "Toeslag");
+        assert_eq!(iff.companies[&100].name, "NS");
+        assert_eq!(iff.countries["B"].name, "Belgie");
+        assert_eq!(iff.timezones.len(), 1);
+        assert_eq!(iff.changes[0].duration, 4);
+        assert_eq!(iff.continuous_connections[0].to_station, "ass");
+        assert_eq!(iff.version.version, 1);
+        assert_eq!(iff.version.valid_till, NaiveDate::from_ymd(2021, 1, 31));
+
+        // Footnote 2 is valid every other day, starting at the first day of the timetable
+        assert_eq!(iff.validity[&1].len(), 28);
+        assert!(iff.validity[&2].contains(&NaiveDate::from_ymd(2021, 1, 4)));
+        assert!(!iff.validity[&2].contains(&NaiveDate::from_ymd(2021, 1, 5)));
+
+        let service = &iff.services[&1];
+        assert_eq!(service.company, 100);
+        assert_eq!(service.service_number, vec![(1234, 1..4)]);
+        assert_eq!(service.attributes, vec!["TOES"]);
+        assert_eq!(service.stops.len(), 4);
+        assert!(matches!(iff.services[&2].stops[1], Stop::Pass { .. }));
+
+        // Both services run on the first day, train 5678 passes Leiden without stopping
+        let timetable = iff.timetable_for_day(&NaiveDate::from_ymd(2021, 1, 4));
+        assert_eq!(timetable.stops.len(), 5);
+        assert_eq!(timetable.trips.len(), 2);
+        assert_eq!(timetable.trips.iter().find(|trip| trip.identifier == 5678).unwrap().connections.len(), 1);
+        assert_eq!(timetable.details[&1234].to_string(), "Intercity (NS), Toeslag");
+
+        let stop = |code: &str| *timetable.stops.iter().find(|(_, stop)| stop.to_string() == code).unwrap().0;
+        assert!(timetable.footpaths[&stop("asd")].contains(&(stop("ass"), 15 * 60)));
+        assert_eq!(timetable.transfer_time(stop("ledn"), 1234, 5678), Some(4 * 60));
+
+        let timetable = iff.timetable_for_day(&NaiveDate::from_ymd(2021, 1, 5));
+        assert_eq!(timetable.trips.len(), 1);
+    }
+
+    #[test]
+    fn optional_files() {
+        // Services are described without the attributes, companies and countries
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let mut zip = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let iff = IFF::from_files(|file| match file {
+            "trnsattr.dat" | "company.dat" | "country.dat" => Err(ZipError::FileNotFound),
+            file => {
+                let mut text = String::new();
+                zip.by_name(file)?.read_to_string(&mut text)?;
+                Ok(text)
+            }
+        }).unwrap();
+
+        assert!(iff.attributes.is_empty() && iff.companies.is_empty() && iff.countries.is_empty());
+        assert_eq!(iff.services.len(), 2);
+        let timetable = iff.timetable_for_day(&NaiveDate::from_ymd(2021, 1, 4));
+        assert_eq!(timetable.details[&1234].to_string(), "Intercity");
+    }
+
+    #[test]
+    fn multiple_days() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let iff = IFF::from_path(&path).unwrap();
+        let calendar = iff.calendar();
+        let (monday, tuesday) = (NaiveDate::from_ymd(2021, 1, 4), NaiveDate::from_ymd(2021, 1, 5));
+
+        // The calendar expands the trips of a day just like the services are converted directly
+        let stops: HashMap<&str, usize> = calendar.stops.iter().map(|(&i, stop)| (stop.code.as_str(), i)).collect();
+        let (trips, continuations) = services_to_trips(&iff.services, &iff.validity, &stops, &monday, |_, time| service_time(&IFF_TIMEZONE, &monday, time, 0), |_, number| number);
+        let timetable = iff.timetable_for_day(&monday);
+        assert_eq!(timetable.trips, trips);
+        assert_eq!(timetable.continuations, continuations);
+
+        // Only train 1234 runs on tuesday, with trip ids of the second day
+        let timetable = calendar.timetable_for_range(&monday, &tuesday);
+        assert_eq!(timetable.trips.len(), 3);
+        assert_eq!(timetable.stops.len(), 5);
+
+        let trip = |id| timetable.trips.iter().find(|trip| trip.identifier == id).unwrap();
+        assert_eq!(trip(TRIP_IDS_PER_DAY + 1234).connections[0].dep_time, trip(1234).connections[0].dep_time + 24 * 3600);
+        assert_eq!(timetable.details[&(TRIP_IDS_PER_DAY + 1234)].to_string(), "Intercity (NS), Toeslag");
+        assert_eq!(timetable.transfer_time(stops["ledn"], TRIP_IDS_PER_DAY + 1234, TRIP_IDS_PER_DAY + 5678), Some(4 * 60));
+    }
+
+    #[test]
+    fn time_window() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let calendar = IFF::from_path(&path).unwrap().calendar();
+        let (monday, tuesday) = (NaiveDate::from_ymd(2021, 1, 4), NaiveDate::from_ymd(2021, 1, 5));
+        let time = |date: &NaiveDate, hour: u16, minute: u16| service_time(&IFF_TIMEZONE, date, hour * 100 + minute, 0);
+
+        // Only the trips running in the window are expanded, with the ids of their day in the calendar
+        let timetable = calendar.timetable_for_window(time(&monday, 12, 40), time(&monday, 13, 10));
+        let mut ids: Vec<usize> = timetable.trips.iter().map(|trip| trip.identifier).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1234, 5678]);
+
+        let timetable = calendar.timetable_for_window(time(&monday, 13, 30), time(&tuesday, 12, 10));
+        let mut ids: Vec<usize> = timetable.trips.iter().map(|trip| trip.identifier).collect();
+        ids.sort();
+        assert_eq!(ids, vec![5678, TRIP_IDS_PER_DAY + 1234]);
+        assert_eq!(timetable.trips, calendar.timetable_for_range(&monday, &tuesday).trips.into_iter()
+            .filter(|trip| ids.contains(&trip.identifier))
+            .collect::<Vec<_>>());
+
+        // A query across the window finds the connection of the next day
+        let stop = |code| *calendar.stops.iter().find(|(_, stop)| stop.code == code).unwrap().0;
+        let alg = CSAVec::new(&timetable);
+        assert_eq!(alg.find_earliest_arrival(stop("asd"), stop("gvc"), time(&monday, 13, 30)).unwrap().arrival(), time(&tuesday, 12, 50));
+    }
+
+    #[test]
+    fn around_midnight() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let calendar = IFF::from_path(&path).unwrap().calendar();
+        let (monday, tuesday) = (NaiveDate::from_ymd(2021, 1, 4), NaiveDate::from_ymd(2021, 1, 5));
+
+        // Train 1234 on tuesday, both trains on wednesday, and nothing of monday since all trips arrive before midnight
+        let timetable = calendar.timetable_around(&tuesday);
+        let mut ids: Vec<usize> = timetable.trips.iter().map(|trip| trip.identifier).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1234, TRIP_IDS_PER_DAY + 1234, TRIP_IDS_PER_DAY + 5678]);
+
+        // Trips of the previous day are kept as long as they have not arrived yet
+        let mut timetable = calendar.timetable_for_range(&tuesday, &tuesday);
+        let previous = calendar.timetable_for_range(&monday, &monday);
+        let arrival = |id| previous.trips.iter().find(|trip| trip.identifier == id).unwrap().connections.last().unwrap().arr_time;
+        let from_time = arrival(1234).max(arrival(5678));
+        let latest = if arrival(1234) >= arrival(5678) { 1234 } else { 5678 };
+
+        timetable.merge_day(2, previous, from_time);
+        assert_eq!(timetable.trips.len(), 2);
+        assert_eq!(timetable.trips[1].identifier, 2 * TRIP_IDS_PER_DAY + latest);
+        assert!(timetable.trips[1].connections.iter().all(|conn| conn.trip_id == 2 * TRIP_IDS_PER_DAY + latest));
+        assert!(timetable.details.contains_key(&(2 * TRIP_IDS_PER_DAY + latest)));
+    }
+
+    #[test]
+    fn times_across_dst() {
+        let utc = |date: &NaiveDate, hour| chrono::Utc.from_utc_datetime(&date.and_hms(hour, 0, 0)).timestamp() as u32;
+
+        // Summer time starts on the 28th of March 2021, noon is at 11:00 UTC the day before and at 10:00 UTC that day
+        let before = NaiveDate::from_ymd(2021, 3, 27);
+        let after = NaiveDate::from_ymd(2021, 3, 28);
+        assert_eq!(service_time(&IFF_TIMEZONE, &before, 1200, 0), utc(&before, 11));
+        assert_eq!(service_time(&IFF_TIMEZONE, &after, 1200, 0), utc(&after, 10));
+
+        // Times past midnight belong to the service date, stations in London are an hour behind
+        assert_eq!(service_time(&IFF_TIMEZONE, &before, 2500, 0), utc(&after, 0));
+        assert_eq!(service_time(&IFF_TIMEZONE, &before, 1000, -1), utc(&before, 10));
+
+        // Night trains on the day itself run before and after the clocks are turned forward at 02:00
+        assert_eq!(service_time(&IFF_TIMEZONE, &after, 100, 0), utc(&after, 0));
+        assert_eq!(service_time(&IFF_TIMEZONE, &after, 300, 0), utc(&after, 1));
+    }
+}
@@ -0,0 +1,376 @@
+use std::{collections::{BTreeMap, BTreeSet}, error::Error, io::{Seek, Write}};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use zip::{ZipWriter, write::FileOptions};
+
+use super::iff_types::{IFF, Service, Stop};
+
+const AGENCY_ID: &str = "NS";
+
+// Route of the services without a transport mode
+const FALLBACK_ROUTE: &str = "OTHER";
+
+#[derive(Debug, Serialize)]
+struct AgencyRecord<'a> {
+    agency_id: &'a str,
+    agency_name: &'a str,
+    agency_url: &'a str,
+    agency_timezone: &'a str
+}
+
+#[derive(Debug, Serialize)]
+struct StopRecord<'a> {
+    stop_id: &'a str,
+    stop_name: &'a str,
+    stop_lat: f64,
+    stop_lon: f64
+}
+
+#[derive(Debug, Serialize)]
+struct RouteRecord<'a> {
+    route_id: &'a str,
+    agency_id: &'a str,
+    route_short_name: &'a str,
+    route_long_name: &'a str,
+    route_type: u8
+}
+
+#[derive(Debug, Serialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: String,
+    exception_type: u8
+}
+
+#[derive(Debug, Serialize)]
+struct TripRecord<'a> {
+    route_id: &'a str,
+    service_id: String,
+    trip_id: String,
+    trip_short_name: usize
+}
+
+#[derive(Debug, Serialize)]
+struct StopTimeRecord<'a> {
+    trip_id: &'a str,
+    arrival_time: String,
+    departure_time: String,
+    stop_id: &'a str,
+    stop_sequence: usize
+}
+
+/// Converts rijksdriehoek coordinates (in meters) to WGS84 latitude and longitude, using the approximation by Schreutelkamp and Strang van Hees
+pub fn rd_to_wgs84(x: f64, y: f64) -> (f64, f64) {
+    let dx = (x - 155_000.0) * 1e-5;
+    let dy = (y - 463_000.0) * 1e-5;
+
+    let lat = 3235.65389 * dy - 32.58297 * dx.powi(2) - 0.2475 * dy.powi(2) - 0.84978 * dx.powi(2) * dy
+        - 0.0655 * dy.powi(3) - 0.01709 * dx.powi(2) * dy.powi(2) - 0.00738 * dx + 0.0053 * dx.powi(4)
+        - 0.00039 * dx.powi(2) * dy.powi(3) + 0.00033 * dx.powi(4) * dy - 0.00012 * dx * dy;
+
+    let lon = 5260.52916 * dx + 105.94684 * dx * dy + 2.45656 * dx * dy.powi(2) - 0.81885 * dx.powi(3)
+        + 0.05594 * dx * dy.powi(3) - 0.05607 * dx.powi(3) * dy + 0.01199 * dy - 0.00256 * dx.powi(3) * dy.powi(2)
+        + 0.00128 * dx * dy.powi(4) + 0.00022 * dy.powi(2) - 0.00022 * dx.powi(2) + 0.00026 * dx.powi(5);
+
+    (52.1551744 + lat / 3600.0, 5.38720621 + lon / 3600.0)
+}
+
+/// Trip identifier in the GTFS feed of a train number within a service
+pub fn gtfs_trip_id(service: usize, number: usize) -> String {
+    format!("{}:{}", service, number)
+}
+
+// IFF times are HHMM, which can exceed 2400 for services running past midnight
+fn format_time(time: u16) -> String {
+    format!("{:02}:{:02}:00", time / 100, time % 100)
+}
+
+// Time in the timezone of the agency of an IFF time at a station of which the times differ `difference` hours, as
+// GTFS gives all times of a trip in the timezone of its agency (stop_timezone is only used for displaying times)
+fn agency_time(time: u16, difference: i64) -> u16 {
+    (time as i64 - difference * 100).max(0) as u16
+}
+
+// Route of a service, which is its (first) transport mode
+fn route_id(service: &Service) -> &str {
+    service.trns_modes.first().map_or(FALLBACK_ROUTE, |mode| mode.as_str())
+}
+
+// The GTFS route type based on the description of a transport mode, rail unless stated otherwise
+fn route_type(description: &str) -> u8 {
+    let description = description.to_lowercase();
+    if description.contains("bus") {
+        3
+    } else if description.contains("metro") {
+        1
+    } else if description.contains("tram") {
+        0
+    } else {
+        2
+    }
+}
+
+fn write_records<W: Write + Seek, T: Serialize>(zip: &mut ZipWriter<W>, file: &str, records: impl IntoIterator<Item=T>) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for record in records {
+        writer.serialize(record)?;
+    }
+
+    zip.start_file(file, FileOptions::default())?;
+    zip.write_all(&writer.into_inner()?)?;
+    Ok(())
+}
+
+impl IFF {
+    // Hours the times of a station differ from the timetable on a date, see `CalendarTimetable::differences`
+    fn difference(&self, station: &str, date: &NaiveDate) -> i64 {
+        self.stations.get(station)
+            .and_then(|station| self.timezones.iter().find(|period| {
+                period.id as u32 == station.timezone as u32 && &period.from_date <= date && date <= &period.to_date
+            }))
+            .map_or(0, |period| period.difference as i64)
+    }
+
+    /// Writes the services running between two dates (inclusive) as a GTFS feed. Every train number of a service becomes
+    /// a trip, the validity of a service becomes its GTFS service and transport modes become routes. Services without a
+    /// transport mode use a fallback route. Times at stations abroad are converted to the timezone of the agency, when
+    /// these conversions change within the period a service is split into a trip per period.
+    pub fn write_gtfs<W: Write + Seek>(&self, writer: W, from: &NaiveDate, to: &NaiveDate) -> Result<(), Box<dyn Error>> {
+        let mut zip = ZipWriter::new(writer);
+
+        // Dates of validities in the exported period
+        let validities: BTreeMap<usize, BTreeSet<&NaiveDate>> = self.validity.iter()
+            .map(|(&id, dates)| (id, dates.iter().filter(|&date| from <= date && date <= to).collect::<BTreeSet<_>>()))
+            .filter(|(_, dates)| !dates.is_empty())
+            .collect();
+
+        let mut services: Vec<_> = self.services.values()
+            .filter(|service| validities.contains_key(&service.validity))
+            .collect();
+
+        services.sort_by_key(|service| service.identification);
+
+        write_records(&mut zip, "agency.txt", vec![AgencyRecord {
+            agency_id: AGENCY_ID,
+            agency_name: "Nederlandse Spoorwegen",
+            agency_url: "https://www.ns.nl",
+            agency_timezone: "Europe/Amsterdam"
+        }])?;
+
+        // IFF gives the x coordinate first, which is stored as the latitude
+        let mut stations: Vec<_> = self.stations.values().collect();
+        stations.sort_by(|a, b| a.code.cmp(&b.code));
+
+        write_records(&mut zip, "stops.txt", stations.into_iter().map(|station| {
+            let (stop_lat, stop_lon) = rd_to_wgs84(station.lat as f64, station.lng as f64);
+            StopRecord { stop_id: &station.code, stop_name: &station.name, stop_lat, stop_lon }
+        }))?;
+
+        let used_modes: BTreeSet<&str> = services.iter().map(|service| route_id(service)).collect();
+        write_records(&mut zip, "routes.txt", used_modes.into_iter().map(|mode| {
+            let description = match mode {
+                FALLBACK_ROUTE => "Other",
+                mode => self.trns_modes.get(mode).map_or(mode, |description| description.as_str())
+            };
+            RouteRecord { route_id: mode, agency_id: AGENCY_ID, route_short_name: mode, route_long_name: description, route_type: route_type(description) }
+        }))?;
+
+        // Dates of every GTFS service, which is a validity or the part of a validity a split service runs on
+        let mut service_dates: BTreeMap<String, Vec<&NaiveDate>> = BTreeMap::new();
+        let mut trips = vec![];
+        let mut stop_times = vec![];
+        for service in services {
+            // Train numbers refer to the stops of the service at which it halts
+            let halts: Vec<(&String, u16, u16)> = service.stops.iter().filter_map(|stop| match stop {
+                Stop::Departure { dep_time, station, .. } => Some((station, *dep_time, *dep_time)),
+                Stop::ShortIntermediate { dep_time, station, .. } => Some((station, *dep_time, *dep_time)),
+                Stop::Intermediate { arr_time, dep_time, station, .. } => Some((station, *arr_time, *dep_time)),
+                Stop::Arrival { arr_time, station, .. } => Some((station, *arr_time, *arr_time)),
+                Stop::Pass { .. } => None
+            }).collect();
+
+            // The dates of the service grouped by the time differences of its halts
+            let mut periods: BTreeMap<Vec<i64>, Vec<&NaiveDate>> = BTreeMap::new();
+            for &date in &validities[&service.validity] {
+                let differences = halts.iter().map(|(station, _, _)| self.difference(station, date)).collect();
+                periods.entry(differences).or_insert_with(Vec::new).push(date);
+            }
+
+            let mut periods: Vec<(Vec<i64>, Vec<&NaiveDate>)> = periods.into_iter().collect();
+            periods.sort_by_key(|(_, dates)| dates[0]);
+
+            for (i, (differences, dates)) in periods.iter().enumerate() {
+                // Only the trips of later periods of a split service get another identifier
+                let suffix = if i == 0 { String::new() } else { format!("-{}", dates[0].format("%Y%m%d")) };
+                let service_id = if periods.len() == 1 { service.validity.to_string() } else { format!("{}:{}{}", service.validity, service.identification, suffix) };
+                service_dates.entry(service_id.clone()).or_insert_with(|| dates.clone());
+
+                for (number, range) in &service.service_number {
+                    let trip_id = gtfs_trip_id(service.identification, *number) + &suffix;
+                    let range = (range.start - 1).min(halts.len())..range.end.min(halts.len());
+
+                    let times = halts[range.clone()].iter().zip(&differences[range])
+                        .map(|(&(station, arr_time, dep_time), &difference)| (station, agency_time(arr_time, difference), agency_time(dep_time, difference)))
+                        .collect::<Vec<_>>();
+
+                    if times.len() < 2 {
+                        continue;
+                    }
+
+                    stop_times.extend(times.into_iter().enumerate().map(|(i, (station, arr_time, dep_time))| (trip_id.clone(), station, arr_time, dep_time, i)));
+                    trips.push(TripRecord {
+                        route_id: route_id(service),
+                        service_id: service_id.clone(),
+                        trip_id,
+                        trip_short_name: *number
+                    });
+                }
+            }
+        }
+
+        write_records(&mut zip, "calendar_dates.txt", service_dates.into_iter().flat_map(|(service_id, dates)| {
+            dates.into_iter().map(move |date| CalendarDateRecord {
+                service_id: service_id.clone(),
+                date: date.format("%Y%m%d").to_string(),
+                exception_type: 1
+            })
+        }))?;
+
+        write_records(&mut zip, "trips.txt", trips)?;
+        write_records(&mut zip, "stop_times.txt", stop_times.iter().map(|(trip_id, station, arr_time, dep_time, i)| StopTimeRecord {
+            trip_id,
+            arrival_time: format_time(*arr_time),
+            departure_time: format_time(*dep_time),
+            stop_id: station,
+            stop_sequence: *i
+        }))?;
+
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinates() {
+        // Onze Lieve Vrouwetoren in Amersfoort is the origin of the rijksdriehoek coordinates
+        let (lat, lon) = rd_to_wgs84(155_000.0, 463_000.0);
+        assert!((lat - 52.1551744).abs() < 1e-6 && (lon - 5.38720621).abs() < 1e-6);
+
+        // Amsterdam Centraal
+        let (lat, lon) = rd_to_wgs84(121_400.0, 487_900.0);
+        assert!((lat - 52.379).abs() < 0.01 && (lon - 4.900).abs() < 0.01);
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(format_time(905), "09:05:00");
+        assert_eq!(format_time(2510), "25:10:00");
+    }
+
+    use std::collections::HashMap;
+    use smol_str::SmolStr;
+    use super::super::iff_types::{Station, TimezonePeriod, Version};
+
+    fn station(code: &str, timezone: i8, lat: i32, lng: i32) -> (SmolStr, Station) {
+        (SmolStr::new(code), Station {
+            code: code.to_string(), name: code.to_uppercase(), country: "NL".to_string(), timezone: timezone.into(),
+            interchange_duration: 2, is_interchange_station: true, lat, lng
+        })
+    }
+
+    // IFF export of services which run on a date and the next date
+    fn iff(date: NaiveDate, stations: Vec<(SmolStr, Station)>, services: Vec<Service>, timezones: Vec<TimezonePeriod>) -> IFF {
+        let mut validity = HashMap::new();
+        validity.insert(1, vec![date, date.succ()].into_iter().collect());
+
+        let mut trns_modes = HashMap::new();
+        trns_modes.insert(SmolStr::new("IC"), "Intercity".to_string());
+
+        IFF {
+            stations: stations.into_iter().collect(),
+            trns_modes,
+            attributes: HashMap::new(),
+            companies: HashMap::new(),
+            countries: HashMap::new(),
+            timezones,
+            services: services.into_iter().map(|service| (service.identification, service)).collect(),
+            validity,
+            changes: vec![],
+            continuous_connections: vec![],
+            version: Version { company: 100, version: 1, valid_from: date, valid_till: date, description: "Test".to_string() }
+        }
+    }
+
+    #[test]
+    fn readable_feed() {
+        let date = NaiveDate::from_ymd(2021, 1, 15);
+
+        // Train 100 runs from esk to amf, continuing as train 200 to asd
+        let iff = iff(date, vec![station("esk", 0, 258_000, 471_000), station("amf", 0, 155_000, 463_000), station("asd", 0, 121_400, 487_900)], vec![Service {
+            identification: 7,
+            company: 100,
+            service_number: vec![(100, 1..2), (200, 2..3)],
+            validity: 1,
+            trns_modes: vec!["IC".to_string()],
+            attributes: vec![],
+            stops: vec![
+                Stop::Departure { dep_time: 1200, station: "esk".to_string(), track: None },
+                Stop::Pass { station: "hgl".to_string() },
+                Stop::Intermediate { arr_time: 1300, dep_time: 1305, station: "amf".to_string(), track: None },
+                Stop::Arrival { arr_time: 1340, station: "asd".to_string(), track: None }
+            ]
+        }], vec![]);
+
+        let feed = tempfile::Builder::new().suffix(".zip").tempfile().unwrap();
+        let path = feed.path();
+        iff.write_gtfs(feed.reopen().unwrap(), &date, &date).unwrap();
+
+        let timetable = crate::data::gtfs::get_timetable_for_day(path, &date).unwrap();
+        assert_eq!(timetable.stops.len(), 3);
+        assert_eq!(timetable.trips.len(), 2);
+        assert_eq!(timetable.trips.iter().map(|trip| trip.connections.len()).sum::<usize>(), 2);
+
+        let trip_ids = crate::data::gtfs::read_trip_ids(path).unwrap();
+        assert!(trip_ids.contains_key("7:100") && trip_ids.contains_key("7:200"));
+    }
+
+    #[test]
+    fn stations_abroad() {
+        use chrono::TimeZone;
+
+        let date = NaiveDate::from_ymd(2021, 1, 15);
+
+        // Times at lon are an hour behind on the first date only, the train does not have a transport mode
+        let timezones = vec![TimezonePeriod { id: 1, difference: -1, from_date: date, to_date: date }];
+        let iff = iff(date, vec![station("asd", 0, 121_400, 487_900), station("lon", 1, 0, 0)], vec![Service {
+            identification: 8,
+            company: 100,
+            service_number: vec![(300, 1..2)],
+            validity: 1,
+            trns_modes: vec![],
+            attributes: vec![],
+            stops: vec![
+                Stop::Departure { dep_time: 1200, station: "asd".to_string(), track: None },
+                Stop::Arrival { arr_time: 1500, station: "lon".to_string(), track: None }
+            ]
+        }], timezones);
+
+        let feed = tempfile::Builder::new().suffix(".zip").tempfile().unwrap();
+        let path = feed.path();
+        iff.write_gtfs(feed.reopen().unwrap(), &date, &date.succ()).unwrap();
+
+        // The train arrives at 16:00 in Amsterdam on the first date, and at 15:00 on the next date
+        let arrival = |date: NaiveDate| crate::data::gtfs::get_timetable_for_day(path, &date).unwrap().trips[0].connections[0].arr_time;
+        let amsterdam = |date: NaiveDate, hour| chrono_tz::Europe::Amsterdam.from_local_datetime(&date.and_hms(hour, 0, 0)).unwrap().timestamp() as u32;
+        assert_eq!(arrival(date), amsterdam(date, 16));
+        assert_eq!(arrival(date.succ()), amsterdam(date.succ(), 15));
+
+        let trip_ids = crate::data::gtfs::read_trip_ids(path).unwrap();
+        assert!(trip_ids.contains_key("8:300") && trip_ids.contains_key("8:300-20210116"));
+    }
+}
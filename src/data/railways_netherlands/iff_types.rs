@@ -4,7 +4,8 @@ use chrono::NaiveDate;
 use diesel::Insertable;
 use smol_str::SmolStr;
 
-use crate::database::schema::stations;
+use crate::database::{DbI8, DbU8, DbU16, DbU32};
+use crate::database::schema::{changes, companies, continuous_connections, countries, iff_versions, stations, timezones};
 
 #[derive(Debug, Clone)]
 pub struct IFF {
@@ -12,8 +13,26 @@ pub struct IFF {
     pub stations: HashMap<SmolStr, Station>,
     
     pub trns_modes: HashMap<SmolStr, String>,
+    pub attributes: HashMap<SmolStr, String>,
+    pub companies: HashMap<usize, Company>,
+    pub countries: HashMap<SmolStr, Country>,
+    pub timezones: Vec<TimezonePeriod>,
     pub services: HashMap<usize, Service>,
-    pub validity: HashMap<usize, HashSet<NaiveDate>>
+    pub validity: HashMap<usize, HashSet<NaiveDate>>,
+    pub changes: Vec<Change>,
+    pub continuous_connections: Vec<ContinuousConnection>,
+    pub version: Version
+}
+
+/// Version of an IFF export from the first line of footnote.dat, with the period in which the timetable is valid
+#[derive(Debug, Clone, PartialEq, Queryable, Insertable)]
+#[table_name = "iff_versions"]
+pub struct Version {
+    pub company: DbU32,
+    pub version: DbU32,
+    pub valid_from: NaiveDate,
+    pub valid_till: NaiveDate,
+    pub description: String
 }
 
 #[derive(Debug, Clone, Queryable, Insertable)]
@@ -27,18 +46,71 @@ pub struct Station {
 
     #[diesel(deserialize_as = "String")]
     pub country: String,
-    pub timezone: i8,
+    pub timezone: DbI8,
 
-    pub interchange_duration: u8,
+    pub interchange_duration: DbU8,
     pub is_interchange_station: bool,
 
     pub lat: i32,
     pub lng: i32,
 }
 
+/// Operator of services from company.dat, the time turn (HHMM) is the time at which its service day starts
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "companies"]
+pub struct Company {
+    pub id: DbU32,
+    pub code: String,
+    pub name: String,
+    pub time_turn: DbU16
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "countries"]
+pub struct Country {
+    pub code: String,
+    pub inland: bool,
+    pub name: String
+}
+
+/// Difference in hours between the times of stations in a timezone and the times in the timetable during a period,
+/// from timezone.dat. Stations refer to the timezone by its id.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "timezones"]
+pub struct TimezonePeriod {
+    pub id: DbU32,
+    pub difference: DbI8,
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate
+}
+
+/// Minimum transfer time in minutes from a train at one station to a train at another (or the same) station from changes.dat,
+/// a train number of 0 applies to every train
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "changes"]
+pub struct Change {
+    pub from_station: String,
+    pub from_service: DbU32,
+    pub to_station: String,
+    pub to_service: DbU32,
+    pub duration: DbU8
+}
+
+/// Connection between two stations that is always available from contconn.dat, e.g. walking or taking the metro,
+/// the duration is in minutes
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "continuous_connections"]
+pub struct ContinuousConnection {
+    pub from_station: String,
+    pub to_station: String,
+    pub duration: DbU8,
+    pub mode: String
+}
+
 #[derive(Debug, Clone)]
 pub struct Service {
     pub identification: usize,
+    pub company: usize,
     pub service_number: Vec<(usize, Range<usize>)>,
     
     pub validity: usize,
@@ -49,7 +121,7 @@ pub struct Service {
     pub stops: Vec<Stop>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum Stop {
     Departure { dep_time: u16, station: String, track: Option<String> },
     ShortIntermediate { dep_time: u16, station: String, track: Option<String> },
@@ -4,10 +4,10 @@ use chrono::NaiveDate;
 use diesel::{dsl::max, prelude::*};
 use quick_xml::de::from_str;
 
-use crate::{data::zeromq, types::{Connection, Trip, TripUpdate}};
+use crate::{data::zeromq, types::{Connection, DelayDistribution, Delays, Timetable, TrainSection, Trip, TripUpdate, TRIP_IDS_PER_DAY}};
 use crate::database;
 
-use super::{iff, rit_message_types::RITMessage};
+use super::{iff, dvs_message_types::DVSMessage, rit_matching::{MatchReport, TripMatch, TripMatcher}, rit_message_types::RITMessage};
 
 #[allow(dead_code)]
 pub const ENVELOPES_ALL: &[&[u8]] = &[
@@ -64,119 +64,216 @@ pub fn dvs_stream(envelopes: &'static [&[u8]]) -> Vec<JoinHandle<Result<(), Box<
     
 }
 
-pub fn read_dvs_to_updates(date: &NaiveDate) -> Result<Vec<Vec<TripUpdate>>, Box<dyn Error>> {
+/// Updates of the trips of a day from the stored RIT messages, together with a report of the trains which could not be
+/// matched to a trip (see `rit_matching`). Messages of the adjacent dates are included as well, since trains planned
+/// after midnight can belong to another service day than the date of their messages. The sections of splitting trains
+/// in the DVS messages restrict a trip to the stops its own section still calls at (see `Trip::with_sections`).
+pub fn read_dvs_to_updates(date: &NaiveDate) -> Result<(Vec<Vec<TripUpdate>>, MatchReport), Box<dyn Error>> {
 
-    // Get timetable for given data
-    let timetable = iff::get_timetable_for_day(&date)?;
+    // Get timetable for given data, the trips of the adjacent days are only used for matching
+    let timetable = iff::get_timetable_around(date)?;
+    let matcher = TripMatcher::new(&timetable);
 
     // We create a connections list using a Binary Tree, to make sure that the connections are always ordered.
     // This list should always contain the 'newest' known timetable
     let mut trips: HashMap<usize, BTreeSet<Connection>> = timetable.trips.iter()
+        .filter(|trip| trip.identifier < TRIP_IDS_PER_DAY)
         .map(|trip| (trip.identifier, trip.connections.clone().into_iter().collect())).collect();
     let stops: HashMap<String, usize> = timetable.stops.iter().map(|(stop_id, stop)| (stop.to_string(), *stop_id)).collect();
 
     let mut updates = vec![];
+    let mut matches: HashMap<(NaiveDate, usize), TripMatch> = HashMap::new();
+    let mut report = MatchReport::default();
+
+    // RIT messages contain the stops and times of trips, DVS messages are only used for the sections of splitting
+    // trains. DVS info might return departure information a bit earlier, but does not contain more information.
+    for_each_message(&["/RIG/InfoPlusRITInterface2", "/RIG/InfoPlusDVSInterface4"], |envelope, message| {
+        match envelope {
+            "/RIG/InfoPlusRITInterface2" => {
+
+                // We read the message to a RIT message type using Serde
+                let rit_message: RITMessage = from_str(message)?;
+                let rit_date = rit_message.message.rit.date;
+
+                if (rit_date - *date).num_days().abs() > 1 {
+                    return Ok(());
+                }
+
+                // Go through each seperate trip in the message
+                for part in rit_message.message.rit.trip.parts.iter() {
+
+                    // A train is matched once, using the planned stops and departure which stay the same in later messages
+                    let result = matches.entry((rit_date, part.trip_id)).or_insert_with(|| {
+                        let result = part.planned_run(&stops).map_or(TripMatch::Unmatched, |run| matcher.find(&run));
+
+                        // Messages of the adjacent dates are only reported when they concern a trip of this day
+                        let this_day = match &result {
+                            TripMatch::Matched(trip_id) => *trip_id < TRIP_IDS_PER_DAY,
+                            TripMatch::Ambiguous(trip_ids) => trip_ids.iter().any(|&trip_id| trip_id < TRIP_IDS_PER_DAY),
+                            _ => false
+                        };
+                        if this_day || &rit_date == date {
+                            report.record(rit_date, part.trip_id, &result);
+                        }
+                        result
+                    });
+
+                    // Trips of this day have their train number as id, extra trains are added with their train number
+                    match result {
+                        TripMatch::Matched(trip_id) if *trip_id < TRIP_IDS_PER_DAY => {},
+                        TripMatch::New if &rit_date == date => {},
+                        _ => continue
+                    }
+
+                    match part.to_trip(&stops) {
+                        Some(rit_trip) => updates.push(TripUpdate::from_new_trip(&mut trips, rit_trip)),
+                        None => println!("{}", message) // Log trips which cannot be serialized
+                    }
+                }
+            },
+            "/RIG/InfoPlusDVSInterface4" => {
+                let dvs_message: DVSMessage = match from_str(message) {
+                    Ok(dvs_message) => dvs_message,
+                    Err(_) => return Ok(())
+                };
+
+                let dvs = &dvs_message.message.dvs;
+
+                // Trains are found through the trip that their RIT messages were matched to, like the trains of the RIT
+                // messages, as a train number of another date can be a trip of this day. Trains without a matched RIT
+                // message are skipped.
+                let trip_id = match matches.get(&(dvs.date, dvs.train.trip_id)) {
+                    Some(TripMatch::Matched(trip_id)) if *trip_id < TRIP_IDS_PER_DAY => *trip_id,
+                    Some(TripMatch::New) if &dvs.date == date => dvs.train.trip_id,
+                    _ => return Ok(())
+                };
+
+                let station = match stops.get(&dvs.station.code.to_lowercase()) {
+                    Some(&station) => station,
+                    None => return Ok(())
+                };
+
+                let trip = match trips.get(&trip_id) {
+                    Some(connections) => Trip { identifier: trip_id, connections: connections.iter().cloned().collect() },
+                    None => return Ok(())
+                };
+
+                let sections: Vec<TrainSection> = dvs.train.sections.iter().filter_map(|section| section.to_section(&stops)).collect();
+                if let Some(section) = trip.own_section(station, &sections) {
+                    let sectioned = trip.for_section(station, section);
+                    if sectioned != trip {
+                        updates.push(TripUpdate::from_new_trip(&mut trips, sectioned));
+                    }
+                }
+            },
+            _ => {}
+        }
+
+        Ok(())
+    })?;
+    
+    Ok((updates, report))
+}
+
+/// Derives the observed arrival delays (in seconds) per train number and station from all stored RIT messages.
+/// A train is updated many times a day, so only the last known delay per train, date and station is used.
+pub fn read_dvs_to_delays() -> Result<HashMap<(usize, String), Vec<u32>>, Box<dyn Error>> {
+    let mut last_delays: HashMap<(usize, NaiveDate, String), u32> = HashMap::new();
+
+    for_each_message(&["/RIG/InfoPlusRITInterface2"], |_, message| {
+        // Messages which cannot be read are skipped, they would not change the distributions much anyway
+        let rit_message: RITMessage = match from_str(message) {
+            Ok(rit_message) => rit_message,
+            Err(_) => return Ok(())
+        };
+
+        for part in &rit_message.message.rit.trip.parts {
+            for (station, delay) in part.arrival_delays() {
+                last_delays.insert((part.trip_id, rit_message.message.rit.date, station), delay);
+            }
+        }
+
+        Ok(())
+    })?;
+
+    let mut delays: HashMap<(usize, String), Vec<u32>> = HashMap::new();
+    for ((train, _, station), delay) in last_delays {
+        delays.entry((train, station)).or_insert_with(Vec::new).push(delay);
+    }
+
+    Ok(delays)
+}
+
+/// Converts delays per train number and station code to delay distributions for the trips and stops of a timetable
+pub fn delays_for_timetable(timetable: &Timetable, observed: HashMap<(usize, String), Vec<u32>>) -> Delays {
+    let stops: HashMap<String, usize> = timetable.stops.iter().map(|(stop_id, stop)| (stop.to_string(), *stop_id)).collect();
+
+    observed.into_iter()
+        .filter_map(|((train, station), delays)| stops.get(&station).map(|&stop| ((train, stop), DelayDistribution::new(delays))))
+        .collect()
+}
 
-    // Query through all messages
+/// Reads the parts of splitting trains (TreinVleugel) per train number and departure station from the stored DVS messages of a day.
+/// Later messages replace earlier ones, such that the sections reflect the latest known stops of each part.
+pub fn read_dvs_to_sections(date: &NaiveDate, timetable: &Timetable) -> Result<HashMap<(usize, usize), Vec<TrainSection>>, Box<dyn Error>> {
+    let stops: HashMap<String, usize> = timetable.stops.iter().map(|(stop_id, stop)| (stop.to_string(), *stop_id)).collect();
+    let mut sections = HashMap::new();
+
+    for_each_message(&["/RIG/InfoPlusDVSInterface4"], |_, message| {
+        let dvs_message: DVSMessage = match from_str(message) {
+            Ok(dvs_message) => dvs_message,
+            Err(_) => return Ok(())
+        };
+
+        let dvs = &dvs_message.message.dvs;
+        if &dvs.date != date {
+            return Ok(());
+        }
+
+        let station = match stops.get(&dvs.station.code.to_lowercase()) {
+            Some(&station) => station,
+            None => return Ok(())
+        };
+
+        let train_sections: Vec<TrainSection> = dvs.train.sections.iter().filter_map(|section| section.to_section(&stops)).collect();
+        if train_sections.is_empty() {
+            sections.remove(&(dvs.train.trip_id, station));
+        } else {
+            sections.insert((dvs.train.trip_id, station), train_sections);
+        }
+
+        Ok(())
+    })?;
+
+    Ok(sections)
+}
+
+/// Calls `f` with the envelope and content of every stored message of the given envelopes, in the order they were received.
+/// The messages are loaded in batches, as the table holds a message for every update of every train.
+fn for_each_message(envelopes: &[&str], mut f: impl FnMut(&str, &str) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
     use database::schema::dvs_messages::dsl::*;
-    #[derive(Debug, Queryable)]
-    struct MessageQuery { message: Option<String>, envelope: Option<String> }
 
     const STEP: usize = 5000;
 
     let conn = database::establish_connection();
-    let message_count = dvs_messages.select(max(id)).first::<Option<i32>>(&conn)?.unwrap() as usize;
+    let message_count = dvs_messages.select(max(id)).first::<Option<i32>>(&conn)?.unwrap_or(0) as usize;
 
-    for i in (0..message_count).step_by(STEP) {
-        let messages: Vec<MessageQuery> = dvs_messages.select((message, envelope))
+    for i in (0..=message_count).step_by(STEP) {
+        let messages: Vec<(Option<String>, Option<String>)> = dvs_messages.select((envelope, message))
             .filter(id.ge(i as i32))
             .filter(id.lt((i + STEP) as i32))
-            // For now we filter on one type of message, later on this could be expended to take a look at DVS info
-            // DVS info should not contain more information but might return departure information a bit earlier
-            .filter(envelope.eq("/RIG/InfoPlusRITInterface2")) 
+            .filter(envelope.eq_any(envelopes.to_vec()))
             .order(id.asc())
-            .load::<MessageQuery>(&conn)?;
-
-        for msg in messages {
-            match msg.envelope.as_deref() {
-                Some("/RIG/InfoPlusRITInterface2") => {
-
-                    // We read the message to a RIT message type using Serde
-                    let rit_message: RITMessage = from_str(&msg.message.as_deref().unwrap())?;
-
-                    if &rit_message.message.rit.date != date {
-                        continue;
-                    }
-                    
-                    // Go through each seperate trip in the message
-                    for rit_trip in rit_message.message.rit.trip.parts.iter()
-                        .map(|t| t.to_trip(&stops)).filter(|t| {
-                            if t.is_none() {
-                                println!("{}", &msg.message.as_deref().unwrap()); // Log trips which cannot be serialized
-                            }
-                            t.is_some()
-                        }).map(|t| t.unwrap()) {
-
-                        let mut update = vec![];
-
-                        // TODO: One current bug is the fact that date+train id for RIT messages does not always correspond
-                        // to the same date+train id in IFF. This is the case for some trains that are planned in a timeschedule day
-                        // after midnight.
-
-                        // Discussion: Not sure why this is done, this seems like more of a problem of different systems not corresponding
-                        // to the same format making it impossible to exactly match the incoming update to the corresponding trip
-                        // Possible solutions:
-                        // - Rewrite IFF to timetable trips that fully take place the next day, on the next day
-                        // - Match trip using original planned departure time (requires keeping an original lookup of some sort)
-                        // - Acquire information for NS Reizigers to the expected way IFF and RIT should be matched
-                        // The first two solutions are also not guaranteed to be a solution, more research should be done for that.
-                        // Funally enough the same problem is also found on https://treinposities.nl, e.g. for the 1410
-
-                        if let Some(trip) = trips.get_mut(&rit_trip.identifier) {
-                            let rit_trip_set: BTreeSet<Connection> = rit_trip.connections.clone().into_iter().collect();
-                            
-                            let old_trip = Trip {
-                                identifier: rit_trip.identifier,
-                                connections: trip.clone().into_iter().collect()
-                            };
-
-                            // Should we remove the whole trip?
-                            if rit_trip.connections.is_empty() {
-                                trips.remove(&rit_trip.identifier);
-                                update.push(TripUpdate::DeleteTrip { trip: old_trip.clone() });
-                                updates.push(update);
-                                continue;
-                            }
-
-                            // We try to discover the differences between the rit_trip and the current known trip
-                            let del_connections: Vec<Connection> = trip.difference(&rit_trip_set).map(|x| x.clone()).collect();
-                            let new_connections: Vec<Connection> = rit_trip_set.difference(trip).map(|x| x.clone()).collect();
-
-                            for new_connection in new_connections {
-                                trip.insert(new_connection.clone());
-                                update.push(TripUpdate::AddConnection { old_trip: old_trip.clone(), new_trip: rit_trip.clone(), connection: new_connection });
-                            }
-
-                            for del_connection in del_connections {
-                                trip.remove(&del_connection);
-                                update.push(TripUpdate::DeleteConnection { old_trip: old_trip.clone(), new_trip: rit_trip.clone(), connection: del_connection });
-                            }
-
-                        } else {
-                            // This is a new trip, we add the trip.
-                            trips.insert(rit_trip.identifier, rit_trip.connections.clone().into_iter().collect());
-                            update.push(TripUpdate::AddTrip { trip: rit_trip });
-                        }
+            .load(&conn)?;
 
-                        updates.push(update);
-                    }
-                },
-                _ => {}
+        for (env, msg) in messages {
+            if let (Some(env), Some(msg)) = (env, msg) {
+                f(&env, &msg)?;
             }
         }
 
-        println!("Finished: {}", i + 1000);
+        println!("Finished: {}", i + STEP);
     }
-    
-    Ok(updates)
-}
\ No newline at end of file
+
+    Ok(())
+}
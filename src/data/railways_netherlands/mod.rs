@@ -1,6 +1,9 @@
-mod iff_types;
+pub mod iff_types;
 pub mod iff;
+mod iff_gtfs;
+pub mod rit_gtfs_realtime;
 
 mod dvs_message_types;
 mod rit_message_types;
+pub mod rit_matching;
 pub mod info_plus;
\ No newline at end of file
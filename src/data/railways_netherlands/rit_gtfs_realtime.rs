@@ -0,0 +1,316 @@
+use std::{collections::HashMap, error::Error, fs, io::Read, path::PathBuf, thread::JoinHandle, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use chrono::{Local, NaiveDate};
+use prost::Message;
+use quick_xml::de::from_str;
+
+use crate::data::{gtfs_realtime_types as rt, zeromq};
+
+use super::{info_plus::ENVELOPES_RIT, iff, rit_matching::{TripMatch, TripMatcher, service_trip}, rit_message_types::{RITDateTime, RITInformation, RITLogicalPart, RITMessage, RITState}};
+
+/// Keeps the latest known state of every train from the RIT messages, which can be written as a GTFS-RT TripUpdates feed.
+/// Trains are matched to the trips of a GTFS export (see `IFF::write_gtfs`) of the same IFF data by a `TripLookup`.
+#[derive(Debug, Default)]
+pub struct FeedProducer {
+    entities: HashMap<(NaiveDate, usize), rt::FeedEntity>
+}
+
+fn find(times: &[RITDateTime], state: RITState) -> Option<&RITDateTime> {
+    times.iter().find(|time| time.state == state)
+}
+
+fn stop_time_event(times: &[RITDateTime]) -> Option<rt::StopTimeEvent> {
+    let current = find(times, RITState::Current)?;
+    let planned = find(times, RITState::Planned);
+
+    Some(rt::StopTimeEvent {
+        delay: planned.map(|planned| (current.date - planned.date).num_seconds() as i32),
+        time: Some(current.date.timestamp()),
+        uncertainty: None
+    })
+}
+
+/// Converts a logical part of a RIT message to a GTFS-RT trip update, trips without a trip identifier are not in the
+/// timetable and are added. Stops are identified by their station code.
+pub fn part_to_trip_update(part: &RITLogicalPart, date: &NaiveDate, trip_id: Option<&String>) -> rt::TripUpdate {
+    let mut stop_time_update = vec![];
+    let mut cancelled = true;
+
+    for stop in &part.stops {
+        let stopping = |state| stop.stopping.iter().find(|s| s.state == state).map(|s| s.stopping);
+        let current = stopping(RITState::Current).unwrap_or(false);
+        let planned = stopping(RITState::Planned).unwrap_or(current);
+
+        let update = if current {
+            cancelled = false;
+            rt::StopTimeUpdate {
+                arrival: stop_time_event(&stop.arr_time),
+                departure: stop_time_event(&stop.dep_time),
+                ..Default::default()
+            }
+        } else if planned && trip_id.is_some() {
+            rt::StopTimeUpdate {
+                schedule_relationship: Some(rt::StopScheduleRelationship::Skipped as i32),
+                ..Default::default()
+            }
+        } else {
+            continue;
+        };
+
+        stop_time_update.push(rt::StopTimeUpdate {
+            stop_id: Some(stop.station.code.to_lowercase()),
+            ..update
+        });
+    }
+
+    let schedule_relationship = match trip_id {
+        Some(_) if cancelled => rt::TripScheduleRelationship::Canceled,
+        Some(_) => rt::TripScheduleRelationship::Scheduled,
+        None => rt::TripScheduleRelationship::Added
+    };
+
+    rt::TripUpdate {
+        trip: rt::TripDescriptor {
+            trip_id: Some(trip_id.cloned().unwrap_or_else(|| part.trip_id.to_string())),
+            start_date: Some(date.format("%Y%m%d").to_string()),
+            schedule_relationship: Some(schedule_relationship as i32),
+            ..Default::default()
+        },
+        stop_time_update: if cancelled { vec![] } else { stop_time_update },
+        timestamp: Some(Local::now().timestamp() as u64),
+        delay: None
+    }
+}
+
+impl FeedProducer {
+    /// Replaces the state of the trains in a RIT message. For every train `trip` gives the service date and GTFS trip
+    /// identifier of its trip, or the date of the message without an identifier for extra trains. Trains for which it
+    /// gives nothing are left out.
+    pub fn update(&mut self, rit: &RITInformation, mut trip: impl FnMut(&RITLogicalPart) -> Option<(NaiveDate, Option<String>)>) {
+        for part in &rit.trip.parts {
+            let (date, trip_id) = match trip(part) {
+                Some(trip) => trip,
+                None => continue
+            };
+
+            self.entities.insert((rit.date, part.trip_id), rt::FeedEntity {
+                id: format!("{}:{}", rit.date.format("%Y%m%d"), part.trip_id),
+                is_deleted: None,
+                trip_update: Some(part_to_trip_update(part, &date, trip_id.as_ref()))
+            });
+        }
+    }
+
+    /// Forgets all trains running before the given date
+    pub fn prune(&mut self, date: &NaiveDate) {
+        self.entities.retain(|(trip_date, _), _| trip_date >= date);
+    }
+
+    /// The full dataset of trip updates, ordered by date and train number
+    pub fn feed(&self) -> rt::FeedMessage {
+        let mut keys: Vec<&(NaiveDate, usize)> = self.entities.keys().collect();
+        keys.sort();
+
+        rt::FeedMessage {
+            header: rt::FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                incrementality: Some(rt::Incrementality::FullDataset as i32),
+                timestamp: Some(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+            },
+            entity: keys.into_iter().map(|key| self.entities[key].clone()).collect()
+        }
+    }
+}
+
+/// Matches the trains of RIT messages to the trips of the GTFS export (see `rit_matching`). The timetable around the date
+/// of the messages and the GTFS trip identifiers of a service date are loaded once, also when loading them fails, such
+/// that the database is not queried again for every message.
+#[derive(Debug, Default)]
+pub struct TripLookup {
+    // Matcher and stop ids of the timetable around a date of messages
+    matchers: HashMap<NaiveDate, Option<(TripMatcher, HashMap<String, usize>)>>,
+    trip_ids: HashMap<NaiveDate, HashMap<usize, String>>,
+    matches: HashMap<(NaiveDate, usize), TripMatch>
+}
+
+impl TripLookup {
+    /// Service date and GTFS trip identifier of the trip of a train in a message of a date, see `FeedProducer::update`.
+    /// Trains which match no trip or multiple trips are left out.
+    pub fn find(&mut self, date: &NaiveDate, part: &RITLogicalPart) -> Option<(NaiveDate, Option<String>)> {
+        let (matcher, stops) = self.matchers.entry(*date).or_insert_with(|| match iff::get_timetable_around(date) {
+            Ok(timetable) => Some((
+                TripMatcher::new(&timetable),
+                timetable.stops.iter().map(|(stop_id, stop)| (stop.to_string(), *stop_id)).collect()
+            )),
+            Err(err) => {
+                println!("Could not load trips around {}: {}", date, err);
+                None
+            }
+        }).as_ref()?;
+
+        // A train is matched once, using the planned stops and departure which stay the same in later messages
+        let result = self.matches.entry((*date, part.trip_id))
+            .or_insert_with(|| part.planned_run(stops).map_or(TripMatch::Unmatched, |run| matcher.find(&run)));
+
+        match result {
+            TripMatch::Matched(trip_id) => {
+                let (service_date, identifier) = service_trip(date, *trip_id);
+                let trip_ids = self.trip_ids.entry(service_date).or_insert_with(|| {
+                    iff::gtfs_trip_ids_for_day(&service_date).unwrap_or_else(|err| {
+                        println!("Could not load trips of {}: {}", service_date, err);
+                        HashMap::new()
+                    })
+                });
+
+                Some((service_date, Some(trip_ids.get(&identifier)?.clone())))
+            },
+            TripMatch::New => Some((*date, None)),
+            TripMatch::Unmatched | TripMatch::Ambiguous(_) => None
+        }
+    }
+
+    /// Forgets all dates before the given date
+    pub fn prune(&mut self, date: &NaiveDate) {
+        self.matchers.retain(|matcher_date, _| matcher_date >= date);
+        self.trip_ids.retain(|trip_date, _| trip_date >= date);
+        self.matches.retain(|(match_date, _), _| match_date >= date);
+    }
+}
+
+// Writes the feed to a temporary file first, such that readers never see a partially written feed
+fn write_snapshot(feed: &rt::FeedMessage, output: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut bytes = vec![];
+    feed.encode(&mut bytes)?;
+
+    let temp = output.with_extension("tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(temp, output)?;
+    Ok(())
+}
+
+/// Listens for RIT messages and writes a snapshot of the resulting GTFS-RT feed to `output` at most every `interval`
+pub fn gtfs_realtime_stream(output: PathBuf, interval: Duration) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let subscription = zeromq::subscribe("tcp://pubsub.besteffort.ndovloket.nl:7664", ENVELOPES_RIT).unwrap();
+
+        let mut producer = FeedProducer::default();
+        let mut lookup = TripLookup::default();
+        let mut last_snapshot = Instant::now();
+
+        println!("Writing GTFS-RT feed to {:?}", output);
+
+        loop {
+            let mut message = String::new();
+            zeromq::receive(&subscription).unwrap().read_to_string(&mut message).unwrap();
+
+            // Messages which cannot be read are logged and skipped
+            let rit_message: RITMessage = match from_str(&message) {
+                Ok(rit_message) => rit_message,
+                Err(err) => {
+                    println!("Could not read RIT message: {}", err);
+                    continue;
+                }
+            };
+
+            let date = rit_message.message.rit.date;
+            producer.update(&rit_message.message.rit, |part| lookup.find(&date, part));
+
+            if last_snapshot.elapsed() >= interval {
+                let yesterday = Local::today().naive_local().pred();
+                producer.prune(&yesterday);
+
+                // Trains of yesterday can belong to the service day before
+                lookup.prune(&yesterday.pred());
+
+                if let Err(err) = write_snapshot(&producer.feed(), &output) {
+                    println!("Could not write GTFS-RT feed: {}", err);
+                }
+
+                last_snapshot = Instant::now();
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone};
+    use super::super::rit_message_types::{RITLogical, RITLogicalPartStop, RITStation, RITStopping};
+
+    fn times(planned: Option<DateTime<Local>>, current: Option<DateTime<Local>>) -> Vec<RITDateTime> {
+        planned.map(|date| RITDateTime { state: RITState::Planned, date }).into_iter()
+            .chain(current.map(|date| RITDateTime { state: RITState::Current, date }))
+            .collect()
+    }
+
+    fn stop(code: &str, arr: Option<(u32, u32)>, dep: Option<(u32, u32)>, stopping: bool) -> RITLogicalPartStop {
+        let time = |minute| Some(Local.ymd(2021, 1, 15).and_hms(12, minute, 0));
+
+        RITLogicalPartStop {
+            station: RITStation { code: code.to_string() },
+            arr_time: arr.map_or(vec![], |(planned, current)| times(time(planned), time(current))),
+            dep_time: dep.map_or(vec![], |(planned, current)| times(time(planned), time(current))),
+            stopping: vec![
+                RITStopping { state: RITState::Planned, stopping: true },
+                RITStopping { state: RITState::Current, stopping }
+            ]
+        }
+    }
+
+    #[test]
+    fn delays_and_skipped_stops() {
+        let part = RITLogicalPart {
+            trip_id: 100,
+            stops: vec![
+                stop("ESK", None, Some((0, 2)), true),
+                stop("HGL", Some((10, 12)), Some((11, 13)), false),
+                stop("AMF", Some((50, 53)), None, true)
+            ]
+        };
+
+        let date = NaiveDate::from_ymd(2021, 1, 15);
+        let update = part_to_trip_update(&part, &date, Some(&"7:100".to_string()));
+
+        assert_eq!(update.trip.trip_id.as_deref(), Some("7:100"));
+        assert_eq!(update.trip.schedule_relationship, Some(rt::TripScheduleRelationship::Scheduled as i32));
+        assert_eq!(update.stop_time_update.len(), 3);
+
+        assert_eq!(update.stop_time_update[0].stop_id.as_deref(), Some("esk"));
+        assert_eq!(update.stop_time_update[0].departure.as_ref().unwrap().delay, Some(120));
+        assert_eq!(update.stop_time_update[1].schedule_relationship, Some(rt::StopScheduleRelationship::Skipped as i32));
+        assert_eq!(update.stop_time_update[2].arrival.as_ref().unwrap().delay, Some(180));
+
+        // Without a matching trip the train is added, without the stops it skips
+        let update = part_to_trip_update(&part, &date, None);
+        assert_eq!(update.trip.schedule_relationship, Some(rt::TripScheduleRelationship::Added as i32));
+        assert_eq!(update.stop_time_update.len(), 2);
+    }
+
+    #[test]
+    fn service_date_of_matched_trip() {
+        let part = |trip_id| RITLogicalPart {
+            trip_id,
+            stops: vec![stop("ESK", None, Some((0, 2)), true), stop("AMF", Some((50, 53)), None, true)]
+        };
+
+        // Train 100 in the message of the 16th belongs to the service day before, train 200 is not matched
+        let rit = RITInformation {
+            trip_id: 100,
+            date: NaiveDate::from_ymd(2021, 1, 16),
+            trip: RITLogical { trip_id: "100".to_string(), parts: vec![part(100), part(200)] }
+        };
+        let mut producer = FeedProducer::default();
+        producer.update(&rit, |part| match part.trip_id {
+            100 => Some((NaiveDate::from_ymd(2021, 1, 15), Some("7:100".to_string()))),
+            _ => None
+        });
+
+        let feed = producer.feed();
+        assert_eq!(feed.entity.len(), 1);
+
+        let trip = &feed.entity[0].trip_update.as_ref().unwrap().trip;
+        assert_eq!(trip.trip_id.as_deref(), Some("7:100"));
+        assert_eq!(trip.start_date.as_deref(), Some("20210115"));
+    }
+}
@@ -0,0 +1,203 @@
+// Matching of RIT messages to the trips of the IFF timetable. The date and train number of a RIT message do not always
+// correspond to the service date and train number in IFF, e.g. for trains planned after midnight which can belong to the
+// previous service day. Instead a trip is matched by its train number on adjacent service days, the planned departure at
+// its first stop and how similar its planned stops are.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::types::{Timetable, TRIP_IDS_PER_DAY, trip_identifier};
+
+/// Maximum difference in seconds between the planned departure of a RIT message and the departure of a matching trip
+pub const MAX_DEPARTURE_DIFFERENCE: u32 = 30 * 60;
+
+/// Minimum similarity of the planned stops of a RIT message and the stops of a matching trip, see `stop_similarity`
+pub const MIN_STOP_SIMILARITY: f64 = 0.5;
+
+/// Planned run of a train in a RIT message, see `RITLogicalPart::planned_run`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedRun {
+    pub train: usize,
+    pub departure: u32,
+    pub stops: Vec<usize>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TripMatch {
+    /// Id of the matching trip
+    Matched(usize),
+
+    /// No trip has the train number, such that it is an extra train
+    New,
+
+    /// There are trips with the train number, but none of them departs around the planned time with similar stops
+    Unmatched,
+
+    /// Multiple trips match equally well
+    Ambiguous(Vec<usize>)
+}
+
+/// Length of the longest common subsequence of two stop sequences relative to the longest of them, 1.0 for equal stops
+pub fn stop_similarity(a: &[usize], b: &[usize]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, stop_a) in a.iter().enumerate() {
+        for (j, stop_b) in b.iter().enumerate() {
+            lengths[i + 1][j + 1] = if stop_a == stop_b {
+                lengths[i][j] + 1
+            } else {
+                lengths[i][j + 1].max(lengths[i + 1][j])
+            };
+        }
+    }
+
+    lengths[a.len()][b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+/// Trips of a timetable by train number, which can contain multiple service days (see `iff::get_timetable_around`)
+#[derive(Debug)]
+pub struct TripMatcher {
+    trips: HashMap<usize, Vec<(usize, u32, Vec<usize>)>>
+}
+
+impl TripMatcher {
+    pub fn new(timetable: &Timetable) -> Self {
+        let mut trips: HashMap<usize, Vec<(usize, u32, Vec<usize>)>> = HashMap::new();
+        for trip in timetable.trips.iter().filter(|trip| !trip.connections.is_empty()) {
+            let stops = std::iter::once(trip.connections[0].dep_stop)
+                .chain(trip.connections.iter().map(|conn| conn.arr_stop))
+                .collect();
+
+            trips.entry(trip_identifier(trip.identifier)).or_insert_with(Vec::new)
+                .push((trip.identifier, trip.connections[0].dep_time, stops));
+        }
+
+        TripMatcher { trips }
+    }
+
+    /// Trip of a planned run, preferring the most similar stops and then the closest departure
+    pub fn find(&self, run: &PlannedRun) -> TripMatch {
+        let candidates = match self.trips.get(&run.train) {
+            Some(candidates) => candidates,
+            None => return TripMatch::New
+        };
+
+        let mut scored: Vec<(f64, u32, usize)> = candidates.iter()
+            .map(|(trip_id, departure, stops)| (stop_similarity(&run.stops, stops), (*departure as i64 - run.departure as i64).unsigned_abs() as u32, *trip_id))
+            .filter(|&(similarity, difference, _)| similarity >= MIN_STOP_SIMILARITY && difference <= MAX_DEPARTURE_DIFFERENCE)
+            .collect();
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        match scored.first() {
+            None => TripMatch::Unmatched,
+            Some(&(similarity, difference, trip_id)) => {
+                let best: Vec<usize> = scored.iter()
+                    .filter(|&&(s, d, _)| s == similarity && d == difference)
+                    .map(|&(_, _, trip_id)| trip_id)
+                    .collect();
+
+                if best.len() > 1 { TripMatch::Ambiguous(best) } else { TripMatch::Matched(trip_id) }
+            }
+        }
+    }
+}
+
+/// Service date and identifier of a trip of a timetable around `date` (see `iff::get_timetable_around`), in which the trips
+/// of the next date are the first day and the trips of the previous date the second day
+pub fn service_trip(date: &NaiveDate, trip_id: usize) -> (NaiveDate, usize) {
+    let service_date = match trip_id / TRIP_IDS_PER_DAY {
+        0 => *date,
+        1 => date.succ(),
+        _ => date.pred()
+    };
+
+    (service_date, trip_identifier(trip_id))
+}
+
+/// Trains of RIT messages which could not be matched to a single trip, by RIT date and train number
+#[derive(Debug, Default, Serialize)]
+pub struct MatchReport {
+    pub matched: usize,
+
+    /// Extra trains, which are added as new trips
+    pub added: Vec<(NaiveDate, usize)>,
+    pub unmatched: Vec<(NaiveDate, usize)>,
+    pub ambiguous: Vec<(NaiveDate, usize, Vec<usize>)>
+}
+
+impl MatchReport {
+    pub fn record(&mut self, date: NaiveDate, train: usize, result: &TripMatch) {
+        match result {
+            TripMatch::Matched(_) => self.matched += 1,
+            TripMatch::New => self.added.push((date, train)),
+            TripMatch::Unmatched => self.unmatched.push((date, train)),
+            TripMatch::Ambiguous(trip_ids) => self.ambiguous.push((date, train, trip_ids.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::{Connection, Trip};
+
+    fn trip(trip_id: usize, dep_time: u32, stops: &[usize]) -> Trip {
+        Trip {
+            identifier: trip_id,
+            connections: stops.windows(2).enumerate().map(|(i, pair)| Connection {
+                dep_stop: pair[0],
+                arr_stop: pair[1],
+                dep_time: dep_time + i as u32 * 600,
+                arr_time: dep_time + i as u32 * 600 + 300,
+                trip_id
+            }).collect()
+        }
+    }
+
+    #[test]
+    fn similarity() {
+        assert_eq!(stop_similarity(&[1, 2, 3, 4], &[1, 2, 3, 4]), 1.0);
+        assert_eq!(stop_similarity(&[1, 2, 3, 4], &[1, 3, 4]), 0.75);
+        assert_eq!(stop_similarity(&[1, 2], &[3, 4]), 0.0);
+        assert_eq!(stop_similarity(&[], &[1]), 0.0);
+    }
+
+    #[test]
+    fn after_midnight() {
+        let day = 24 * 3600;
+        let timetable = Timetable {
+            trips: vec![
+                // Train 1410 on the service day and on the next service day, and a train which is present twice
+                trip(1410, 3600, &[1, 2, 3]),
+                trip(TRIP_IDS_PER_DAY + 1410, 3600 + day, &[1, 2, 3]),
+                trip(3000, 7200, &[4, 5]),
+                trip(3000 + TRIP_IDS_PER_DAY, 7200, &[4, 5])
+            ],
+            ..Default::default()
+        };
+        let matcher = TripMatcher::new(&timetable);
+
+        // The service day is found by the departure, regardless of the date of the message
+        assert_eq!(matcher.find(&PlannedRun { train: 1410, departure: 3600 + day, stops: vec![1, 2, 3] }), TripMatch::Matched(TRIP_IDS_PER_DAY + 1410));
+        assert_eq!(matcher.find(&PlannedRun { train: 1410, departure: 3660, stops: vec![1, 3] }), TripMatch::Matched(1410));
+
+        assert_eq!(matcher.find(&PlannedRun { train: 1410, departure: 12 * 3600, stops: vec![1, 2, 3] }), TripMatch::Unmatched);
+        assert_eq!(matcher.find(&PlannedRun { train: 1410, departure: 3600, stops: vec![6, 7, 3] }), TripMatch::Unmatched);
+        assert_eq!(matcher.find(&PlannedRun { train: 1411, departure: 3600, stops: vec![1, 2, 3] }), TripMatch::New);
+        assert_eq!(matcher.find(&PlannedRun { train: 3000, departure: 7200, stops: vec![4, 5] }), TripMatch::Ambiguous(vec![3000, 3000 + TRIP_IDS_PER_DAY]));
+    }
+
+    #[test]
+    fn service_trips() {
+        let date = NaiveDate::from_ymd(2021, 1, 15);
+        assert_eq!(service_trip(&date, 1410), (date, 1410));
+        assert_eq!(service_trip(&date, TRIP_IDS_PER_DAY + 1410), (date.succ(), 1410));
+        assert_eq!(service_trip(&date, 2 * TRIP_IDS_PER_DAY + 1410), (date.pred(), 1410));
+    }
+}
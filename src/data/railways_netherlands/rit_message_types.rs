@@ -6,6 +6,8 @@ use chrono::{DateTime, Local, NaiveDate};
 
 use crate::types::{Connection, Trip};
 
+use super::rit_matching::PlannedRun;
+
 #[derive(Deserialize, Debug)]
 pub struct RITMessage {
     #[serde(rename = "ReisInformatieProductRitInfo")]
@@ -113,6 +115,32 @@ impl RITLogicalPart {
             connections
         })
     }
+    /// Stops the train was planned to stop at and the planned departure at the first of them, which stay the same when
+    /// the train is delayed or changed. Stations which are not in `stops` are left out.
+    pub fn planned_run(&self, stops: &HashMap<String, usize>) -> Option<PlannedRun> {
+        let planned: Vec<&RITLogicalPartStop> = self.stops.iter()
+            .filter(|stop| stop.stopping.iter().any(|s| s.state == RITState::Planned && s.stopping))
+            .collect();
+
+        Some(PlannedRun {
+            train: self.trip_id,
+            departure: planned.first()?.dep_time.iter().find(|s| s.state == RITState::Planned)?.date.timestamp() as u32,
+            stops: planned.iter().filter_map(|stop| stops.get(&stop.station.code.to_lowercase()).copied()).collect()
+        })
+    }
+
+    /// Arrival delay in seconds for each station the train stops at, arriving early counts as being on time
+    pub fn arrival_delays(&self) -> Vec<(String, u32)> {
+        self.stops.iter()
+            .filter(|stop| stop.stopping.iter().any(|s| s.state == RITState::Current && s.stopping))
+            .filter_map(|stop| {
+                let planned = stop.arr_time.iter().find(|s| s.state == RITState::Planned)?;
+                let current = stop.arr_time.iter().find(|s| s.state == RITState::Current)?;
+
+                Some((stop.station.code.to_lowercase(), (current.date - planned.date).num_seconds().max(0) as u32))
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,5 @@
+SELECT service_identifier.service_id, service_identifier.identifier
+FROM services, validities, service_identifier
+WHERE services.validity_id = validities.id
+AND validities.date = ?
+AND service_identifier.service_id = services.id
\ No newline at end of file
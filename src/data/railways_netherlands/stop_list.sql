@@ -3,5 +3,6 @@
 -- WHERE service_stops.type != 'pass'
 -- ORDER BY station_code
 
-SELECT code, lat, lng
-FROM stations
\ No newline at end of file
+SELECT code, name, timezone, lat, lng
+FROM stations
+ORDER BY code
\ No newline at end of file
@@ -3,5 +3,5 @@ FROM services, validities, service_stops
 WHERE services.validity_id = validities.id
 AND validities.date = ?
 AND service_stops.service_id = services.id
-AND service_stops.type <> "pass"
+AND service_stops.type <> 'pass'
 ORDER BY service_id, ordering ASC
\ No newline at end of file
@@ -2,17 +2,48 @@
 pub mod schema;
 pub mod types;
 
+#[cfg(feature = "mysql")]
+mod mysql;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "mysql")]
+pub use self::mysql::{sql_types, DbConnection, DbI8, DbU8, DbU16, DbU32, DbU64};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::{sql_types, DbConnection, DbI8, DbU8, DbU16, DbU32, DbU64};
+
+#[cfg(all(feature = "mysql", feature = "sqlite"))]
+compile_error!("Only one database backend can be enabled, use `--no-default-features --features sqlite` for SQLite");
+
+#[cfg(not(any(feature = "mysql", feature = "sqlite")))]
+compile_error!("A database backend should be enabled with either the `mysql` or the `sqlite` feature");
+
 use std::{env, error::Error};
 
 use diesel::prelude::*;
-use diesel::mysql::MysqlConnection;
+use diesel_migrations::RunMigrationsError;
 use dotenv::dotenv;
 
-pub fn establish_connection() -> MysqlConnection {
+/// Database in which the IFF timetable and the received messages are stored. MySQL is used by default, with the `sqlite`
+/// feature everything is stored in a local file instead, which is convenient for development and tests. Only one backend
+/// is compiled in, so the queries use `DbConnection` directly and this trait covers what differs between the backends. The
+/// schema is defined by the MySQL migrations, the SQLite migrations in `migrations_sqlite` have to be kept in sync by hand.
+pub trait Storage: Connection + Sized {
+    /// Maximum number of rows that are written or filtered on in a single query
+    const BATCH_SIZE: usize;
+
+    /// Connects to the database at an URL, for SQLite this is the path of the database file
+    fn open(url: &str) -> ConnectionResult<Self>;
+
+    /// Brings the schema of the database up-to-date
+    fn run_migrations(&self) -> Result<(), RunMigrationsError>;
+}
+
+pub fn establish_connection() -> DbConnection {
     dotenv().ok();
 
     let database_url = env::var("DATABASE_URL")
         .expect("DATABASE_URL environment variable must be set");
-    MysqlConnection::establish(&database_url)
+    DbConnection::open(&database_url)
         .expect(&format!("Error connecting to {}", database_url))
-}
\ No newline at end of file
+}
@@ -0,0 +1,33 @@
+use diesel::{mysql::MysqlConnection, prelude::*};
+use diesel_migrations::RunMigrationsError;
+
+use super::Storage;
+
+pub type DbConnection = MysqlConnection;
+
+// Rust types of the tiny and unsigned integer columns
+pub type DbI8 = i8;
+pub type DbU8 = u8;
+pub type DbU16 = u16;
+pub type DbU32 = u32;
+pub type DbU64 = u64;
+
+/// The schema is written for MySQL, so the types of diesel can be used as is
+pub mod sql_types {
+    pub use diesel::sql_types::*;
+}
+
+// Embeds migrations from migrations folder
+embed_migrations!();
+
+impl Storage for MysqlConnection {
+    const BATCH_SIZE: usize = 1000;
+
+    fn open(url: &str) -> ConnectionResult<Self> {
+        MysqlConnection::establish(url)
+    }
+
+    fn run_migrations(&self) -> Result<(), RunMigrationsError> {
+        embedded_migrations::run(self)
+    }
+}
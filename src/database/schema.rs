@@ -1,5 +1,5 @@
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     attributes (id) {
@@ -9,7 +9,55 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
+    use crate::database::types::*;
+
+    changes (from_station, from_service, to_station, to_service) {
+        from_station -> Varchar,
+        from_service -> Unsigned<Integer>,
+        to_station -> Varchar,
+        to_service -> Unsigned<Integer>,
+        duration -> Unsigned<Tinyint>,
+    }
+}
+
+table! {
+    use crate::database::sql_types::*;
+    use crate::database::types::*;
+
+    companies (id) {
+        id -> Unsigned<Integer>,
+        code -> Varchar,
+        name -> Varchar,
+        time_turn -> Unsigned<Smallint>,
+    }
+}
+
+table! {
+    use crate::database::sql_types::*;
+    use crate::database::types::*;
+
+    continuous_connections (from_station, to_station, mode) {
+        from_station -> Varchar,
+        to_station -> Varchar,
+        duration -> Unsigned<Tinyint>,
+        mode -> Varchar,
+    }
+}
+
+table! {
+    use crate::database::sql_types::*;
+    use crate::database::types::*;
+
+    countries (code) {
+        code -> Varchar,
+        inland -> Bool,
+        name -> Varchar,
+    }
+}
+
+table! {
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     dvs_messages (id) {
@@ -21,17 +69,33 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
+    use crate::database::types::*;
+
+    iff_versions (company, version) {
+        company -> Unsigned<Integer>,
+        version -> Unsigned<Integer>,
+        valid_from -> Date,
+        valid_till -> Date,
+        description -> Varchar,
+        imported_at -> Datetime,
+    }
+}
+
+table! {
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     services (id) {
         id -> Unsigned<Integer>,
         validity_id -> Unsigned<Integer>,
+        company_id -> Unsigned<Integer>,
+        checksum -> Unsigned<Bigint>,
     }
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     service_attributes (service_id, attribute_id) {
@@ -41,7 +105,7 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     service_identifier (service_id, identifier, from_index, to_index) {
@@ -53,7 +117,7 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     service_stops (service_id, ordering) {
@@ -69,7 +133,7 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     service_trns_modes (service_id, trns_mode_id) {
@@ -79,7 +143,7 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     stations (code) {
@@ -95,7 +159,19 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
+    use crate::database::types::*;
+
+    timezones (id, from_date) {
+        id -> Unsigned<Integer>,
+        difference -> Tinyint,
+        from_date -> Date,
+        to_date -> Date,
+    }
+}
+
+table! {
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     trns_modes (id) {
@@ -105,7 +181,7 @@ table! {
 }
 
 table! {
-    use diesel::sql_types::*;
+    use crate::database::sql_types::*;
     use crate::database::types::*;
 
     validities (id, date) {
@@ -123,13 +199,19 @@ joinable!(service_trns_modes -> trns_modes (trns_mode_id));
 
 allow_tables_to_appear_in_same_query!(
     attributes,
+    changes,
+    companies,
+    continuous_connections,
+    countries,
     dvs_messages,
+    iff_versions,
     services,
     service_attributes,
     service_identifier,
     service_stops,
     service_trns_modes,
     stations,
+    timezones,
     trns_modes,
     validities,
 );
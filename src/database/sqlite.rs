@@ -0,0 +1,72 @@
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use diesel_migrations::RunMigrationsError;
+
+use super::Storage;
+
+pub type DbConnection = SqliteConnection;
+
+// SQLite has no unsigned or tiny integers, so those columns are stored as the signed type of their size (tiny integers as
+// small integers). None of the unsigned values in the timetable come near the maximum of the signed types, except for
+// the checksums which are simply cast.
+pub type DbI8 = i16;
+pub type DbU8 = i16;
+pub type DbU16 = i16;
+pub type DbU32 = i32;
+pub type DbU64 = i64;
+
+/// SQL types of the (MySQL) schema, with the unsigned and tiny integers and datetimes mapped to types SQLite supports
+pub mod sql_types {
+    pub use diesel::sql_types::{BigInt, Bigint, Binary, Bool, Date, Double, Float, Integer, Longtext, Nullable, SmallInt, Smallint, Text, Time, Timestamp, VarChar, Varchar};
+
+    pub type TinyInt = SmallInt;
+    pub type Tinyint = SmallInt;
+    pub type Unsigned<ST> = ST;
+    pub type Datetime = Timestamp;
+}
+
+// Embeds the migrations for SQLite, which create the same schema as the MySQL migrations. Every MySQL migration needs an
+// equivalent SQLite migration, the `schema` test checks the tables of `schema.rs` (printed from MySQL) against them.
+embed_migrations!("migrations_sqlite");
+
+impl Storage for SqliteConnection {
+    // SQLite allows at most 999 parameters in a query
+    const BATCH_SIZE: usize = 500;
+
+    fn open(url: &str) -> ConnectionResult<Self> {
+        SqliteConnection::establish(url)
+    }
+
+    fn run_migrations(&self) -> Result<(), RunMigrationsError> {
+        embedded_migrations::run(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::schema::*;
+
+    #[test]
+    fn schema() {
+        let connection = SqliteConnection::open(":memory:").unwrap();
+        connection.run_migrations().unwrap();
+
+        // Selecting all columns of a table fails when the SQLite migrations are missing one of them
+        attributes::table.limit(0).execute(&connection).unwrap();
+        changes::table.limit(0).execute(&connection).unwrap();
+        companies::table.limit(0).execute(&connection).unwrap();
+        continuous_connections::table.limit(0).execute(&connection).unwrap();
+        countries::table.limit(0).execute(&connection).unwrap();
+        dvs_messages::table.limit(0).execute(&connection).unwrap();
+        iff_versions::table.limit(0).execute(&connection).unwrap();
+        services::table.limit(0).execute(&connection).unwrap();
+        service_attributes::table.limit(0).execute(&connection).unwrap();
+        service_identifier::table.limit(0).execute(&connection).unwrap();
+        service_stops::table.limit(0).execute(&connection).unwrap();
+        service_trns_modes::table.limit(0).execute(&connection).unwrap();
+        stations::table.limit(0).execute(&connection).unwrap();
+        timezones::table.limit(0).execute(&connection).unwrap();
+        trns_modes::table.limit(0).execute(&connection).unwrap();
+        validities::table.limit(0).execute(&connection).unwrap();
+    }
+}
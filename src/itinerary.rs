@@ -0,0 +1,88 @@
+use std::error::Error;
+
+use serde::Deserialize;
+
+use crate::{benchable::Benchable, types::TripResult};
+
+/// Itinerary as read from a JSON file, e.g. a round along multiple stations starting and ending at the same station
+#[derive(Debug, Deserialize)]
+pub struct Itinerary {
+    /// Station code to start from
+    pub start: String,
+
+    /// Departure time at the start station (HH:MM)
+    pub departure: String,
+
+    pub legs: Vec<ItineraryLeg>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItineraryLeg {
+    /// Station code to travel to
+    pub to: String,
+
+    /// Minimum amount of minutes to stay at the station before departing for the next leg
+    #[serde(default)]
+    pub stay: u32
+}
+
+/// Plans an itinerary leg by leg with the given algorithm, where `legs` contains the stop to travel to and the
+/// minimum stay there in seconds. Each leg departs no earlier than the arrival of the previous leg plus its stay.
+pub fn plan_itinerary<'a, 'b, B: Benchable<'a> + ?Sized>(alg: &'b B, dep_stop: usize, dep_time: u32, legs: &[(usize, u32)]) -> Result<Vec<TripResult<'b>>, Box<dyn Error>> {
+    let mut results = vec![];
+    let mut cur = dep_stop;
+    let mut time = dep_time;
+
+    for (i, &(arr_stop, stay)) in legs.iter().enumerate() {
+        if arr_stop == cur {
+            Err(format!("Leg {} starts and ends at the same stop", i + 1))?
+        }
+
+        let result = alg.find_earliest_arrival(cur, arr_stop, time).ok_or(format!("No route found for leg {}", i + 1))?;
+
+        time = result.arrival() + stay;
+        cur = arr_stop;
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::{algorithms::csa_vec::CSAVec, types::{Connection, Timetable, Trip}};
+
+    #[test]
+    fn stays_are_applied() {
+        let connections = vec![
+            Connection { dep_stop: 0, arr_stop: 1, dep_time: 0, arr_time: 100, trip_id: 0 },
+            Connection { dep_stop: 1, arr_stop: 0, dep_time: 150, arr_time: 250, trip_id: 1 },
+            Connection { dep_stop: 1, arr_stop: 0, dep_time: 300, arr_time: 400, trip_id: 2 },
+        ];
+
+        let mut footpaths = HashMap::new();
+        for i in 0..=1 {
+            footpaths.insert(i, vec![(i, 0)]);
+        }
+
+        let timetable = Timetable {
+            trips: connections.into_iter().map(|c| Trip {
+                identifier: c.trip_id,
+                connections: vec![c]
+            }).collect(),
+            footpaths,
+            ..Default::default()
+        };
+
+        let alg = CSAVec::new(&timetable);
+
+        let results = plan_itinerary(&alg, 0, 0, &[(1, 0), (0, 0)]).unwrap();
+        assert_eq!(results.iter().map(|r| r.arrival()).collect::<Vec<_>>(), vec![100, 250]);
+
+        // Staying at least 100 seconds at stop 1 means the first return trip can not be taken
+        let results = plan_itinerary(&alg, 0, 0, &[(1, 100), (0, 0)]).unwrap();
+        assert_eq!(results.iter().map(|r| r.arrival()).collect::<Vec<_>>(), vec![100, 400]);
+    }
+}
@@ -7,21 +7,48 @@ extern crate diesel_migrations;
 mod types;
 mod benchable;
 mod benchmarking;
+mod calendar;
+mod itinerary;
+mod snapshot;
 mod algorithms;
 mod data;
 pub mod database;
 
-use std::{error::Error, fs::File};
+use std::{error::Error, fs::File, path::{Path, PathBuf}, time::Duration};
 use benchable::{Benchable, BenchableLive};
-use chrono::{Local, NaiveDate, TimeZone};
-use clap::{App, Arg, SubCommand};
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use serde_json::{self, from_reader};
 
-use data::railways_netherlands::{info_plus, iff};
-use types::TripUpdate;
+use data::railways_netherlands::{info_plus, iff, iff_types::IFF, rit_gtfs_realtime};
+use database::Storage;
+use types::{Timetable, TripUpdate};
 
-// Embeds migrations from migrations folder
-embed_migrations!();
+// Loads the IFF timetable of a date from the snapshot given by the `snapshot` argument, which should be of the same date,
+// or from the database
+fn iff_timetable(matches: &ArgMatches<'_>, date: &NaiveDate) -> Result<Timetable, Box<dyn Error + 'static>> {
+    match matches.value_of("snapshot") {
+        Some(path) => Timetable::load_snapshot_of_day(Path::new(path), date),
+        None => iff::get_timetable_for_day(date)
+    }
+}
+
+// Timetable of a date from the database, including the adjacent days if the `overnight` flag is given
+fn day_timetable(matches: &ArgMatches<'_>, date: &NaiveDate) -> Result<Timetable, Box<dyn Error + 'static>> {
+    if matches.is_present("overnight") {
+        iff::get_timetable_around(date)
+    } else {
+        iff::get_timetable_for_day(date)
+    }
+}
+
+// Reads the IFF file given by the `file` argument, or downloads it from the `url` argument
+async fn read_iff(matches: &ArgMatches<'_>) -> Result<IFF, Box<dyn Error + 'static>> {
+    match matches.value_of("file") {
+        Some(file) => IFF::from_path(Path::new(file)),
+        None => iff::download_iff(matches.value_of("url").unwrap()).await
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + 'static>> {
@@ -31,36 +58,139 @@ async fn main() -> Result<(), Box<dyn Error + 'static>> {
 
     // Make sure our database is up-to-date
     let connection = database::establish_connection();
-    embedded_migrations::run(&connection)?;
+    connection.run_migrations()?;
     std::mem::drop(connection);
 
     // Parse command line arguments
     let app = App::new("Train planner benchmarking utilities")
         .about("App for gathering data and benchmarking data on different public transport routing algorithms")
         .author("Yoeri Otten <y.d.otten@student.utwente.nl>")
-        .subcommand(SubCommand::with_name("iff").about("Download, parse and import the most up-to-date IFF file from the NDOV Loket"))
+        .subcommand(SubCommand::with_name("iff").about("Download, parse and import the most up-to-date IFF file from the NDOV Loket")
+            .subcommand(SubCommand::with_name("import").about("Parse and import an IFF file from a local file or an URL")
+                .arg(Arg::with_name("file").long("file").takes_value(true)
+                    .help("IFF zip file or directory with the extracted files to import"))
+                .arg(Arg::with_name("url").long("url").takes_value(true).default_value(iff::IFF_URL)
+                    .help("URL to download the IFF zip file from when no file is given")))
+            .subcommand(SubCommand::with_name("export-gtfs").about("Read an IFF file (the most up-to-date one by default) and write it as a GTFS feed")
+                .arg(Arg::with_name("file").long("file").takes_value(true)
+                    .help("IFF zip file or directory with the extracted files to export"))
+                .arg(Arg::with_name("url").long("url").takes_value(true).default_value(iff::IFF_URL)
+                    .help("URL to download the IFF zip file from when no file is given"))
+                .arg(Arg::with_name("from").long("from").takes_value(true).required(true)
+                    .help("First service date to export (YYYY-MM-DD)"))
+                .arg(Arg::with_name("to").long("to").takes_value(true).required(true)
+                    .help("Last service date to export (YYYY-MM-DD)"))
+                .arg(Arg::with_name("output").long("output").takes_value(true).default_value("gtfs.zip")
+                    .help("File to write the GTFS feed to"))))
+        .subcommand(SubCommand::with_name("snapshot").about("Save the IFF timetable of a day as a snapshot, which loads a lot faster")
+            .arg(Arg::with_name("iff").long("iff").takes_value(true)
+                .help("IFF file (zip file or directory) to use instead of the database"))
+            .arg(Arg::with_name("date").long("date").takes_value(true).default_value("2021-01-15")
+                .help("Service date of the timetable"))
+            .arg(Arg::with_name("output").long("output").takes_value(true).default_value("timetable.snapshot")
+                .help("File to write the snapshot to")))
         .subcommand(SubCommand::with_name("dvs").about("Listen for DVS messages").arg(Arg::with_name("envelopes")
             .help("Which set of envelopes to run")
             .possible_values(&["all", "dvs", "rit"])
             .default_value("all")))
+        .subcommand(SubCommand::with_name("rit-gtfs-rt").about("Listen for RIT messages and publish them as a GTFS-Realtime feed")
+            .arg(Arg::with_name("output").long("output").takes_value(true).default_value("trip_updates.pb")
+                .help("File to write snapshots of the feed to"))
+            .arg(Arg::with_name("interval").long("interval").takes_value(true).default_value("30")
+                .help("Minimum amount of seconds between snapshots")))
         .subcommand(SubCommand::with_name("bench").about("Perform benchmarks with specified dataset").arg(Arg::with_name("set")
             .help("Which data set to use for benching")
-            .possible_values(&["iff", "trainline"])
-            .default_value("iff")))
-        .subcommand(SubCommand::with_name("example"))
+            .possible_values(&["iff", "trainline", "gtfs", "netex", "hrdf"])
+            .default_value("iff"))
+            .arg(Arg::with_name("gtfs").long("gtfs").takes_value(true).required_if("set", "gtfs")
+                .help("GTFS feed (zip file or directory) to use for the gtfs data set"))
+            .arg(Arg::with_name("netex").long("netex").takes_value(true).required_if("set", "netex")
+                .help("NeTEx feed (XML file or directory) to use for the netex data set"))
+            .arg(Arg::with_name("hrdf").long("hrdf").takes_value(true).required_if("set", "hrdf")
+                .help("HRDF export (zip file or directory) to use for the hrdf data set"))
+            .arg(Arg::with_name("iff").long("iff").takes_value(true)
+                .help("IFF file (zip file or directory) to use for the iff data set instead of the database"))
+            .arg(Arg::with_name("days").long("days").takes_value(true).requires("iff")
+                .help("Amount of days starting at the service date to include in the timetable of the iff data set"))
+            .arg(Arg::with_name("snapshot").long("snapshot").takes_value(true).conflicts_with("iff")
+                .help("Timetable snapshot (see snapshot) of the service date to use for the iff data set instead of the database"))
+            .arg(Arg::with_name("date").long("date").takes_value(true).default_value("2021-01-15")
+                .help("Service date to use for the iff, gtfs, netex and hrdf data sets"))
+            .arg(Arg::with_name("updates").long("updates").takes_value(true)
+                .help("Updates file (see gtfs-rt) to use for the live benchmark of the gtfs data set")))
+        .subcommand(SubCommand::with_name("example")
+            .arg(Arg::with_name("snapshot").long("snapshot").takes_value(true)
+                .help("Timetable snapshot (see snapshot) of 2021-01-15 to use instead of the database")))
             .about("Gets route from Enschede Kennispark to Amersfoort Centraal on 2021-01-15 at 12:00")
         .subcommand(SubCommand::with_name("updates"))
             .about("Preload updates and save in file")
+        .subcommand(SubCommand::with_name("gtfs-rt")
+            .about("Converts GTFS-Realtime trip updates to updates of a GTFS timetable and saves them in updates.json")
+            .arg(Arg::with_name("gtfs").long("gtfs").takes_value(true).required(true)
+                .help("GTFS feed (zip file or directory) the realtime feeds belong to"))
+            .arg(Arg::with_name("date").long("date").takes_value(true).default_value("2021-01-15")
+                .help("Service date of the timetable"))
+            .arg(Arg::with_name("feeds").multiple(true).required(true)
+                .help("Files or URLs of GTFS-Realtime feeds, in the order they were published")))
+        .subcommand(SubCommand::with_name("delays")
+            .about("Derives delay distributions from all stored RIT messages and saves them in delays.json"))
+        .subcommand(SubCommand::with_name("reliable")
+            .about("Finds a reliable route on 2021-01-15 at 13:00 using the delays from delays.json")
+            .arg(Arg::with_name("from").help("Departure station code").required(true))
+            .arg(Arg::with_name("to").help("Arrival station code").required(true))
+            .arg(Arg::with_name("probability").long("probability").takes_value(true).default_value("0.9")
+                .help("Minimum success probability of each transfer"))
+            .arg(Arg::with_name("deadline").long("deadline").takes_value(true)
+                .help("Maximise the probability of arriving before this time (HH:MM) instead"))
+            .arg(Arg::with_name("overnight").long("overnight")
+                .help("Also load the trips of the adjacent days, for routes around midnight")))
+        .subcommand(SubCommand::with_name("meet")
+            .about("Finds the best stations to meet on 2021-01-15 for multiple travellers")
+            .arg(Arg::with_name("travellers").multiple(true).required(true)
+                .help("Departure of each traveller as station code and time, e.g. esk@13:00"))
+            .arg(Arg::with_name("objective").long("objective").takes_value(true)
+                .possible_values(&["latest", "sum"])
+                .default_value("latest")
+                .help("Minimise the latest arrival or the sum of arrival times"))
+            .arg(Arg::with_name("count").long("count").takes_value(true).default_value("3")
+                .help("Amount of meeting stations to show"))
+            .arg(Arg::with_name("overnight").long("overnight")
+                .help("Also load the trips of the adjacent days, for routes around midnight")))
+        .subcommand(SubCommand::with_name("itinerary")
+            .about("Plans a multi-leg itinerary with stays on 2021-01-15, as read from a JSON file")
+            .arg(Arg::with_name("file").help("JSON file containing the itinerary").required(true))
+            .arg(Arg::with_name("overnight").long("overnight")
+                .help("Also load the trips of the adjacent days, for routes around midnight")))
         .subcommand(SubCommand::with_name("trip")
             .about("Looks up a specific trip on 2021-01-15")
-            .arg(Arg::with_name("id").help("Train number").required(true)
-        ))
+            .arg(Arg::with_name("id").help("Train number").required(true))
+            .arg(Arg::with_name("snapshot").long("snapshot").takes_value(true)
+                .help("Timetable snapshot (see snapshot) of 2021-01-15 to use instead of the database")))
         .get_matches();
 
     match app.subcommand() {
-        ("iff", _) => {
-            println!("Starting update of IFF data, this might take a while...");
-            iff::update_iff_database().await?;
+        ("iff", Some(sub_matches)) => match sub_matches.subcommand() {
+            ("export-gtfs", Some(export_matches)) => {
+                let from = NaiveDate::parse_from_str(export_matches.value_of("from").unwrap(), "%Y-%m-%d")?;
+                let to = NaiveDate::parse_from_str(export_matches.value_of("to").unwrap(), "%Y-%m-%d")?;
+
+                println!("Reading IFF data, this might take a while...");
+                let iff = read_iff(export_matches).await?;
+
+                println!("Writing GTFS feed for {} until {}", from, to);
+                iff.write_gtfs(File::create(export_matches.value_of("output").unwrap())?, &from, &to)?;
+            },
+            ("import", Some(import_matches)) => {
+                println!("Reading IFF data, this might take a while...");
+                let iff = read_iff(import_matches).await?;
+
+                println!("Importing {} services to the database...", iff.services.len());
+                println!("{}", iff::import_iff(&iff)?);
+            },
+            _ => {
+                println!("Starting update of IFF data, this might take a while...");
+                println!("{}", iff::update_iff_database(iff::IFF_URL).await?);
+            }
         },
         ("dvs", Some(sub_matches)) => {
             println!("Starting to listen for DVS messages...");
@@ -74,12 +204,22 @@ async fn main() -> Result<(), Box<dyn Error + 'static>> {
 
             info_plus::dvs_stream(envelopes).into_iter().for_each(|x| x.join().unwrap().unwrap());
         }
+        ("rit-gtfs-rt", Some(sub_matches)) => {
+            let output = PathBuf::from(sub_matches.value_of("output").unwrap());
+            let interval = Duration::from_secs(sub_matches.value_of("interval").unwrap().parse()?);
+
+            rit_gtfs_realtime::gtfs_realtime_stream(output, interval).join().unwrap();
+        }
         ("bench", Some(sub_matches)) => {
             match sub_matches.value_of("set") {
                 Some("iff") => {
                     println!("Generating timetable and updates list, this might take a while...");
-                    let date = NaiveDate::from_ymd(2021, 1, 15);
-                    let timetable = iff::get_timetable_for_day(&date)?;
+                    let date = NaiveDate::parse_from_str(sub_matches.value_of("date").unwrap(), "%Y-%m-%d")?;
+                    let days: i64 = sub_matches.value_of("days").unwrap_or("1").parse()?;
+                    let timetable = match sub_matches.value_of("iff") {
+                        Some(path) => IFF::from_path(Path::new(path))?.calendar().timetable_for_range(&date, &(date + chrono::Duration::days(days - 1))),
+                        None => iff_timetable(sub_matches, &date)?
+                    };
 
                     println!("The timetable contains {} connections, stopping at {} places.", 
                         &timetable.trips.iter().map(|t| t.connections.len()).sum::<usize>(),
@@ -109,27 +249,98 @@ async fn main() -> Result<(), Box<dyn Error + 'static>> {
                     println!("Starting bench of static algorithms..");
                     benchmarking::bench_algorithms("Trainline EU", &timetable)?;
                 }
+                Some("gtfs") => {
+                    println!("Generating timetable, this might take a while...");
+                    let date = NaiveDate::parse_from_str(sub_matches.value_of("date").unwrap(), "%Y-%m-%d")?;
+                    let timetable = data::gtfs::get_timetable_for_day(Path::new(sub_matches.value_of("gtfs").unwrap()), &date)?;
+
+                    println!("The timetable contains {} connections, stopping at {} places.", 
+                        &timetable.trips.iter().map(|t| t.connections.len()).sum::<usize>(),
+                        &timetable.stops.len()
+                    );
+
+                    println!("Starting bench of static algorithms..");
+                    benchmarking::bench_algorithms("GTFS", &timetable)?;
+
+                    if let Some(updates) = sub_matches.value_of("updates") {
+                        let file = File::open(updates)?;
+                        let updates: Vec<Vec<TripUpdate>> = from_reader(file)?;
+
+                        println!("Starting bench of live algorithms with {} updates...", updates.iter().map(|x| x.len()).sum::<usize>());
+                        benchmarking::bench_algorithms_live("GTFS", &timetable, &updates)?;
+                    }
+                }
+                Some("netex") => {
+                    println!("Generating timetable, this might take a while...");
+                    let date = NaiveDate::parse_from_str(sub_matches.value_of("date").unwrap(), "%Y-%m-%d")?;
+                    let timetable = data::netex::get_timetable_for_day(Path::new(sub_matches.value_of("netex").unwrap()), &date)?;
+
+                    println!("The timetable contains {} connections, stopping at {} places.", 
+                        &timetable.trips.iter().map(|t| t.connections.len()).sum::<usize>(),
+                        &timetable.stops.len()
+                    );
+
+                    println!("Starting bench of static algorithms..");
+                    benchmarking::bench_algorithms("NeTEx", &timetable)?;
+                }
+                Some("hrdf") => {
+                    println!("Generating timetable, this might take a while...");
+                    let date = NaiveDate::parse_from_str(sub_matches.value_of("date").unwrap(), "%Y-%m-%d")?;
+                    let timetable = data::hrdf::get_timetable_for_day(Path::new(sub_matches.value_of("hrdf").unwrap()), &date)?;
+
+                    println!("The timetable contains {} connections, stopping at {} places.", 
+                        &timetable.trips.iter().map(|t| t.connections.len()).sum::<usize>(),
+                        &timetable.stops.len()
+                    );
+
+                    println!("Starting bench of static algorithms..");
+                    benchmarking::bench_algorithms("HRDF", &timetable)?;
+                }
                 _ => {}
             }
 
         }
+        ("snapshot", Some(sub_matches)) => {
+            let date = NaiveDate::parse_from_str(sub_matches.value_of("date").unwrap(), "%Y-%m-%d")?;
+
+            println!("Generating timetable for {:?}, this might take a while...", date);
+            let timetable = match sub_matches.value_of("iff") {
+                Some(path) => IFF::from_path(Path::new(path))?.timetable_for_day(&date),
+                None => {
+                    // The sections of splitting trains are stored as well, such that users of the snapshot need no database
+                    let mut timetable = iff::get_timetable_for_day(&date)?;
+                    timetable.sections = info_plus::read_dvs_to_sections(&date, &timetable)?;
+                    timetable
+                }
+            };
+
+            let output = sub_matches.value_of("output").unwrap();
+            timetable.save_snapshot(Path::new(output), &(date..=date))?;
+            println!("Saved {} trips stopping at {} places to {}", timetable.trips.len(), timetable.stops.len(), output);
+        }
         ("trip", Some(sub_matches)) => {
             let id: usize = sub_matches.value_of("id").unwrap().parse().unwrap();
             println!("Looking up route of service {}", id);
 
-            let timetable = iff::get_timetable_for_day(&NaiveDate::from_ymd(2021, 1, 15))?;
+            let timetable = iff_timetable(sub_matches, &NaiveDate::from_ymd(2021, 1, 15))?;
             let trip = timetable.trips.iter().find(|x| x.identifier == id).unwrap();
 
             for conn in &trip.connections {
                 println!("{:?} at {:?} => {:?} at {:?}", timetable.stops.get(&conn.dep_stop).unwrap(), conn.dep_time, timetable.stops.get(&conn.arr_stop).unwrap(), conn.arr_time);
             }
         }
-        ("example", _) => {
+        ("example", Some(sub_matches)) => {
             // Performs an example routing with CSA Vec
             let date = NaiveDate::from_ymd(2021, 1, 15);
 
             println!("Loading timetable for {:?}", date);
-            let timetable = iff::get_timetable_for_day(&date)?;
+            let mut timetable = iff_timetable(sub_matches, &date)?;
+
+            // Snapshots already contain the sections of splitting trains
+            if !sub_matches.is_present("snapshot") {
+                timetable.sections = info_plus::read_dvs_to_sections(&date, &timetable)?;
+            }
+            timetable.apply_sections();
 
             // Find ID's of amf and esk
             let amf = timetable.stops.iter().find(|(_, stop)| stop.to_string() == "amf").unwrap().0;
@@ -137,7 +348,11 @@ async fn main() -> Result<(), Box<dyn Error + 'static>> {
 
             let mut alg = algorithms::td_simple_btree::TDSimpleBTree::new(&timetable);
             let route = alg.find_earliest_arrival(*esk, *amf, Local.ymd(2021, 1, 15).and_hms(13, 0, 0).timestamp() as u32).unwrap();
-            println!("{}", route.format_fancy(&timetable.stops));
+            println!("{}", timetable.join_continuations(route.clone()).format_fancy(&timetable));
+
+            println!("Departing as late as possible:");
+            let route = timetable.latest_departure(*esk, Local.ymd(2021, 1, 15).and_hms(13, 0, 0).timestamp() as u32, &route).unwrap();
+            println!("{}", timetable.join_continuations(route).format_fancy(&timetable));
 
             // Get changes
             let file = File::open("updates.json")?;
@@ -148,17 +363,139 @@ async fn main() -> Result<(), Box<dyn Error + 'static>> {
 
             println!("After updating:");
             let route = alg.find_earliest_arrival(*esk, *amf, Local.ymd(2021, 1, 15).and_hms(13, 0, 0).timestamp() as u32).unwrap();
-            println!("{}", route.format_fancy(&timetable.stops));
+            println!("{}", timetable.join_continuations(route).format_fancy(&timetable));
 
         }
+        ("gtfs-rt", Some(sub_matches)) => {
+            let path = Path::new(sub_matches.value_of("gtfs").unwrap());
+            let date = NaiveDate::parse_from_str(sub_matches.value_of("date").unwrap(), "%Y-%m-%d")?;
+
+            println!("Loading timetable for {:?}", date);
+            let timetable = data::gtfs::get_timetable_for_day(path, &date)?;
+            let mut converter = data::gtfs_realtime::TripUpdateConverter::new(&timetable, data::gtfs::read_trip_ids(path)?, data::gtfs::read_stop_sequences(path)?, date);
+
+            let mut updates = vec![];
+            for source in sub_matches.values_of("feeds").unwrap() {
+                let feed = data::gtfs_realtime::read_feed(source).await?;
+                updates.extend(converter.convert(&feed));
+            }
+
+            println!("Converted {} trip updates", updates.len());
+            let file = File::create("updates.json")?;
+            serde_json::to_writer(file, &updates)?;
+        }
+        ("delays", _) => {
+            println!("Reading delays from RIT messages, this might take a while...");
+            let delays = info_plus::read_dvs_to_delays()?;
+
+            let file = File::create("delays.json")?;
+            serde_json::to_writer(file, &delays.into_iter().collect::<Vec<_>>())?;
+        }
+        ("reliable", Some(sub_matches)) => {
+            let date = NaiveDate::from_ymd(2021, 1, 15);
+            let dep_time = Local.ymd(2021, 1, 15).and_hms(13, 0, 0).timestamp() as u32;
+
+            println!("Loading timetable for {:?}", date);
+            let timetable = day_timetable(sub_matches, &date)?;
+
+            let from = timetable.stops.iter().find(|(_, stop)| stop.to_string() == sub_matches.value_of("from").unwrap()).ok_or("Unknown departure station")?.0;
+            let to = timetable.stops.iter().find(|(_, stop)| stop.to_string() == sub_matches.value_of("to").unwrap()).ok_or("Unknown arrival station")?.0;
+
+            let file = File::open("delays.json")?;
+            let delays: Vec<((usize, String), Vec<u32>)> = from_reader(file)?;
+            let delays = info_plus::delays_for_timetable(&timetable, delays.into_iter().collect());
+
+            let probability: f64 = sub_matches.value_of("probability").unwrap().parse()?;
+            let alg = algorithms::csa_reliable::CSAReliable::with_delays(&timetable, delays, probability);
+
+            if let Some(deadline) = sub_matches.value_of("deadline") {
+                let deadline = Local.ymd(2021, 1, 15).and_time(NaiveTime::parse_from_str(deadline, "%H:%M")?).ok_or("Invalid deadline")?;
+                let (probability, route) = alg.find_most_reliable_arrival(*from, *to, dep_time, deadline.timestamp() as u32).ok_or("No route found")?;
+
+                println!("Arriving before {} with a probability of {:.1}%", deadline, probability * 100.0);
+                println!("{}", timetable.join_continuations(route).format_fancy(&timetable));
+            } else {
+                let route = alg.find_earliest_arrival(*from, *to, dep_time).ok_or("No route found")?;
+                println!("{}", timetable.join_continuations(route).format_fancy(&timetable));
+            }
+        }
+        ("meet", Some(sub_matches)) => {
+            let date = NaiveDate::from_ymd(2021, 1, 15);
+
+            println!("Loading timetable for {:?}", date);
+            let timetable = day_timetable(sub_matches, &date)?;
+
+            let travellers = sub_matches.values_of("travellers").unwrap().map(|traveller| -> Result<(usize, u32), Box<dyn Error>> {
+                let mut split = traveller.splitn(2, '@');
+                let code = split.next().unwrap();
+                let time = NaiveTime::parse_from_str(split.next().ok_or("Traveller should be given as station@HH:MM")?, "%H:%M")?;
+
+                let stop = *timetable.stops.iter().find(|(_, stop)| stop.to_string() == code).ok_or("Unknown station")?.0;
+                Ok((stop, Local.ymd(2021, 1, 15).and_time(time).ok_or("Invalid time")?.timestamp() as u32))
+            }).collect::<Result<Vec<_>, _>>()?;
+
+            let objective = match sub_matches.value_of("objective") {
+                Some("sum") => algorithms::meeting_point::MeetingObjective::SumOfArrivals,
+                _ => algorithms::meeting_point::MeetingObjective::LatestArrival
+            };
+
+            let alg = algorithms::csa_vec::CSAVec::new(&timetable);
+            let points = algorithms::meeting_point::find_meeting_points(&alg, &travellers, objective);
+
+            for point in points.iter().take(sub_matches.value_of("count").unwrap().parse()?) {
+                println!("Meet at {}, everyone has arrived at {}", timetable.stops.get(&point.stop).unwrap().name(), Local.timestamp(point.latest_arrival() as i64, 0));
+
+                for &(dep_stop, dep_time) in &travellers {
+                    match alg.find_earliest_arrival(dep_stop, point.stop, dep_time) {
+                        Some(route) if dep_stop != point.stop => println!("{}", timetable.join_continuations(route).format_fancy(&timetable)),
+                        _ => println!("Wait at {}\n", timetable.stops.get(&dep_stop).unwrap().name())
+                    }
+                }
+            }
+        }
+        ("itinerary", Some(sub_matches)) => {
+            let file = File::open(sub_matches.value_of("file").unwrap())?;
+            let itinerary: itinerary::Itinerary = from_reader(file)?;
+
+            let date = NaiveDate::from_ymd(2021, 1, 15);
+
+            println!("Loading timetable for {:?}", date);
+            let timetable = day_timetable(sub_matches, &date)?;
+
+            let find_stop = |code: &str| timetable.stops.iter()
+                .find(|(_, stop)| stop.to_string() == code)
+                .map(|(&id, _)| id)
+                .ok_or(format!("Unknown station: {}", code));
+
+            let dep_stop = find_stop(&itinerary.start)?;
+            let dep_time = Local.ymd(2021, 1, 15).and_time(NaiveTime::parse_from_str(&itinerary.departure, "%H:%M")?).ok_or("Invalid departure time")?.timestamp() as u32;
+            let legs = itinerary.legs.iter()
+                .map(|leg| find_stop(&leg.to).map(|stop| (stop, leg.stay * 60)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let alg = algorithms::csa_vec::CSAVec::new(&timetable);
+            for (route, &(stop, stay)) in itinerary::plan_itinerary(&alg, dep_stop, dep_time, &legs)?.into_iter().zip(&legs) {
+                let route = timetable.join_continuations(route);
+                println!("{}", route.format_fancy(&timetable));
+
+                if stay > 0 {
+                    println!("Stay at {} until at least {}\n", timetable.stops.get(&stop).unwrap().name(), Local.timestamp((route.arrival() + stay) as i64, 0));
+                }
+            }
+        }
         ("updates", _) => {
             // Preload updates and save in file.
             let date = NaiveDate::from_ymd(2021, 1, 15);
-            let updates = info_plus::read_dvs_to_updates(&date)?;
+            let (updates, report) = info_plus::read_dvs_to_updates(&date)?;
 
             let file = File::create("updates.json")?;
             serde_json::to_writer(file, &updates)?;
 
+            println!("Matched {} trains, added {} extra trains, {} could not be matched and {} are ambiguous (see updates_report.json)",
+                report.matched, report.added.len(), report.unmatched.len(), report.ambiguous.len());
+            let file = File::create("updates_report.json")?;
+            serde_json::to_writer_pretty(file, &report)?;
+
         }
         _ => {},
     }
@@ -0,0 +1,359 @@
+// Binary snapshots of a timetable, such that a day does not have to be generated from the database (or another source)
+// every time it is used. A snapshot starts with a magic number, the version of the format and the first and last service
+// date of the timetable (as days since the common era), followed by the gzipped timetable. Integers are little-endian,
+// strings and lists are prefixed by their length.
+
+use std::{error::Error, fs::File, io::{self, BufReader, BufWriter, Read, Write}, iter::FromIterator, ops::RangeInclusive, path::Path};
+
+use chrono::{Datelike, NaiveDate};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use crate::types::{Connection, Stop, Timetable, TrainSection, Trip, TripDetails, haversine_distance};
+
+const MAGIC: &[u8; 4] = b"TTSN";
+
+/// Version of the snapshot format, snapshots of another version can't be loaded and should be created again
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Stop loaded from a snapshot, with the information the original stop gave through the `Stop` trait
+#[derive(Debug)]
+pub struct SnapshotStop {
+    pub id: String,
+    pub name: String,
+    pub coords: Option<(f64, f64)>,
+    pub planar: bool
+}
+
+impl Stop for SnapshotStop {
+    fn to_string(&self) -> String {
+        self.id.clone()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn coords(&self) -> Option<(f64, f64)> {
+        self.coords
+    }
+
+    fn distance(&self, other: &Box<dyn Stop>) -> Option<f64> {
+        let (c1, c2) = (self.coords()?, other.coords()?);
+        if self.planar {
+            Some(((c1.0 - c2.0).powi(2) + (c1.1 - c2.1).powi(2)).sqrt())
+        } else {
+            Some(haversine_distance(c1, c2))
+        }
+    }
+
+    fn planar(&self) -> bool {
+        self.planar
+    }
+}
+
+struct SnapshotWriter<W: Write>(W);
+
+impl<W: Write> SnapshotWriter<W> {
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.0.write_all(&value.to_le_bytes())
+    }
+
+    fn usize(&mut self, value: usize) -> io::Result<()> {
+        self.0.write_all(&(value as u64).to_le_bytes())
+    }
+
+    fn f64(&mut self, value: f64) -> io::Result<()> {
+        self.0.write_all(&value.to_le_bytes())
+    }
+
+    fn bool(&mut self, value: bool) -> io::Result<()> {
+        self.0.write_all(&[value as u8])
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        self.usize(value.len())?;
+        self.0.write_all(value.as_bytes())
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T) -> io::Result<()>) -> io::Result<()> {
+        self.bool(value.is_some())?;
+        match value {
+            Some(value) => write(self, value),
+            None => Ok(())
+        }
+    }
+
+    fn list<I: ExactSizeIterator>(&mut self, items: I, mut write: impl FnMut(&mut Self, I::Item) -> io::Result<()>) -> io::Result<()> {
+        self.usize(items.len())?;
+        for item in items {
+            write(self, item)?;
+        }
+        Ok(())
+    }
+
+    fn connection(&mut self, connection: &Connection) -> io::Result<()> {
+        self.usize(connection.dep_stop)?;
+        self.usize(connection.arr_stop)?;
+        self.u32(connection.dep_time)?;
+        self.u32(connection.arr_time)?;
+        self.usize(connection.trip_id)
+    }
+}
+
+struct SnapshotReader<R: Read>(R);
+
+impl<R: Read> SnapshotReader<R> {
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.0.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        let mut bytes = [0; 8];
+        self.0.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes) as usize)
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        let mut bytes = [0; 8];
+        self.0.read_exact(&mut bytes)?;
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        let mut bytes = [0; 1];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes[0] != 0)
+    }
+
+    // Strings are read up to their length instead of allocating it up front, as a corrupt length could be huge
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let len = self.usize()?;
+        let mut bytes = vec![];
+        (&mut self.0).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            Err("Snapshot ends within a string")?;
+        }
+
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, Box<dyn Error>>) -> Result<Option<T>, Box<dyn Error>> {
+        Ok(if self.bool()? { Some(read(self)?) } else { None })
+    }
+
+    fn list<T, C: FromIterator<T>>(&mut self, mut read: impl FnMut(&mut Self) -> Result<T, Box<dyn Error>>) -> Result<C, Box<dyn Error>> {
+        let len = self.usize()?;
+        (0..len).map(|_| read(self)).collect()
+    }
+
+    fn connection(&mut self) -> Result<Connection, Box<dyn Error>> {
+        Ok(Connection {
+            dep_stop: self.usize()?,
+            arr_stop: self.usize()?,
+            dep_time: self.u32()?,
+            arr_time: self.u32()?,
+            trip_id: self.usize()?
+        })
+    }
+}
+
+impl Timetable {
+    /// Saves the timetable of the service dates `dates` as a snapshot, stops are saved as far as the `Stop` trait describes them
+    pub fn save_snapshot(&self, path: &Path, dates: &RangeInclusive<NaiveDate>) -> Result<(), Box<dyn Error>> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        for date in &[dates.start(), dates.end()] {
+            file.write_all(&date.num_days_from_ce().to_le_bytes())?;
+        }
+
+        let mut w = SnapshotWriter(GzEncoder::new(file, Compression::default()));
+
+        let mut stops: Vec<_> = self.stops.iter().collect();
+        stops.sort_by_key(|(&id, _)| id);
+        w.list(stops.into_iter(), |w, (&id, stop)| {
+            w.usize(id)?;
+            w.string(&stop.to_string())?;
+            w.string(&stop.name())?;
+            w.option(stop.coords(), |w, (x, y)| { w.f64(x)?; w.f64(y) })?;
+            w.bool(stop.planar())
+        })?;
+
+        w.list(self.trips.iter(), |w, trip| {
+            w.usize(trip.identifier)?;
+            w.list(trip.connections.iter(), SnapshotWriter::connection)
+        })?;
+
+        w.list(self.footpaths.iter(), |w, (&stop, footpaths)| {
+            w.usize(stop)?;
+            w.list(footpaths.iter(), |w, &(to, duration)| { w.usize(to)?; w.u32(duration) })
+        })?;
+
+        w.list(self.continuations.iter(), |w, (&from, &to)| { w.usize(from)?; w.usize(to) })?;
+
+        w.list(self.sections.iter(), |w, (&(trip, stop), sections)| {
+            w.usize(trip)?;
+            w.usize(stop)?;
+            w.list(sections.iter(), |w, section| {
+                w.usize(section.destination)?;
+                w.list(section.stops.iter(), |w, &stop| w.usize(stop))?;
+                w.option(section.track.as_deref(), SnapshotWriter::string)
+            })
+        })?;
+
+        w.list(self.transfers.iter(), |w, (&(stop, from_trip, to_trip), &duration)| {
+            w.usize(stop)?;
+            w.option(from_trip, SnapshotWriter::usize)?;
+            w.option(to_trip, SnapshotWriter::usize)?;
+            w.u32(duration)
+        })?;
+
+        w.list(self.details.iter(), |w, (&trip, details)| {
+            w.usize(trip)?;
+            w.option(details.operator.as_deref(), SnapshotWriter::string)?;
+            w.option(details.mode.as_deref(), SnapshotWriter::string)?;
+            w.list(details.attributes.iter(), |w, attribute| w.string(attribute))?;
+            w.option(details.number, SnapshotWriter::usize)
+        })?;
+
+        w.0.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Loads a timetable from a snapshot created by `save_snapshot` together with its service dates, its stops are `SnapshotStop`s
+    pub fn load_snapshot(path: &Path) -> Result<(Timetable, RangeInclusive<NaiveDate>), Box<dyn Error>> {
+        let mut file = BufReader::new(File::open(path)?);
+
+        let mut header = [0; 8];
+        file.read_exact(&mut header).map_err(|_| "Not a timetable snapshot")?;
+        if &header[..4] != MAGIC {
+            Err("Not a timetable snapshot")?;
+        }
+
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != SNAPSHOT_VERSION {
+            Err(format!("Snapshot has version {}, while version {} is supported, create the snapshot again", version, SNAPSHOT_VERSION))?;
+        }
+
+        let mut dates = [0; 8];
+        file.read_exact(&mut dates)?;
+        let date = |bytes: &[u8]| NaiveDate::from_num_days_from_ce_opt(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).ok_or("Invalid date in snapshot");
+        let dates = date(&dates[..4])?..=date(&dates[4..])?;
+
+        let mut r = SnapshotReader(GzDecoder::new(file));
+
+        let stops = r.list(|r| {
+            let id = r.usize()?;
+            let stop = SnapshotStop {
+                id: r.string()?,
+                name: r.string()?,
+                coords: r.option(|r| Ok((r.f64()?, r.f64()?)))?,
+                planar: r.bool()?
+            };
+            Ok((id, Box::new(stop) as Box<dyn Stop>))
+        })?;
+
+        let trips = r.list(|r| Ok(Trip {
+            identifier: r.usize()?,
+            connections: r.list(SnapshotReader::connection)?
+        }))?;
+
+        let footpaths = r.list(|r| Ok((r.usize()?, r.list(|r| Ok((r.usize()?, r.u32()?)))?)))?;
+        let continuations = r.list(|r| Ok((r.usize()?, r.usize()?)))?;
+
+        let sections = r.list(|r| {
+            let key = (r.usize()?, r.usize()?);
+            let sections = r.list(|r| Ok(TrainSection {
+                destination: r.usize()?,
+                stops: r.list(|r| Ok(r.usize()?))?,
+                track: r.option(SnapshotReader::string)?
+            }))?;
+            Ok((key, sections))
+        })?;
+
+        let transfers = r.list(|r| Ok((
+            (r.usize()?, r.option(|r| Ok(r.usize()?))?, r.option(|r| Ok(r.usize()?))?),
+            r.u32()?
+        )))?;
+
+        let details = r.list(|r| Ok((r.usize()?, TripDetails {
+            operator: r.option(SnapshotReader::string)?,
+            mode: r.option(SnapshotReader::string)?,
+            attributes: r.list(SnapshotReader::string)?,
+            number: r.option(|r| Ok(r.usize()?))?
+        })))?;
+
+        Ok((Timetable {
+            stops,
+            trips,
+            footpaths,
+            continuations,
+            sections,
+            transfers,
+            details
+        }, dates))
+    }
+
+    /// Loads a snapshot like `load_snapshot`, which should be a snapshot of a single service date
+    pub fn load_snapshot_of_day(path: &Path, date: &NaiveDate) -> Result<Timetable, Box<dyn Error>> {
+        let (timetable, dates) = Timetable::load_snapshot(path)?;
+        if dates != (*date..=*date) {
+            Err(format!("Snapshot is of {} until {} instead of {}, create a snapshot of this date", dates.start(), dates.end(), date))?;
+        }
+
+        Ok(timetable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::railways_netherlands::iff_types::IFF;
+
+    #[test]
+    fn round_trip() {
+        let date = chrono::NaiveDate::from_ymd(2021, 1, 15);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/iff_fixture.zip");
+        let mut timetable = IFF::from_path(&path).unwrap().timetable_for_day(&date);
+        timetable.sections.insert((1, 0), vec![TrainSection { destination: 2, stops: vec![1, 2], track: Some("5a".to_string()) }]);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let snapshot = file.path();
+        timetable.save_snapshot(snapshot, &(date..=date)).unwrap();
+        let (loaded, dates) = Timetable::load_snapshot(snapshot).unwrap();
+        assert_eq!(dates, date..=date);
+
+        assert_eq!(loaded.trips, timetable.trips);
+        assert_eq!(loaded.footpaths, timetable.footpaths);
+        assert_eq!(loaded.continuations, timetable.continuations);
+        assert_eq!(loaded.sections, timetable.sections);
+        assert_eq!(loaded.transfers, timetable.transfers);
+        assert_eq!(loaded.details, timetable.details);
+
+        assert_eq!(loaded.stops.len(), timetable.stops.len());
+        for (id, stop) in &timetable.stops {
+            let loaded_stop = &loaded.stops[id];
+            assert_eq!((loaded_stop.to_string(), loaded_stop.name(), loaded_stop.coords()), (stop.to_string(), stop.name(), stop.coords()));
+            assert_eq!(loaded_stop.distance(&loaded.stops[&0]), stop.distance(&timetable.stops[&0]));
+        }
+
+        // Snapshots of another date or version are rejected
+        assert!(Timetable::load_snapshot_of_day(snapshot, &date).is_ok());
+        assert!(Timetable::load_snapshot_of_day(snapshot, &date.succ()).is_err());
+
+        std::fs::write(snapshot, b"TTSN\x02\0\0\0").unwrap();
+        assert!(Timetable::load_snapshot(snapshot).is_err());
+    }
+
+    #[test]
+    fn truncated_string() {
+        let mut reader = SnapshotReader(&b"\x03\0\0\0\0\0\0\0abc"[..]);
+        assert_eq!(reader.string().unwrap(), "abc");
+
+        let mut reader = SnapshotReader(&b"\x05\0\0\0\0\0\0\0abc"[..]);
+        assert!(reader.string().is_err());
+    }
+}
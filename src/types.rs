@@ -8,11 +8,11 @@
 //  only one stop for a set of 
 // A timetable can also be updated with live information, either changing connections, deleting them or adding new connections (and associated trips)
 
-use std::{cmp::Ordering, collections::HashMap, error::Error, fmt::{self, Debug}};
+use std::{cmp::Ordering, collections::{BTreeSet, HashMap, HashSet}, error::Error, fmt::{self, Debug}, iter};
 use std::hash::Hash;
 
 use serde::{Deserialize, Serialize};
-use chrono::{Local, TimeZone};
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone};
 
 /// As defined
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
@@ -75,6 +75,58 @@ impl PartialOrd for Trip {
     }
 }
 
+impl Trip {
+    /// Section of a splitting train departing from a stop which this trip itself runs as: the section with the destination
+    /// of the trip, or otherwise the section calling at most of its remaining stops
+    pub fn own_section<'s>(&self, stop: usize, sections: &'s [TrainSection]) -> Option<&'s TrainSection> {
+        let from = self.connections.iter().position(|conn| conn.dep_stop == stop)?;
+        let remaining: Vec<usize> = self.connections[from..].iter().map(|conn| conn.arr_stop).collect();
+        let served = |section: &TrainSection| remaining.iter().filter(|stop| section.stops.contains(stop)).count();
+
+        sections.iter().find(|section| Some(&section.destination) == remaining.last())
+            .or_else(|| sections.iter().filter(|section| served(section) > 0).max_by_key(|section| served(section)))
+    }
+
+    /// The trip as run by a section of the train departing from `stop`. Later stops at which the section does not call are
+    /// left out by joining the connections around them, such that the trip can not be used to reach stops that are only
+    /// served by other sections. The stops up to and including `stop` are kept as is.
+    pub fn for_section(&self, stop: usize, section: &TrainSection) -> Trip {
+        let from = match self.connections.iter().position(|conn| conn.dep_stop == stop) {
+            Some(from) => from,
+            None => return self.clone()
+        };
+
+        let mut connections = self.connections[..from].to_vec();
+        let mut departure: Option<&Connection> = None;
+        for conn in &self.connections[from..] {
+            let dep = departure.unwrap_or(conn);
+            if section.stops.contains(&conn.arr_stop) {
+                connections.push(Connection { arr_stop: conn.arr_stop, arr_time: conn.arr_time, ..dep.clone() });
+                departure = None;
+            } else {
+                departure = Some(dep);
+            }
+        }
+
+        Trip {
+            identifier: self.identifier,
+            connections
+        }
+    }
+
+    /// The trip restricted to its own section at every stop where the train is known to split, see `for_section`
+    pub fn with_sections(&self, sections: &HashMap<(usize, usize), Vec<TrainSection>>) -> Trip {
+        let stops: Vec<usize> = self.connections.iter().map(|conn| conn.dep_stop).collect();
+
+        stops.into_iter().fold(self.clone(), |trip, stop| {
+            match sections.get(&(self.identifier, stop)).and_then(|sections| trip.own_section(stop, sections)) {
+                Some(section) => trip.for_section(stop, section),
+                None => trip
+            }
+        })
+    }
+}
+
 // As defined, however trip based for identification purposes
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
@@ -85,6 +137,79 @@ pub enum TripUpdate {
     DeleteConnection { old_trip: Trip, new_trip: Trip, connection: Connection },
 }
 
+/// Observed delays (in seconds) of a trip arriving at a stop, used for reliability-aware routing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DelayDistribution {
+    delays: Vec<u32>
+}
+
+impl DelayDistribution {
+    pub fn new(mut delays: Vec<u32>) -> Self {
+        delays.sort();
+        DelayDistribution { delays }
+    }
+
+    /// Probability that the delay is at most `delay` seconds, without observations a trip is assumed to be on time
+    pub fn probability_within(&self, delay: u32) -> f64 {
+        if self.delays.is_empty() {
+            return 1.0;
+        }
+
+        partition_point(&self.delays, |&d| d <= delay) as f64 / self.delays.len() as f64
+    }
+
+    pub fn samples(&self) -> &[u32] {
+        &self.delays
+    }
+}
+
+/// Delay distributions per trip identifier (regardless of the service day, see `trip_identifier`) and arrival stop
+pub type Delays = HashMap<(usize, usize), DelayDistribution>;
+
+impl TripUpdate {
+    /// Updates needed to go from the latest known state of a trip to `new_trip`, where `trips` contains the latest known
+    /// connections of every trip and is kept up-to-date. A trip without connections is deleted, an unknown trip is added.
+    pub fn from_new_trip(trips: &mut HashMap<usize, BTreeSet<Connection>>, new_trip: Trip) -> Vec<TripUpdate> {
+        let mut update = vec![];
+
+        if let Some(trip) = trips.get_mut(&new_trip.identifier) {
+            let new_trip_set: BTreeSet<Connection> = new_trip.connections.clone().into_iter().collect();
+
+            let old_trip = Trip {
+                identifier: new_trip.identifier,
+                connections: trip.clone().into_iter().collect()
+            };
+
+            // Should we remove the whole trip?
+            if new_trip.connections.is_empty() {
+                trips.remove(&new_trip.identifier);
+                update.push(TripUpdate::DeleteTrip { trip: old_trip });
+                return update;
+            }
+
+            // We try to discover the differences between the new trip and the current known trip
+            let del_connections: Vec<Connection> = trip.difference(&new_trip_set).map(|x| x.clone()).collect();
+            let new_connections: Vec<Connection> = new_trip_set.difference(trip).map(|x| x.clone()).collect();
+
+            for new_connection in new_connections {
+                trip.insert(new_connection.clone());
+                update.push(TripUpdate::AddConnection { old_trip: old_trip.clone(), new_trip: new_trip.clone(), connection: new_connection });
+            }
+
+            for del_connection in del_connections {
+                trip.remove(&del_connection);
+                update.push(TripUpdate::DeleteConnection { old_trip: old_trip.clone(), new_trip: new_trip.clone(), connection: del_connection });
+            }
+        } else {
+            // This is a new trip, we add the trip.
+            trips.insert(new_trip.identifier, new_trip.connections.clone().into_iter().collect());
+            update.push(TripUpdate::AddTrip { trip: new_trip });
+        }
+
+        update
+    }
+}
+
 // As defined
 #[derive(Debug)]
 pub struct Path {
@@ -100,15 +225,337 @@ pub trait Stop: Debug {
     // Might also want to look to change the timetable to use a generic stop type instead of a dynamic
     // However, multiple types of stop might also be possible, think of the Norwegian model containing MultimodalStopPlace and GroupOfStopPlaces etc.
     fn to_string(&self) -> String;
+
+    /// Human readable name of the stop, for stops without a name this is the same as `to_string`
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
     fn coords(&self) -> Option<(f64, f64)>;
     fn distance(&self, other: &Box<dyn Stop>) -> Option<f64>;
+
+    /// Whether the coordinates are planar (in meters) instead of WGS84 latitude and longitude
+    fn planar(&self) -> bool {
+        false
+    }
 }
 
-#[derive(Debug)]
+/// Index of the first item for which `pred` is false in a slice that is partitioned by it, like `slice::partition_point`
+/// which is not available in the Rust versions this crate supports
+pub fn partition_point<T>(slice: &[T], mut pred: impl FnMut(&T) -> bool) -> usize {
+    slice.binary_search_by(|item| if pred(item) { Ordering::Less } else { Ordering::Greater }).unwrap_or_else(|i| i)
+}
+
+/// Great-circle distance in meters between two WGS84 coordinates (latitude, longitude)
+pub fn haversine_distance((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let a = ((lat2 - lat1).to_radians() / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * ((lon2 - lon1).to_radians() / 2.0).sin().powi(2);
+
+    2.0 * 6_371_000.0 * a.sqrt().asin()
+}
+
+/// Timestamp of a wall-clock time on a service date in a timezone, the time is given in seconds since midnight and exceeds
+/// 24 hours for times on the next days. An ambiguous time (when the clocks are turned back) is the first of both, a time that
+/// does not exist (when the clocks are turned forward) is moved forward by the length of the gap.
+pub fn local_timestamp<T: TimeZone>(tz: &T, date: &NaiveDate, time: u32) -> u32 {
+    fn resolve<T: TimeZone>(tz: &T, local: NaiveDateTime) -> DateTime<T> {
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => datetime,
+            LocalResult::None => resolve(tz, local - Duration::hours(1)) + Duration::hours(1)
+        }
+    }
+
+    resolve(tz, date.and_hms(0, 0, 0) + Duration::seconds(time as i64)).timestamp() as u32
+}
+
+/// Trip ids of the n-th service day in a timetable of multiple days are offset by n times this, such that they are unique per
+/// trip and service date. The trips of the first day keep their identifiers, which are train numbers of at most 6 digits or
+/// positions in a GTFS feed, and should be below this.
+pub const TRIP_IDS_PER_DAY: usize = 10_000_000;
+
+/// Trip id of a trip on the n-th service day of a timetable, panics when the identifier would overlap with the next day
+pub fn day_trip_id(day: usize, identifier: usize) -> usize {
+    assert!(identifier < TRIP_IDS_PER_DAY, "Trip identifier {} is too large to combine with a service day", identifier);
+    day * TRIP_IDS_PER_DAY + identifier
+}
+
+/// Identifier of a trip (e.g. its train number) without its service day, for trip ids given by `day_trip_id`
+pub fn trip_identifier(trip_id: usize) -> usize {
+    trip_id % TRIP_IDS_PER_DAY
+}
+
+#[derive(Debug, Default)]
 pub struct Timetable {
     pub stops: HashMap<usize, Box<dyn Stop>>,
     pub trips: Vec<Trip>,
-    pub footpaths: HashMap<usize, Vec<(usize, u32)>> // Stop a to stop b => time
+    pub footpaths: HashMap<usize, Vec<(usize, u32)>>, // Stop a to stop b => time
+    pub continuations: HashMap<usize, usize>, // Trip a continues as trip b, e.g. a through-running train changing its number
+    pub sections: HashMap<(usize, usize), Vec<TrainSection>>, // Trip and stop => parts of the train departing there
+    pub transfers: HashMap<(usize, Option<usize>, Option<usize>), u32>, // Stop, trip a and trip b (None for any trip) => minimum transfer time
+    pub details: HashMap<usize, TripDetails> // Trip => operator, transport mode and attributes
+}
+
+/// Information about a trip for travellers, e.g. "Intercity direct (NS), surcharge required"
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TripDetails {
+    pub operator: Option<String>,
+    pub mode: Option<String>,
+    pub attributes: Vec<String>,
+
+    // Train number, when it differs from the trip id
+    pub number: Option<usize>
+}
+
+impl fmt::Display for TripDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mode.as_deref().unwrap_or("Train"))?;
+        if let Some(number) = self.number {
+            write!(f, " {}", number)?;
+        }
+        if let Some(operator) = &self.operator {
+            write!(f, " ({})", operator)?;
+        }
+
+        for attribute in &self.attributes {
+            write!(f, ", {}", attribute)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Part of a train that splits later on, with the stops it calls at after departing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrainSection {
+    pub destination: usize,
+    pub stops: Vec<usize>,
+    pub track: Option<String>
+}
+
+impl Timetable {
+    /// Length of vectors indexed by stop id, which is one more than the highest id of the stops, connections and footpaths
+    pub fn stop_count(&self) -> usize {
+        let stops = self.stops.keys().copied();
+        let connections = self.trips.iter()
+            .flat_map(|trip| trip.connections.iter())
+            .flat_map(|conn| iter::once(conn.dep_stop).chain(iter::once(conn.arr_stop)));
+        let footpaths = self.footpaths.iter()
+            .flat_map(|(&from, to)| iter::once(from).chain(to.iter().map(|&(stop, _)| stop)));
+
+        stops.chain(connections).chain(footpaths).max().map_or(0, |stop| stop + 1)
+    }
+
+    /// Adds the trips of another service day with the same stops as the n-th day (see `day_trip_id`), leaving out the trips
+    /// that have arrived before `from_time`. This way queries near midnight can use the trips of the previous day that are
+    /// still running and reach their destination on the next day.
+    pub fn merge_day(&mut self, day: usize, other: Timetable, from_time: u32) {
+        let trip_id = |id| day_trip_id(day, id);
+
+        let trips: Vec<Trip> = other.trips.into_iter()
+            .filter(|trip| trip.connections.last().map_or(false, |conn| conn.arr_time >= from_time))
+            .map(|trip| Trip {
+                identifier: trip_id(trip.identifier),
+                connections: trip.connections.into_iter().map(|conn| Connection { trip_id: trip_id(conn.trip_id), ..conn }).collect()
+            })
+            .collect();
+
+        let identifiers: HashSet<usize> = trips.iter().map(|trip| trip.identifier).collect();
+        self.trips.extend(trips);
+
+        self.continuations.extend(other.continuations.into_iter()
+            .map(|(from, to)| (trip_id(from), trip_id(to)))
+            .filter(|(from, _)| identifiers.contains(from)));
+        self.sections.extend(other.sections.into_iter()
+            .map(|((trip, stop), sections)| ((trip_id(trip), stop), sections))
+            .filter(|((trip, _), _)| identifiers.contains(trip)));
+        self.details.extend(other.details.into_iter()
+            .map(|(trip, details)| (trip_id(trip), details))
+            .filter(|(trip, _)| identifiers.contains(trip)));
+        self.transfers.extend(other.transfers.into_iter()
+            .filter(|((_, from_trip, to_trip), _)| from_trip.is_some() || to_trip.is_some())
+            .map(|((stop, from_trip, to_trip), duration)| ((stop, from_trip.map(trip_id), to_trip.map(trip_id)), duration)));
+    }
+
+    /// Restricts the trips of splitting trains to the stops served by their own section, see `Trip::with_sections`
+    pub fn apply_sections(&mut self) {
+        let sections = &self.sections;
+        for trip in self.trips.iter_mut().filter(|trip| !trip.connections.is_empty()) {
+            *trip = trip.with_sections(sections);
+        }
+    }
+
+    /// Part of the train to board at the start of a leg in order to reach the end of the leg, only given for trains that split
+    pub fn section_for_leg(&self, board: &Connection, alight: &Connection) -> Option<&TrainSection> {
+        let sections = self.sections.get(&(board.trip_id, board.dep_stop))?;
+        if sections.len() < 2 {
+            return None;
+        }
+
+        sections.iter().find(|section| section.stops.contains(&alight.arr_stop))
+    }
+
+    /// Minimum time to transfer from one trip to another at a stop, when it is given specifically for these trips instead of
+    /// by the footpaths of the stop
+    pub fn transfer_time(&self, stop: usize, from_trip: usize, to_trip: usize) -> Option<u32> {
+        self.transfers.get(&(stop, Some(from_trip), Some(to_trip)))
+            .or_else(|| self.transfers.get(&(stop, Some(from_trip), None)))
+            .or_else(|| self.transfers.get(&(stop, None, Some(to_trip))))
+            .copied()
+    }
+
+    /// Parts of a leg from `board` to `alight`, which are on different trips when the traveller stays seated in a trip that
+    /// continues as another one. Such a leg is split into a connection per trip with a footpath of 0 minutes in between,
+    /// the way `join_continuations` expects it.
+    pub fn seated_legs<'a>(&'a self, board: &'a Connection, alight: &'a Connection) -> Vec<TripPart<'a>> {
+        let trip = |id| self.trips.iter().find(|trip: &&Trip| trip.identifier == id);
+
+        let mut parts = vec![];
+        let mut board = board;
+        while board.trip_id != alight.trip_id {
+            let continuation = self.continuations.get(&board.trip_id)
+                .and_then(|&next| Some((trip(board.trip_id)?.connections.last()?, trip(next)?.connections.first()?)));
+
+            match continuation {
+                Some((last, first)) => {
+                    parts.push(TripPart::Connection(board, last));
+                    parts.push(TripPart::Footpath(last.arr_stop, last.arr_stop, 0));
+                    board = first;
+                },
+                None => break
+            }
+        }
+
+        parts.push(TripPart::Connection(board, alight));
+        parts
+    }
+
+    /// Joins the legs of a journey on trips that continue as each other, such that staying seated
+    /// in a through-running train is not shown as a transfer
+    pub fn join_continuations<'a>(&self, journey: TripResult<'a>) -> TripResult<'a> {
+        let mut parts: Vec<TripPart<'a>> = vec![];
+
+        for part in journey.parts {
+            if let TripPart::Connection(next, _) = part {
+                let continues = match &parts[parts.len().saturating_sub(2)..] {
+                    [TripPart::Connection(_, prev), TripPart::Footpath(a, b, _)] =>
+                        a == b && prev.arr_stop == next.dep_stop && self.continuations.get(&prev.trip_id) == Some(&next.trip_id),
+                    _ => false
+                };
+
+                if continues {
+                    parts.pop();
+                }
+            }
+
+            parts.push(part);
+        }
+
+        TripResult {
+            parts
+        }
+    }
+
+    /// Normalizes a journey found by an earliest arrival query, moving the departure as late as possible
+    /// (but never before `dep_time`) while keeping the same arrival time. Using a reverse connection scan bounded by the arrival
+    /// of the given journey, transfers that are not needed to reach the destination in time are removed as well.
+    pub fn latest_departure(&self, dep_stop: usize, dep_time: u32, journey: &TripResult) -> Option<TripResult<'_>> {
+        // The journey may end by walking from its last connection to the destination, in which case the scan targets the
+        // stop of that connection and the trailing footpaths are appended to the normalized journey afterwards
+        let last = journey.parts.iter().rposition(|part| matches!(part, TripPart::Connection(_, _)))?;
+        let (arr_stop, arr_time) = match journey.parts[last] {
+            TripPart::Connection(_, b) => (b.arr_stop, b.arr_time),
+            TripPart::Footpath(_, _, _) => unreachable!()
+        };
+
+        let mut connections: Vec<&Connection> = self.trips.iter()
+            .map(|trip| trip.connections.iter()).flatten()
+            .filter(|conn| conn.dep_time >= dep_time && conn.arr_time <= arr_time)
+            .collect();
+        connections.sort();
+
+        let no_footpaths = vec![];
+
+        // Latest time we can be at a stop while still arriving in time, and the amount of legs needed from there
+        let mut latest: HashMap<usize, (u32, usize)> = HashMap::new();
+
+        // Leg taken from a stop: boarding connection, alighting connection and the footpath after alighting (if any)
+        let mut journeys: HashMap<usize, (&Connection, &Connection, Option<(usize, usize, u32)>)> = HashMap::new();
+
+        // Best known alighting connection of a trip, the footpath after alighting and the amount of legs needed afterwards
+        let mut exits: HashMap<usize, (&Connection, Option<(usize, usize, u32)>, usize)> = HashMap::new();
+
+        for &conn in connections.iter().rev() {
+            let exit = if conn.arr_stop == arr_stop {
+                Some((conn, None, 0))
+            } else {
+                // Staying seated is preferred over alighting, unless alighting saves us a transfer later on. This includes staying
+                // seated at the end of a trip that continues as another one.
+                let mut exit = exits.get(&conn.trip_id)
+                    .or_else(|| self.continuations.get(&conn.trip_id).and_then(|next| exits.get(next)))
+                    .cloned();
+                for &(f_stop, dur) in self.footpaths.get(&conn.arr_stop).unwrap_or(&no_footpaths) {
+                    if let Some(&(time, legs)) = latest.get(&f_stop) {
+                        if conn.arr_time + dur <= time && exit.map_or(true, |(_, _, exit_legs)| legs < exit_legs) {
+                            exit = Some((conn, Some((conn.arr_stop, f_stop, dur)), legs));
+                        }
+                    }
+                }
+                exit
+            };
+
+            if let Some(exit) = exit {
+                exits.insert(conn.trip_id, exit);
+
+                let legs = exit.2 + 1;
+                let improves = match latest.get(&conn.dep_stop) {
+                    Some(&(time, stop_legs)) => conn.dep_time > time || (conn.dep_time == time && legs < stop_legs),
+                    None => true
+                };
+
+                if improves {
+                    latest.insert(conn.dep_stop, (conn.dep_time, legs));
+                    journeys.insert(conn.dep_stop, (conn, exit.0, exit.1));
+                }
+            }
+        }
+
+        // Find the latest departure, possibly by first walking to a nearby stop
+        let mut start_footpaths = self.footpaths.get(&dep_stop).cloned().unwrap_or_default();
+        start_footpaths.push((dep_stop, 0));
+
+        let (_, _, start, dur) = start_footpaths.into_iter()
+            .filter_map(|(f_stop, dur)| latest.get(&f_stop)
+                .filter(|(time, _)| *time >= dep_time + dur)
+                .map(|&(time, legs)| (time - dur, legs, f_stop, dur)))
+            .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)))?;
+
+        let mut parts = vec![];
+        if start != dep_stop {
+            parts.push(TripPart::Footpath(dep_stop, start, dur));
+        }
+
+        let mut cur = start;
+        while let Some(&(board, alight, footpath)) = journeys.get(&cur) {
+            parts.extend(self.seated_legs(board, alight));
+
+            match footpath {
+                Some((from, to, dur)) => {
+                    parts.push(TripPart::Footpath(from, to, dur));
+                    cur = to;
+                },
+                None => break
+            }
+        }
+
+        parts.extend(journey.parts[last + 1..].iter().filter_map(|part| match *part {
+            TripPart::Footpath(from, to, dur) => Some(TripPart::Footpath(from, to, dur)),
+            TripPart::Connection(_, _) => None
+        }));
+
+        Some(TripResult {
+            parts
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -136,12 +583,12 @@ impl TripPart<'_> {
         match self {
             TripPart::Connection(a, b) => format!(
                 "Depart from {} at {}, and arrive at {} at {}", 
-                stops.get(&a.dep_stop).unwrap().to_string(), Local.timestamp(a.dep_time as i64, 0),
-                stops.get(&b.arr_stop).unwrap().to_string(), Local.timestamp(b.arr_time as i64, 0)
+                stops.get(&a.dep_stop).unwrap().name(), Local.timestamp(a.dep_time as i64, 0),
+                stops.get(&b.arr_stop).unwrap().name(), Local.timestamp(b.arr_time as i64, 0)
             ),
             TripPart::Footpath(a, b, duration) => format!(
                 "Walk from {} to {} taking {} mins",
-                stops.get(a).unwrap().to_string(), stops.get(b).unwrap().to_string(), duration / 60
+                stops.get(a).unwrap().name(), stops.get(b).unwrap().name(), duration / 60
             )
         }
     }
@@ -163,7 +610,7 @@ impl fmt::Display for TripPart<'_> {
 }
 
 // Journey as defined in the paper
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct TripResult<'a> {
     pub parts: Vec<TripPart<'a>>
 }
@@ -178,15 +625,40 @@ impl TripResult<'_> {
         panic!("Trip result did not contain a final connection!");
     }
 
-    pub fn format_fancy(&self, stops: &HashMap<usize, Box<dyn Stop>>) -> String {
+    pub fn format_fancy(&self, timetable: &Timetable) -> String {
+        let stops = &timetable.stops;
         let mut res = format!(
             "Trip from {} to {}\n", 
-            stops.get(&self.parts.first().unwrap().from()).unwrap().to_string(), 
-            stops.get(&self.parts.last().unwrap().to()).unwrap().to_string()
+            stops.get(&self.parts.first().unwrap().from()).unwrap().name(), 
+            stops.get(&self.parts.last().unwrap().to()).unwrap().name()
         );
 
+        let mut prev: Option<&TripPart> = None;
         for part in &self.parts {
-            res.push_str(&format!("{}\n", part.format_fancy(stops))[..]);
+            match (prev, part) {
+                // Two connections directly following each other are on the same vehicle
+                (Some(TripPart::Connection(_, _)), TripPart::Connection(a, b)) => res.push_str(&format!(
+                    "Train continues as {}, and arrive at {} at {}\n",
+                    timetable.details.get(&a.trip_id).and_then(|details| details.number).unwrap_or_else(|| trip_identifier(a.trip_id)), stops.get(&b.arr_stop).unwrap().name(), Local.timestamp(b.arr_time as i64, 0)
+                )[..]),
+                _ => res.push_str(&format!("{}\n", part.format_fancy(stops))[..])
+            }
+
+            if let TripPart::Connection(a, b) = part {
+                if let (Some(details), false) = (timetable.details.get(&a.trip_id), matches!(prev, Some(TripPart::Connection(_, _)))) {
+                    res.push_str(&format!("Take the {}\n", details)[..]);
+                }
+
+                if let Some(section) = timetable.section_for_leg(a, b) {
+                    res.push_str(&format!("Board the part of the train to {}", stops.get(&section.destination).unwrap().name())[..]);
+                    if let Some(track) = &section.track {
+                        res.push_str(&format!(" on track {}", track)[..]);
+                    }
+                    res.push_str("\n");
+                }
+            }
+
+            prev = Some(part);
         }
 
         res
@@ -202,4 +674,73 @@ impl fmt::Display for TripResult<'_> {
 
         write!(f, "{}", self.parts[self.parts.len()-1])
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day_trip_ids() {
+        assert_eq!(day_trip_id(2, 1234), 2 * TRIP_IDS_PER_DAY + 1234);
+        assert_eq!(trip_identifier(day_trip_id(2, 1234)), 1234);
+    }
+
+    #[test]
+    #[should_panic]
+    fn day_trip_id_overlapping_next_day() {
+        day_trip_id(1, TRIP_IDS_PER_DAY);
+    }
+
+    #[test]
+    fn partition_points() {
+        let slice = [1, 2, 2, 3, 5];
+        assert_eq!(partition_point(&slice, |&i| i < 2), 1);
+        assert_eq!(partition_point(&slice, |&i| i <= 2), 3);
+        assert_eq!(partition_point(&slice, |&i| i < 6), 5);
+        assert_eq!(partition_point(&[] as &[u32], |&i| i < 6), 0);
+    }
+
+    #[test]
+    fn local_times() {
+        let tz = chrono_tz::Europe::Amsterdam;
+        let utc = |day, hour, minute| chrono::Utc.ymd(2021, 3, day).and_hms(hour, minute, 0).timestamp() as u32;
+
+        // Clocks are turned forward from 02:00 to 03:00 on the 28th of March
+        assert_eq!(local_timestamp(&tz, &NaiveDate::from_ymd(2021, 3, 28), 3600), utc(28, 0, 0));
+        assert_eq!(local_timestamp(&tz, &NaiveDate::from_ymd(2021, 3, 28), 2 * 3600 + 1800), utc(28, 1, 30));
+        assert_eq!(local_timestamp(&tz, &NaiveDate::from_ymd(2021, 3, 28), 3 * 3600), utc(28, 1, 0));
+        assert_eq!(local_timestamp(&tz, &NaiveDate::from_ymd(2021, 3, 27), 25 * 3600), utc(28, 0, 0));
+
+        // Clocks are turned back from 03:00 to 02:00 on the 31st of October
+        let october = chrono::Utc.ymd(2021, 10, 31).and_hms(0, 30, 0).timestamp() as u32;
+        assert_eq!(local_timestamp(&tz, &NaiveDate::from_ymd(2021, 10, 31), 2 * 3600 + 1800), october);
+    }
+
+    #[test]
+    fn trip_for_section() {
+        // Trip 1 runs from stop 0 to stop 3, at stop 1 a section to stop 4 splits off which does not call at stop 2
+        let connections: Vec<Connection> = (0..3).map(|i| Connection { dep_stop: i, arr_stop: i + 1, dep_time: i as u32 * 10, arr_time: i as u32 * 10 + 5, trip_id: 1 }).collect();
+        let trip = Trip { identifier: 1, connections: connections.clone() };
+
+        let sections = vec![
+            TrainSection { destination: 4, stops: vec![4], track: None },
+            TrainSection { destination: 3, stops: vec![3], track: Some("5a".to_string()) }
+        ];
+
+        let mut timetable_sections = HashMap::new();
+        timetable_sections.insert((1, 1), sections.clone());
+
+        assert_eq!(trip.own_section(1, &sections), Some(&sections[1]));
+        assert_eq!(trip.own_section(3, &sections), None);
+
+        // The trip no longer stops at stop 2
+        assert_eq!(trip.with_sections(&timetable_sections).connections, vec![
+            connections[0].clone(),
+            Connection { dep_stop: 1, arr_stop: 3, dep_time: 10, arr_time: 25, trip_id: 1 }
+        ]);
+
+        // A section not serving any of the stops of the trip is not its own section
+        assert_eq!(trip.own_section(1, &sections[..1]), None);
+    }
+}